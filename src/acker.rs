@@ -7,6 +7,9 @@ use crate::{
     types::{ChannelId, DeliveryTag},
 };
 
+#[cfg(feature = "trace-context")]
+use crate::{BasicProperties, trace_context::DeliverySpan, types::ShortString};
+
 #[derive(Clone, Debug)]
 pub struct Acker {
     channel_id: ChannelId,
@@ -15,6 +18,8 @@ pub struct Acker {
     error: Option<ErrorHolder>,
     killswitch: KillSwitch,
     channel_killswitch: KillSwitch,
+    #[cfg(feature = "trace-context")]
+    span: DeliverySpan,
 }
 
 impl Acker {
@@ -32,6 +37,8 @@ impl Acker {
             error,
             killswitch: KillSwitch::default(),
             channel_killswitch,
+            #[cfg(feature = "trace-context")]
+            span: DeliverySpan::default(),
         }
     }
 
@@ -90,6 +97,8 @@ impl Acker {
             f(internal_rpc, resolver);
             promise.await?;
         }
+        #[cfg(feature = "trace-context")]
+        self.span.finish(marker);
         Ok(true)
     }
 
@@ -103,6 +112,24 @@ impl Acker {
         !self.poisoned() && !self.killswitch.killed()
     }
 
+    /// The span covering the processing of this delivery, from the first call to this method
+    /// until it gets acked, nacked or rejected. Its parent is the trace context found in the
+    /// message headers, if any.
+    #[cfg(feature = "trace-context")]
+    pub fn span(&self) -> tracing::Span {
+        self.span.span()
+    }
+
+    #[cfg(feature = "trace-context")]
+    pub(crate) fn receive_span(
+        &self,
+        exchange: ShortString,
+        routing_key: ShortString,
+        properties: &BasicProperties,
+    ) {
+        self.span.receive(exchange, routing_key, properties);
+    }
+
     pub(crate) fn invalidate(&self) {
         self.killswitch.kill();
     }
@@ -1,6 +1,7 @@
 use crate::{
     Error, Promise, PromiseResolver,
     id_sequence::IdSequence,
+    metrics::Metrics,
     protocol::{AMQPError, AMQPSoftError},
     publisher_confirm::{Confirmation, PublisherConfirm},
     returned_messages::ReturnedMessages,
@@ -10,6 +11,7 @@ use std::{
     collections::{HashMap, HashSet},
     fmt,
     sync::{Arc, Mutex, MutexGuard},
+    time::Instant,
 };
 use tracing::trace;
 
@@ -19,10 +21,15 @@ pub(crate) struct Acknowledgements(Arc<Mutex<Inner>>);
 type AMQPResult = std::result::Result<(), AMQPError>;
 
 impl Acknowledgements {
-    pub(crate) fn new(channel_id: u16, returned_messages: ReturnedMessages) -> Self {
+    pub(crate) fn new(
+        channel_id: u16,
+        returned_messages: ReturnedMessages,
+        metrics: Option<Arc<dyn Metrics>>,
+    ) -> Self {
         Self(Arc::new(Mutex::new(Inner::new(
             channel_id,
             returned_messages,
+            metrics,
         ))))
     }
 
@@ -34,6 +41,10 @@ impl Acknowledgements {
         self.lock_inner().last.take()
     }
 
+    pub(crate) fn pending_count(&self) -> usize {
+        self.lock_inner().pending.len()
+    }
+
     pub(crate) fn ack(&self, delivery_tag: DeliveryTag) -> AMQPResult {
         self.lock_inner().drop_pending(delivery_tag, true)
     }
@@ -90,18 +101,26 @@ struct Inner {
     channel_id: u16,
     delivery_tag: IdSequence<DeliveryTag>,
     last: Option<Promise<()>>,
-    pending: HashMap<DeliveryTag, (PromiseResolver<Confirmation>, PromiseResolver<()>)>,
+    pending: HashMap<DeliveryTag, Pending>,
     returned_messages: ReturnedMessages,
+    metrics: Option<Arc<dyn Metrics>>,
 }
 
+type Pending = (PromiseResolver<Confirmation>, PromiseResolver<()>, Instant);
+
 impl Inner {
-    fn new(channel_id: u16, returned_messages: ReturnedMessages) -> Self {
+    fn new(
+        channel_id: u16,
+        returned_messages: ReturnedMessages,
+        metrics: Option<Arc<dyn Metrics>>,
+    ) -> Self {
         Self {
             channel_id,
             delivery_tag: IdSequence::new(false),
             last: None,
             pending: HashMap::default(),
             returned_messages,
+            metrics,
         }
     }
 
@@ -112,16 +131,15 @@ impl Inner {
         let (err_promise, err_resolver) = Promise::new("acknowledgement-error");
         let promise = PublisherConfirm::new(promise, self.returned_messages.clone());
         self.last = Some(err_promise);
-        self.pending.insert(delivery_tag, (resolver, err_resolver));
+        self.pending
+            .insert(delivery_tag, (resolver, err_resolver, Instant::now()));
         promise
     }
 
-    fn complete_pending(
-        &mut self,
-        success: bool,
-        delivery_tag: DeliveryTag,
-        resolvers: (PromiseResolver<Confirmation>, PromiseResolver<()>),
-    ) {
+    fn complete_pending(&mut self, success: bool, delivery_tag: DeliveryTag, resolvers: Pending) {
+        if let Some(metrics) = self.metrics.as_deref() {
+            metrics.publish_confirmed(self.channel_id, resolvers.2.elapsed(), success);
+        }
         let returned_message = self.returned_messages.get_waiting_message();
         resolvers.0.resolve(if success {
             Confirmation::Ack(returned_message)
@@ -0,0 +1,45 @@
+use crate::{
+    Acker, Result,
+    options::{BasicAckOptions, BasicNackOptions, BasicRejectOptions},
+};
+use async_trait::async_trait;
+
+/// Something that can acknowledge a delivery.
+///
+/// It is implemented by [`Acker`] (and thus reachable from a `Delivery`), so that application
+/// code handling deliveries can be unit tested without a broker, using the
+/// `RecordingAcknowledger` from the `testing` module for example.
+///
+/// [`Acker`]: ./struct.Acker.html
+#[async_trait]
+pub trait Acknowledger: Send + Sync {
+    /// Acknowledge the delivery, see [`Acker::ack`].
+    ///
+    /// [`Acker::ack`]: ./struct.Acker.html#method.ack
+    async fn ack(&self, options: BasicAckOptions) -> Result<bool>;
+
+    /// Negatively acknowledge the delivery, see [`Acker::nack`].
+    ///
+    /// [`Acker::nack`]: ./struct.Acker.html#method.nack
+    async fn nack(&self, options: BasicNackOptions) -> Result<bool>;
+
+    /// Reject the delivery, see [`Acker::reject`].
+    ///
+    /// [`Acker::reject`]: ./struct.Acker.html#method.reject
+    async fn reject(&self, options: BasicRejectOptions) -> Result<bool>;
+}
+
+#[async_trait]
+impl Acknowledger for Acker {
+    async fn ack(&self, options: BasicAckOptions) -> Result<bool> {
+        Acker::ack(self, options).await
+    }
+
+    async fn nack(&self, options: BasicNackOptions) -> Result<bool> {
+        Acker::nack(self, options).await
+    }
+
+    async fn reject(&self, options: BasicRejectOptions) -> Result<bool> {
+        Acker::reject(self, options).await
+    }
+}
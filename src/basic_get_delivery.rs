@@ -54,7 +54,7 @@ impl Inner {
 
     fn handle_content_header_frame(&mut self, size: PayloadSize, properties: BasicProperties) {
         if let Some(inner) = self.0.as_mut() {
-            inner.message.properties = properties;
+            inner.message.receive_properties(properties);
         }
         if size == 0 {
             self.new_delivery_complete();
@@ -64,6 +64,24 @@ impl Buffer {
         self.end = checkpoint.end;
     }
 
+    // The bytes written since the checkpoint
+    pub(crate) fn written_since(&self, checkpoint: &Checkpoint, count: usize) -> Vec<u8> {
+        self.copy(checkpoint.end, count)
+    }
+
+    // The next bytes to consume
+    pub(crate) fn peek(&self, count: usize) -> Vec<u8> {
+        self.copy(self.position, count)
+    }
+
+    fn copy(&self, start: usize, count: usize) -> Vec<u8> {
+        let first = cmp::min(count, self.capacity - start);
+        let mut bytes = Vec::with_capacity(count);
+        bytes.extend_from_slice(&self.memory[start..start + first]);
+        bytes.extend_from_slice(&self.memory[..count - first]);
+        bytes
+    }
+
     pub(crate) fn grow(&mut self, new_size: usize) -> bool {
         if self.capacity >= new_size {
             return false;
@@ -1,32 +1,41 @@
 use crate::{
-    BasicProperties, ChannelState, ChannelStatus, Connection, ConnectionState, ConnectionStatus,
-    Error, ErrorKind, ExchangeKind, Promise, PromiseResolver, Result,
+    BasicProperties, ChannelRecoveryState, ChannelState, ChannelStatus, Connection,
+    ConnectionState, ConnectionStatus, Error, ErrorKind, ExchangeKind, Promise, PromiseResolver,
+    RecoveryListener, Result,
     acknowledgement::Acknowledgements,
     auth::AuthProvider,
     basic_get_delivery::BasicGetDelivery,
     channel_closer::ChannelCloser,
     channel_receiver_state::DeliveryCause,
+    channels::ChannelKind,
     configuration::{NegotiatedConfig, RecoveryConfig},
     connection_closer::ConnectionCloser,
     connection_step::ConnectionStep,
     consumer::Consumer,
     consumers::Consumers,
-    events::EventsSender,
+    events::{Event, Events, EventsSender},
     frames::{ExpectedReply, Frames},
     internal_rpc::InternalRPCHandle,
     message::{BasicGetMessage, BasicReturnMessage, Delivery},
+    metrics::Metrics,
     promise::Cancelable,
-    protocol::{self, AMQPClass, AMQPError, AMQPHardError},
+    protocol::{self, AMQPClass, AMQPError, AMQPErrorKind, AMQPHardError, AMQPSoftError},
     publisher_confirm::PublisherConfirm,
     queue::Queue,
     registry::Registry,
     returned_messages::ReturnedMessages,
+    snapshot::ChannelSnapshot,
     socket_state::SocketStateHandle,
-    topology::ChannelDefinition,
+    topology::{
+        BindingDefinition, ChannelDefinition, ChannelTopology, ConsumerDefinition, Topology,
+        TopologyMismatch, TopologyReport, TopologyVerification,
+    },
     types::*,
+    unacked_deliveries::UnackedDeliveries,
 };
 use amq_protocol::frame::{AMQPContentHeader, AMQPFrame};
-use std::{convert::TryFrom, fmt, sync::Arc};
+use futures_core::Stream;
+use std::{collections::HashMap, convert::TryFrom, fmt, sync::Arc, time::Duration};
 use tracing::{error, info, trace};
 
 /// Main entry point for most AMQP operations.
@@ -49,13 +58,17 @@ pub struct Channel {
     consumers: Consumers,
     basic_get_delivery: BasicGetDelivery,
     returned_messages: ReturnedMessages,
+    unacked_deliveries: UnackedDeliveries,
     waker: SocketStateHandle,
     internal_rpc: InternalRPCHandle,
     frames: Frames,
+    events: Events,
     events_sender: EventsSender,
     channel_closer: Option<Arc<ChannelCloser>>,
-    _connection_closer: Option<Arc<ConnectionCloser>>,
+    connection_closer: Option<Arc<ConnectionCloser>>,
     recovery_config: RecoveryConfig,
+    rpc_timeout: Option<Duration>,
+    metrics: Option<Arc<dyn Metrics>>,
 }
 
 impl PartialEq for Channel {
@@ -75,6 +88,7 @@ impl fmt::Debug for Channel {
             .field("consumers", &self.consumers)
             .field("basic_get_delivery", &self.basic_get_delivery)
             .field("returned_messages", &self.returned_messages)
+            .field("unacked_deliveries", &self.unacked_deliveries)
             .field("frames", &self.frames)
             .finish()
     }
@@ -91,9 +105,13 @@ impl Channel {
         frames: Frames,
         connection_closer: Option<Arc<ConnectionCloser>>,
         recovery_config: RecoveryConfig,
+        rpc_timeout: Option<Duration>,
+        metrics: Option<Arc<dyn Metrics>>,
         events_sender: EventsSender,
     ) -> Channel {
         let returned_messages = ReturnedMessages::default();
+        let events = Events::new();
+        let events_sender = events_sender.with_channel_events(&events);
         let status = ChannelStatus::new(channel_id, internal_rpc.clone());
         let channel_closer = if channel_id == 0 {
             None
@@ -110,17 +128,25 @@ impl Channel {
             status,
             connection_status,
             local_registry: Registry::default(),
-            acknowledgements: Acknowledgements::new(channel_id, returned_messages.clone()),
+            acknowledgements: Acknowledgements::new(
+                channel_id,
+                returned_messages.clone(),
+                metrics.clone(),
+            ),
             consumers: Consumers::default(),
             basic_get_delivery: BasicGetDelivery::default(),
             returned_messages,
+            unacked_deliveries: UnackedDeliveries::default(),
             waker,
             internal_rpc,
             frames,
+            events,
             events_sender,
             channel_closer,
-            _connection_closer: connection_closer,
+            connection_closer,
             recovery_config,
+            rpc_timeout,
+            metrics,
         }
     }
 
@@ -128,11 +154,103 @@ impl Channel {
         &self.status
     }
 
+    /// Get a stream of the events happening on this channel only (flow changes, closing,
+    /// returned messages, recovery, consumers cancelled by the server...).
+    ///
+    /// These events are also sent to [`Connection::events_listener`].
+    ///
+    /// [`Connection::events_listener`]: ./struct.Connection.html#method.events_listener
+    pub fn events_listener(&self) -> impl Stream<Item = Event> + Send + 'static {
+        self.events.listener()
+    }
+
+    /// Get a handle to this channel using a different timeout for synchronous methods
+    /// (`None` to wait forever), overriding [`ConnectionProperties::with_rpc_timeout`].
+    ///
+    /// A timed out method closes the whole channel, as its late reply could not be told apart
+    /// from the reply to a subsequent method. Timing out on `channel.close` fails the connection.
+    ///
+    /// ```rust,no_run
+    /// # use lapin::{Channel, options::QueueDeclareOptions, types::FieldTable};
+    /// # use std::time::Duration;
+    /// # async fn declare(channel: &Channel) -> lapin::Result<()> {
+    /// channel
+    ///     .with_rpc_timeout(Some(Duration::from_secs(5)))
+    ///     .queue_declare("queue".into(), QueueDeclareOptions::default(), FieldTable::default())
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`ConnectionProperties::with_rpc_timeout`]: ./struct.ConnectionProperties.html#method.with_rpc_timeout
+    #[must_use]
+    pub fn with_rpc_timeout(&self, timeout: Option<Duration>) -> Channel {
+        let mut channel = self.clone();
+        channel.rpc_timeout = timeout;
+        channel
+    }
+
+    pub fn rpc_timeout(&self) -> Option<Duration> {
+        self.rpc_timeout
+    }
+
+    async fn wait_for_reply<T>(&self, operation: &'static str, promise: Promise<T>) -> Result<T> {
+        let res = self
+            .internal_rpc
+            .timeout(self.rpc_timeout, operation, promise)
+            .await;
+        if let Err(error) = res.as_ref()
+            && let ErrorKind::Timeout(_) = error.kind()
+        {
+            self.on_reply_timeout(operation, error.clone());
+        }
+        res
+    }
+
+    // The replies are matched to the ExpectedReply of their kind in order, so we cannot just drop
+    // the timed out one: its late reply would be mistaken for the reply of a subsequent call.
+    // Close the channel instead, which cancels all the replies it still expects once the server
+    // acknowledges it. If the server doesn't, or if it's the connection itself, give up on the
+    // whole connection.
+    fn on_reply_timeout(&self, operation: &'static str, error: Error) {
+        if self.id == 0 || operation == "channel.close" {
+            self.internal_rpc.set_connection_error(error);
+            return;
+        }
+        if !self.status.connected() {
+            return;
+        }
+        let channel = self.clone();
+        self.internal_rpc.spawn(async move {
+            match channel
+                .close(
+                    protocol::constants::REPLY_SUCCESS,
+                    format!("timed out waiting for {operation}").into(),
+                )
+                .await
+            {
+                Err(err) if matches!(err.kind(), ErrorKind::Timeout(_)) => Err(err),
+                _ => Ok(()),
+            }
+        });
+    }
+
+    /// Wait for the channel to be recovered after an operation failed with `error`.
+    ///
+    /// Returns `error` right away if it cannot be recovered from, and once the recovery is over
+    /// if it failed. An operation whose frame was already being written when the connection got
+    /// lost fails with the raw IO error: this then waits for the ongoing recovery of this channel.
     pub async fn wait_for_recovery(&self, error: Error) -> Result<()> {
+        // Frames that were already serialized when the connection broke get rejected with the
+        // raw IO error, fallback to the notifier of the ongoing recovery
         if self.recovery_config.can_recover(&error)
-            && let Some(notifier) = error.notifier()
+            && let Some(notifier) = error.notifier().or_else(|| self.status.recovery_notifier())
         {
             notifier.await;
+            // We gave up on this channel if we failed to restore its topology
+            if self.status.errored() {
+                return Err(error);
+            }
             return Ok(());
         }
         Err(error)
@@ -149,7 +267,13 @@ impl Channel {
     }
 
     pub(crate) fn set_closed(&self, error: Error) {
+        if self.status.reconnecting() {
+            // The connection got closed while we were recovering it
+            self.status.abort_recovery(error.clone());
+        }
         self.set_state(ChannelState::Closed);
+        self.report_stopped();
+        self.unacked_deliveries.clear();
         self.error_publisher_confirms(error.clone());
         self.cancel_consumers();
         self.internal_rpc.remove_channel(self.id, error);
@@ -157,12 +281,28 @@ impl Channel {
 
     // Only called in case of a protocol failure
     pub(crate) fn set_connection_error(&self, error: Error) {
+        if self.status.reconnecting() {
+            // We gave up on recovering the connection
+            self.status.abort_recovery(error.clone());
+        }
         self.set_state(ChannelState::Error);
+        self.report_stopped();
+        self.unacked_deliveries.clear();
         self.error_publisher_confirms(error.clone());
         self.error_consumers(error.clone());
         self.internal_rpc.remove_channel(self.id, error.clone());
     }
 
+    // The channel won't be used anymore, whether it got closed or failed
+    fn report_stopped(&self) {
+        if self.id != 0
+            && self.status.set_stopped()
+            && let Some(metrics) = self.metrics.as_deref()
+        {
+            metrics.channel_closed(self.id);
+        }
+    }
+
     fn error_publisher_confirms(&self, error: Error) {
         self.acknowledgements.on_channel_error(error);
     }
@@ -218,7 +358,26 @@ impl Channel {
     }
 
     pub async fn close(&self, reply_code: ReplyCode, reply_text: ShortString) -> Result<()> {
-        self.do_channel_close(reply_code, reply_text, 0, 0).await
+        self.do_channel_close(reply_code, reply_text.clone(), 0, 0)
+            .await?;
+        self.events_sender
+            .channel_closed(self.id, reply_code, reply_text);
+        Ok(())
+    }
+
+    /// Commit the current transaction.
+    ///
+    /// If the channel got recovered while a transaction was open, that transaction got rolled
+    /// back by the server. In that case, whatever was done since the recovery gets rolled back
+    /// too, as it would only be a part of the transaction, and a
+    /// [`ErrorKind::TransactionRolledBack`] error is returned so that the whole transaction can
+    /// be retried.
+    pub async fn tx_commit(&self) -> Result<()> {
+        if self.status.transaction_lost() {
+            self.tx_rollback().await?;
+            return Err(ErrorKind::TransactionRolledBack.into());
+        }
+        self.do_tx_commit().await
     }
 
     pub async fn basic_consume(
@@ -259,6 +418,310 @@ impl Channel {
         .await
     }
 
+    /// Declare all the exchanges, queues and bindings of `topology`, in dependency order: the
+    /// exchanges, then the bindings between exchanges, then the queues and finally their bindings.
+    ///
+    /// To report which exchanges and queues already existed, they first get passively declared
+    /// on a throwaway channel, as the server closes the channel when they don't.
+    pub async fn apply_topology(&self, topology: &Topology) -> Result<TopologyReport> {
+        let mut report = TopologyReport::default();
+        let mut probe = None;
+
+        for ex in topology.exchanges.iter().filter(|ex| ex.is_declared) {
+            let exists = self
+                .exists(&mut probe, async |channel| {
+                    channel
+                        .exchange_declare(
+                            ex.name.clone(),
+                            ex.kind.clone().unwrap_or_default(),
+                            ExchangeDeclareOptions {
+                                passive: true,
+                                ..ExchangeDeclareOptions::default()
+                            },
+                            FieldTable::default(),
+                        )
+                        .await
+                })
+                .await?;
+            self.exchange_declare(
+                ex.name.clone(),
+                ex.kind.clone().unwrap_or_default(),
+                ex.options.unwrap_or_default(),
+                ex.arguments.clone().unwrap_or_default(),
+            )
+            .await?;
+            if exists {
+                report.existing_exchanges.push(ex.name.clone());
+            } else {
+                report.created_exchanges.push(ex.name.clone());
+            }
+        }
+
+        for ex in &topology.exchanges {
+            for binding in &ex.bindings {
+                self.exchange_bind(
+                    ex.name.clone(),
+                    binding.source.clone(),
+                    binding.routing_key.clone(),
+                    ExchangeBindOptions::default(),
+                    binding.arguments.clone(),
+                )
+                .await?;
+            }
+        }
+
+        for queue in &topology.queues {
+            let name = if queue.is_declared {
+                let exists = !queue.server_named
+                    && self
+                        .exists(&mut probe, async |channel| {
+                            channel
+                                .queue_declare(
+                                    queue.name.clone(),
+                                    QueueDeclareOptions {
+                                        passive: true,
+                                        ..QueueDeclareOptions::default()
+                                    },
+                                    FieldTable::default(),
+                                )
+                                .await
+                                .map(|_| ())
+                        })
+                        .await?;
+                let declared = self
+                    .queue_declare(
+                        if queue.server_named {
+                            ShortString::default()
+                        } else {
+                            queue.name.clone()
+                        },
+                        queue.options.unwrap_or_default(),
+                        queue.arguments.clone().unwrap_or_default(),
+                    )
+                    .await?;
+                if exists {
+                    report.existing_queues.push(declared.name().clone());
+                } else {
+                    report.created_queues.push(declared.name().clone());
+                }
+                declared.name().clone()
+            } else {
+                queue.name.clone()
+            };
+            for binding in &queue.bindings {
+                self.queue_bind(
+                    name.clone(),
+                    binding.source.clone(),
+                    binding.routing_key.clone(),
+                    QueueBindOptions::default(),
+                    binding.arguments.clone(),
+                )
+                .await?;
+            }
+        }
+
+        if let Some(probe) = probe {
+            probe
+                .close(protocol::constants::REPLY_SUCCESS, "OK".into())
+                .await?;
+        }
+        Ok(report)
+    }
+
+    /// Check that the exchanges and queues of `topology` exist on the server, without creating
+    /// or altering them.
+    ///
+    /// Each of them is passively declared on a throwaway channel, as the server closes the
+    /// channel when they don't exist. A passive declare doesn't compare the kind, options and
+    /// arguments, so only the entities which cannot be accessed, such as an exclusive queue of
+    /// another connection, are reported as mismatched. Use [`Channel::verify_topology_strict`]
+    /// to check those too.
+    ///
+    /// Bindings cannot be checked, and queues with a server-generated name are skipped.
+    ///
+    /// [`Channel::verify_topology_strict`]: #method.verify_topology_strict
+    pub async fn verify_topology(&self, topology: &Topology) -> Result<TopologyVerification> {
+        self.do_verify_topology(topology, false).await
+    }
+
+    /// Like [`Channel::verify_topology`], but also check that the existing exchanges and queues
+    /// are equivalent to the ones marked as declared in `topology`.
+    ///
+    /// AMQP has no way to compare definitions without declaring them, so after the passive
+    /// declare, these get declared again with the expected kind, options and arguments on the
+    /// throwaway channel. This is a no-op for the server when they're equivalent, but an entity
+    /// deleted between both declares gets created again. Only use this when that is acceptable.
+    ///
+    /// [`Channel::verify_topology`]: #method.verify_topology
+    pub async fn verify_topology_strict(
+        &self,
+        topology: &Topology,
+    ) -> Result<TopologyVerification> {
+        self.do_verify_topology(topology, true).await
+    }
+
+    async fn do_verify_topology(
+        &self,
+        topology: &Topology,
+        strict: bool,
+    ) -> Result<TopologyVerification> {
+        let mut report = TopologyVerification::default();
+        let mut probe = None;
+
+        for ex in &topology.exchanges {
+            let kind = ex.kind.clone().unwrap_or_default();
+            let missing = self
+                .probe(&mut probe, async |channel| {
+                    channel
+                        .exchange_declare(
+                            ex.name.clone(),
+                            kind.clone(),
+                            ExchangeDeclareOptions {
+                                passive: true,
+                                ..ExchangeDeclareOptions::default()
+                            },
+                            FieldTable::default(),
+                        )
+                        .await
+                })
+                .await?;
+            let mismatch = match missing {
+                Some(error) if is_not_found(&error) => {
+                    report.missing_exchanges.push(ex.name.clone());
+                    continue;
+                }
+                Some(error) => Some(error),
+                None if strict && ex.is_declared => {
+                    self.probe(&mut probe, async |channel| {
+                        channel
+                            .exchange_declare(
+                                ex.name.clone(),
+                                kind,
+                                ExchangeDeclareOptions {
+                                    passive: false,
+                                    nowait: false,
+                                    ..ex.options.unwrap_or_default()
+                                },
+                                ex.arguments.clone().unwrap_or_default(),
+                            )
+                            .await
+                    })
+                    .await?
+                }
+                None => None,
+            };
+            if let Some(error) = mismatch {
+                report.mismatched_exchanges.push(TopologyMismatch {
+                    name: ex.name.clone(),
+                    reason: error.get_message().clone(),
+                });
+            }
+        }
+
+        for queue in topology.queues.iter().filter(|queue| !queue.server_named) {
+            let missing = self
+                .probe(&mut probe, async |channel| {
+                    channel
+                        .queue_declare(
+                            queue.name.clone(),
+                            QueueDeclareOptions {
+                                passive: true,
+                                ..QueueDeclareOptions::default()
+                            },
+                            FieldTable::default(),
+                        )
+                        .await
+                        .map(|_| ())
+                })
+                .await?;
+            let mismatch = match missing {
+                Some(error) if is_not_found(&error) => {
+                    report.missing_queues.push(queue.name.clone());
+                    continue;
+                }
+                Some(error) => Some(error),
+                None if strict && queue.is_declared => {
+                    self.probe(&mut probe, async |channel| {
+                        channel
+                            .queue_declare(
+                                queue.name.clone(),
+                                QueueDeclareOptions {
+                                    passive: false,
+                                    nowait: false,
+                                    ..queue.options.unwrap_or_default()
+                                },
+                                queue.arguments.clone().unwrap_or_default(),
+                            )
+                            .await
+                            .map(|_| ())
+                    })
+                    .await?
+                }
+                None => None,
+            };
+            if let Some(error) = mismatch {
+                report.mismatched_queues.push(TopologyMismatch {
+                    name: queue.name.clone(),
+                    reason: error.get_message().clone(),
+                });
+            }
+        }
+
+        if let Some(probe) = probe {
+            probe
+                .close(protocol::constants::REPLY_SUCCESS, "OK".into())
+                .await?;
+        }
+        Ok(report)
+    }
+
+    // Passively declare an entity on a throwaway channel to know whether it exists
+    async fn exists(
+        &self,
+        probe: &mut Option<Channel>,
+        declare: impl AsyncFnOnce(&Channel) -> Result<()>,
+    ) -> Result<bool> {
+        match self.probe(probe, declare).await? {
+            None => Ok(true),
+            Some(error) if is_not_found(&error) => Ok(false),
+            Some(error) => Err(ErrorKind::ProtocolError(error).into()),
+        }
+    }
+
+    // Run a command on a throwaway channel, reopening it when the server closed it. The soft
+    // error which made the server close it is returned.
+    async fn probe(
+        &self,
+        probe: &mut Option<Channel>,
+        command: impl AsyncFnOnce(&Channel) -> Result<()>,
+    ) -> Result<Option<AMQPError>> {
+        let channel = match probe.take() {
+            Some(channel) if channel.status().connected() => channel,
+            _ => self.create_throwaway_channel().await?,
+        };
+        match command(&channel).await {
+            Ok(()) => {
+                *probe = Some(channel);
+                Ok(None)
+            }
+            Err(err) => match err.kind() {
+                ErrorKind::ProtocolError(error) if err.is_amqp_soft_error() => {
+                    Ok(Some(error.clone()))
+                }
+                _ => Err(err),
+            },
+        }
+    }
+
+    async fn create_throwaway_channel(&self) -> Result<Channel> {
+        let Some(connection_closer) = self.connection_closer.clone() else {
+            return Err(ErrorKind::InvalidChannel(self.id).into());
+        };
+        self.internal_rpc
+            .create_channel(connection_closer, ChannelKind::Throwaway)
+            .await
+    }
+
     pub async fn wait_for_confirms(&self) -> Result<Vec<BasicReturnMessage>> {
         if let Some(last_pending) = self.acknowledgements.get_last_pending() {
             trace!("Waiting for pending confirms");
@@ -276,7 +739,8 @@ impl Channel {
         options: QueueDeclareOptions,
         arguments: FieldTable,
     ) {
-        self.local_registry.register_queue(name, options, arguments);
+        self.local_registry
+            .register_queue(name, options, arguments, false);
     }
 
     #[cfg(test)]
@@ -289,7 +753,10 @@ impl Channel {
     }
 
     pub(crate) fn init_recovery(&self, error: Error) -> Error {
-        let err = self.status.set_reconnecting(error, self.topology());
+        self.events_sender.recovery_started(self.id);
+        let err = self
+            .status
+            .set_reconnecting(error, self.topology_definition());
         self.frames.drop_frames_for_channel(self.id, err.clone());
         self.poison(err.clone());
         err
@@ -305,6 +772,8 @@ impl Channel {
             ctx.set_expected_replies(self.frames.take_expected_replies(self.id));
             // Also reset the acknowledgements state for this channel
             self.acknowledgements.reset(ctx.cause());
+            // The deliveries received before can no longer be acked
+            self.unacked_deliveries.clear();
             // Also drop frames poisoning
             self.frames.drop_channel_poison(self.id);
             ctx.topology()
@@ -312,7 +781,60 @@ impl Channel {
     }
 
     pub(crate) async fn start_recovery(&self) -> Result<()> {
+        let listener = self.recovery_config.listener.as_deref();
+        if let Some(listener) = listener {
+            listener.before_channel_recovery(self);
+        }
+        self.status
+            .set_recovery_state(ChannelRecoveryState::Recovering);
         let topology = self.update_recovery().expect("No topology during recovery");
+        let consumers = topology.consumers.clone();
+        let res = self.recover_topology(topology, listener).await;
+        match res.as_ref() {
+            Ok(()) => {
+                self.status
+                    .set_recovery_state(ChannelRecoveryState::Recovered);
+                self.events_sender.recovery_succeeded(self.id);
+            }
+            Err(err) => {
+                self.events_sender.recovery_failed(self.id, err.clone());
+                // If the connection itself is still fine, give up on this channel only
+                if self.connection_status.connected() {
+                    self.set_recovery_error(err.clone(), consumers);
+                }
+            }
+        }
+        if let Some(listener) = listener {
+            listener.after_channel_recovery(self, &res);
+        }
+        res
+    }
+
+    fn set_recovery_error(&self, error: Error, consumers: Vec<Consumer>) {
+        error!(channel=%self.id, %error, "Failed to recover channel");
+        self.status.abort_recovery(error.clone());
+        self.report_stopped();
+        self.error_publisher_confirms(error.clone());
+        // Consumers which didn't get restored yet are only known by the topology
+        for consumer in consumers {
+            self.consumers.register(consumer.tag(), consumer);
+        }
+        self.consumers.error(error, false);
+    }
+
+    async fn recover_topology(
+        &self,
+        mut topology: ChannelDefinition,
+        listener: Option<&dyn RecoveryListener>,
+    ) -> Result<()> {
+        let mut altered = AlteredTopology::default();
+        if self.connection_registry().is_some() {
+            // The connection-wide topology already got restored on a dedicated channel
+            topology.exchanges.clear();
+            topology.queues.clear();
+        } else if let Some(listener) = listener {
+            altered = self.filter_topology(listener, &mut topology);
+        }
 
         // First, reopen the channel
         self.channel_open(self.clone()).await?;
@@ -322,7 +844,59 @@ impl Channel {
             self.confirm_select(ConfirmSelectOptions::default()).await?;
         }
 
-        // Third, redeclare all exchanges
+        // Or the transaction mode
+        if self.status.transactional() {
+            self.tx_select().await?;
+        }
+
+        // Third, redeclare all exchanges, queues and bindings
+        let renamed_queues = self.restore_declarations(&topology).await?;
+        self.forget_altered_topology(altered, &renamed_queues);
+
+        // Fourth, restore the prefetch settings before the consumers get registered
+        if let Some(prefetch_count) = topology.qos.global_prefetch_count {
+            self.basic_qos(prefetch_count, BasicQosOptions { global: true })
+                .await?;
+        }
+        if let Some(prefetch_count) = topology.qos.consumer_prefetch_count {
+            self.basic_qos(prefetch_count, BasicQosOptions { global: false })
+                .await?;
+        }
+
+        // Fifth, redeclare all consumers
+        for consumer in topology.consumers.iter().cloned() {
+            let mut definition = ConsumerDefinition::new(&consumer);
+            if let Some(listener) = listener
+                && !listener.filter_consumer(self.id, &mut definition)
+            {
+                self.deregister_consumer(consumer.tag().as_str());
+                continue;
+            }
+            consumer.reset();
+            self.do_basic_consume(
+                definition.queue,
+                consumer.tag(),
+                definition.options,
+                definition.arguments,
+                Some(consumer),
+            )
+            .await?;
+        }
+
+        // Finally, let the RecoveryListener run its own commands
+        if let Some(listener) = listener {
+            listener.after_topology_recovery(self).await?;
+        }
+
+        Ok(())
+    }
+
+    // Returns the new names of the server-named queues
+    pub(crate) async fn restore_declarations(
+        &self,
+        topology: &ChannelDefinition,
+    ) -> Result<HashMap<ShortString, ShortString>> {
+        // First, redeclare all exchanges
         for ex in &topology.exchanges {
             if ex.is_declared {
                 self.exchange_declare(
@@ -335,7 +909,7 @@ impl Channel {
             }
         }
 
-        // Fourth, redeclare all exchange bindings
+        // Then, redeclare all exchange bindings
         for ex in &topology.exchanges {
             for binding in &ex.bindings {
                 self.exchange_bind(
@@ -349,23 +923,36 @@ impl Channel {
             }
         }
 
-        // Fifth, redeclare all queues
+        // Then, redeclare all queues, letting the server pick a new name for server-named ones
+        let mut renamed_queues = HashMap::new();
         for queue in &topology.queues {
             if queue.is_declared {
-                self.queue_declare(
-                    queue.name.clone(),
-                    queue.options.unwrap_or_default(),
-                    queue.arguments.clone().unwrap_or_default(),
-                )
-                .await?;
+                let declared = self
+                    .queue_declare(
+                        if queue.server_named {
+                            ShortString::default()
+                        } else {
+                            queue.name.clone()
+                        },
+                        queue.options.unwrap_or_default(),
+                        queue.arguments.clone().unwrap_or_default(),
+                    )
+                    .await?;
+                if declared.name() != &queue.name {
+                    self.rename_queue(&queue.name, declared.name().clone());
+                    renamed_queues.insert(queue.name.clone(), declared.name().clone());
+                }
             }
         }
 
-        // Sixth, redeclare all queues bindings
+        // Finally, redeclare all queues bindings
         for queue in &topology.queues {
             for binding in &queue.bindings {
                 self.queue_bind(
-                    queue.name.clone(),
+                    renamed_queues
+                        .get(&queue.name)
+                        .unwrap_or(&queue.name)
+                        .clone(),
                     binding.source.clone(),
                     binding.routing_key.clone(),
                     QueueBindOptions::default(),
@@ -375,20 +962,148 @@ impl Channel {
             }
         }
 
-        // Finally, redeclare all consumers
-        for consumer in topology.consumers.iter().cloned() {
-            consumer.reset();
-            self.do_basic_consume(
-                consumer.queue().clone(),
-                consumer.tag().clone(),
-                consumer.options(),
-                consumer.arguments(),
-                Some(consumer),
-            )
-            .await?;
+        Ok(renamed_queues)
+    }
+
+    // Skipped definitions are dropped from the registry right away. The original version of the
+    // altered definitions only gets dropped once the altered one got declared, with
+    // forget_altered_topology, so that we don't lose it if the recovery fails before that.
+    // The registry knows the definitions by their original name, whatever the listener did.
+    pub(crate) fn filter_topology(
+        &self,
+        listener: &dyn RecoveryListener,
+        topology: &mut ChannelDefinition,
+    ) -> AlteredTopology {
+        let mut altered = AlteredTopology::default();
+        topology.exchanges.retain_mut(|exchange| {
+            let name = exchange.name.clone();
+            if exchange.is_declared && !listener.filter_exchange(self.id, exchange) {
+                self.update_registries(|registry| registry.deregister_exchange(name.as_str()));
+                return false;
+            }
+            if exchange.name != name {
+                altered.exchanges.push(name.clone());
+            }
+            exchange.bindings.retain_mut(|binding| {
+                let original = binding.clone();
+                let keep =
+                    listener.filter_exchange_binding(self.id, exchange.name.as_str(), binding);
+                if !keep {
+                    self.update_registries(|registry| {
+                        registry.deregister_exchange_binding(
+                            name.as_str(),
+                            original.source.as_str(),
+                            original.routing_key.as_str(),
+                            &original.arguments,
+                        )
+                    });
+                } else if *binding != original {
+                    altered.exchange_bindings.push((name.clone(), original));
+                }
+                keep
+            });
+            true
+        });
+        topology.queues.retain_mut(|queue| {
+            let name = queue.name.clone();
+            if queue.is_declared && !listener.filter_queue(self.id, queue) {
+                self.update_registries(|registry| registry.deregister_queue(name.as_str()));
+                return false;
+            }
+            if queue.name != name {
+                altered.queues.push(name.clone());
+            }
+            queue.bindings.retain_mut(|binding| {
+                let original = binding.clone();
+                let keep = listener.filter_queue_binding(self.id, queue.name.as_str(), binding);
+                if !keep {
+                    self.update_registries(|registry| {
+                        registry.deregister_queue_binding(
+                            name.as_str(),
+                            original.source.as_str(),
+                            original.routing_key.as_str(),
+                            &original.arguments,
+                        )
+                    });
+                } else if *binding != original {
+                    altered.queue_bindings.push((name.clone(), original));
+                }
+                keep
+            });
+            true
+        });
+        altered
+    }
+
+    pub(crate) fn forget_altered_topology(
+        &self,
+        altered: AlteredTopology,
+        renamed_queues: &HashMap<ShortString, ShortString>,
+    ) {
+        for (exchange, original) in altered.exchange_bindings {
+            self.update_registries(|registry| {
+                registry.deregister_exchange_binding(
+                    exchange.as_str(),
+                    original.source.as_str(),
+                    original.routing_key.as_str(),
+                    &original.arguments,
+                )
+            });
+        }
+        for (queue, original) in altered.queue_bindings {
+            let queue = renamed_queues.get(&queue).unwrap_or(&queue);
+            self.update_registries(|registry| {
+                registry.deregister_queue_binding(
+                    queue.as_str(),
+                    original.source.as_str(),
+                    original.routing_key.as_str(),
+                    &original.arguments,
+                )
+            });
+        }
+        // The altered exchanges and queues got registered under their new name
+        for exchange in altered.exchanges {
+            self.update_registries(|registry| registry.deregister_exchange(exchange.as_str()));
+        }
+        for queue in altered.queues {
+            self.update_registries(|registry| registry.deregister_queue(queue.as_str()));
         }
+    }
 
-        Ok(())
+    pub(crate) fn rename_queue(&self, old_name: &ShortString, new_name: ShortString) {
+        info!(channel=%self.id, %old_name, %new_name, "Server-named queue got renamed during recovery");
+        if let Some(registry) = self.connection_registry() {
+            registry.rename_queue(old_name.as_str(), new_name.clone());
+        }
+        self.follow_queue_rename(old_name, &new_name);
+        self.events_sender
+            .queue_renamed(self.id, old_name.clone(), new_name);
+    }
+
+    // Also used when a queue of the connection-wide topology got renamed on another channel
+    pub(crate) fn follow_queue_rename(&self, old_name: &ShortString, new_name: &ShortString) {
+        self.local_registry
+            .rename_queue(old_name.as_str(), new_name.clone());
+        self.consumers.rename_queue(old_name.as_str(), new_name);
+        // Consumers waiting to be recovered are only known by the recovery context
+        self.status.update_recovery_context(|ctx| {
+            for consumer in ctx.topology().consumers {
+                consumer.rename_queue(old_name.as_str(), new_name);
+            }
+        });
+    }
+
+    fn connection_registry(&self) -> Option<&Registry> {
+        self.recovery_config.registry.as_ref()
+    }
+
+    // Record topology changes in the registry of this channel, and in the connection-wide one
+    // if enabled
+    fn update_registries<F: Fn(&Registry)>(&self, update: F) {
+        update(&self.local_registry);
+        if let Some(registry) = self.connection_registry() {
+            update(registry);
+        }
     }
 
     pub(crate) fn send_method_frame(
@@ -428,6 +1143,8 @@ impl Channel {
         publisher_confirms_result: Option<PublisherConfirm>,
     ) -> Result<PublisherConfirm> {
         let class_id = method.get_amqp_class_id();
+        #[cfg(feature = "trace-context")]
+        let properties = crate::trace_context::inject_current(properties);
         let header = AMQPContentHeader {
             class_id,
             body_size: payload.len() as PayloadSize,
@@ -540,15 +1257,51 @@ impl Channel {
         )
     }
 
-    pub(crate) fn topology(&self) -> ChannelDefinition {
+    /// Get a snapshot of everything recorded on this channel to recover it: exchanges, queues,
+    /// bindings, consumers and prefetch settings.
+    ///
+    /// With the `serde` feature, it can be serialized to dump what this channel believes it
+    /// declared.
+    pub fn topology(&self) -> ChannelTopology {
+        // While recovering, consumers are only known by the recovery context
+        self.status
+            .update_recovery_context(|ctx| ctx.topology())
+            .unwrap_or_else(|| self.topology_definition())
+            .export(self.id)
+    }
+
+    /// Get a snapshot of the runtime state of this channel: its consumers, and the operations
+    /// still pending on it.
+    ///
+    /// With the `serde` feature, it can be serialized to expose it on an admin endpoint.
+    pub fn snapshot(&self) -> ChannelSnapshot {
+        ChannelSnapshot {
+            channel_id: self.id,
+            state: self.status.state(),
+            recovery_state: self.status.recovery_state(),
+            confirm: self.status.confirm(),
+            pending_confirms: self.acknowledgements.pending_count(),
+            consumers: self.consumers.snapshot(),
+            unacked_deliveries: self.unacked_deliveries.count(),
+            queued_frames: self.frames.queued_frames(self.id),
+            expected_replies: self.frames.expected_replies(self.id),
+        }
+    }
+
+    pub(crate) fn topology_definition(&self) -> ChannelDefinition {
         ChannelDefinition {
             exchanges: self.local_registry.exchanges_topology(),
             queues: self.local_registry.queues_topology(),
             consumers: self.consumers.topology(),
+            qos: self.local_registry.qos_topology(),
         }
     }
 
     fn before_basic_publish(&self) -> Option<PublisherConfirm> {
+        self.status.touch_transaction();
+        if let Some(metrics) = self.metrics.as_deref() {
+            metrics.message_published(self.id);
+        }
         if self.status.confirm() {
             Some(self.acknowledgements.register_pending())
         } else {
@@ -620,21 +1373,40 @@ impl Channel {
     }
 
     fn on_basic_recover_async_sent(&self) {
+        self.unacked_deliveries.clear();
         self.consumers.drop_prefetched_messages();
     }
 
     fn on_basic_ack_sent(&self, multiple: bool, delivery_tag: DeliveryTag) {
+        self.status.touch_transaction();
+        self.unacked_deliveries.acknowledge(multiple, delivery_tag);
+        if let Some(metrics) = self.metrics.as_deref() {
+            metrics.message_acked(self.id);
+        }
         if multiple && delivery_tag == 0 {
             self.consumers.drop_prefetched_messages();
         }
     }
 
     fn on_basic_nack_sent(&self, multiple: bool, delivery_tag: DeliveryTag) {
+        self.status.touch_transaction();
+        self.unacked_deliveries.acknowledge(multiple, delivery_tag);
+        if let Some(metrics) = self.metrics.as_deref() {
+            metrics.message_nacked(self.id);
+        }
         if multiple && delivery_tag == 0 {
             self.consumers.drop_prefetched_messages();
         }
     }
 
+    fn on_basic_reject_sent(&self, delivery_tag: DeliveryTag) {
+        self.status.touch_transaction();
+        self.unacked_deliveries.acknowledge(false, delivery_tag);
+        if let Some(metrics) = self.metrics.as_deref() {
+            metrics.message_rejected(self.id);
+        }
+    }
+
     fn tune_connection_configuration(
         &self,
         channel_max: ChannelId,
@@ -850,9 +1622,7 @@ impl Channel {
                             configuration.heartbeat(),
                         )
                         .await?;
-                    channel
-                        .connection_open(vhost, Box::new(connection), resolver)
-                        .await
+                    channel.connection_open(vhost, connection, resolver).await
                 });
                 Ok(())
             }
@@ -937,13 +1707,14 @@ impl Channel {
         if !self.status.confirm() {
             self.finalize_connection();
         }
+        self.events_sender.channel_opened(self.id);
         resolver.resolve(channel);
         Ok(())
     }
 
     fn on_channel_flow_received(&self, method: protocol::channel::Flow) -> Result<()> {
         self.status.set_send_flow(method.active);
-        self.events_sender.send_flow(method.active);
+        self.events_sender.send_flow(self.id, method.active);
         let channel = self.clone();
         self.internal_rpc.spawn(async move {
             channel
@@ -973,6 +1744,8 @@ impl Channel {
                 );
                 Error::from(ErrorKind::ProtocolError(error))
             }).map_err(|error| info!(channel=%self.id, ?method, code_to_error=%error, "Channel closed with a non-error code")).ok();
+        self.events_sender
+            .channel_closed(self.id, method.reply_code, method.reply_text.clone());
         self.set_closing(error.clone());
         let channel = self.clone();
         self.internal_rpc
@@ -995,8 +1768,14 @@ impl Channel {
         routing_key: ShortString,
         arguments: FieldTable,
     ) -> Result<()> {
-        self.local_registry
-            .register_exchange_binding(destination, source, routing_key, arguments);
+        self.update_registries(|registry| {
+            registry.register_exchange_binding(
+                destination.clone(),
+                source.clone(),
+                routing_key.clone(),
+                arguments.clone(),
+            )
+        });
         Ok(())
     }
 
@@ -1007,12 +1786,14 @@ impl Channel {
         routing_key: ShortString,
         arguments: FieldTable,
     ) -> Result<()> {
-        self.local_registry.deregister_exchange_binding(
-            destination.as_str(),
-            source.as_str(),
-            routing_key.as_str(),
-            &arguments,
-        );
+        self.update_registries(|registry| {
+            registry.deregister_exchange_binding(
+                destination.as_str(),
+                source.as_str(),
+                routing_key.as_str(),
+                &arguments,
+            )
+        });
         Ok(())
     }
 
@@ -1024,14 +1805,15 @@ impl Channel {
         options: ExchangeDeclareOptions,
         arguments: FieldTable,
     ) -> Result<()> {
-        self.local_registry
-            .register_exchange(exchange, kind, options, arguments);
+        self.update_registries(|registry| {
+            registry.register_exchange(exchange.clone(), kind.clone(), options, arguments.clone())
+        });
         resolver.resolve(());
         Ok(())
     }
 
     fn on_exchange_delete_ok_received(&self, exchange: ShortString) -> Result<()> {
-        self.local_registry.deregister_exchange(exchange.as_str());
+        self.update_registries(|registry| registry.deregister_exchange(exchange.as_str()));
         Ok(())
     }
 
@@ -1041,7 +1823,7 @@ impl Channel {
         resolver: PromiseResolver<MessageCount>,
         queue: ShortString,
     ) -> Result<()> {
-        self.local_registry.deregister_queue(queue.as_str());
+        self.update_registries(|registry| registry.deregister_queue(queue.as_str()));
         resolver.resolve(method.message_count);
         Ok(())
     }
@@ -1059,13 +1841,28 @@ impl Channel {
         &self,
         method: protocol::queue::DeclareOk,
         resolver: PromiseResolver<Queue>,
+        queue: ShortString,
         options: QueueDeclareOptions,
         arguments: FieldTable,
     ) -> Result<()> {
-        self.local_registry
-            .register_queue(method.queue.clone(), options, arguments);
+        let server_named = queue.as_str().is_empty();
+        if let Some(registry) = self.connection_registry() {
+            registry.register_queue(
+                method.queue.clone(),
+                options,
+                arguments.clone(),
+                server_named,
+            );
+        }
+        let current_name = self.local_registry.register_queue(
+            method.queue.clone(),
+            options,
+            arguments,
+            server_named,
+        );
         resolver.resolve(Queue::new(
             method.queue,
+            current_name,
             method.message_count,
             method.consumer_count,
         ));
@@ -1079,8 +1876,14 @@ impl Channel {
         routing_key: ShortString,
         arguments: FieldTable,
     ) -> Result<()> {
-        self.local_registry
-            .register_queue_binding(queue, exchange, routing_key, arguments);
+        self.update_registries(|registry| {
+            registry.register_queue_binding(
+                queue.clone(),
+                exchange.clone(),
+                routing_key.clone(),
+                arguments.clone(),
+            )
+        });
         Ok(())
     }
 
@@ -1091,12 +1894,14 @@ impl Channel {
         routing_key: ShortString,
         arguments: FieldTable,
     ) -> Result<()> {
-        self.local_registry.deregister_queue_binding(
-            queue.as_str(),
-            exchange.as_str(),
-            routing_key.as_str(),
-            &arguments,
-        );
+        self.update_registries(|registry| {
+            registry.deregister_queue_binding(
+                queue.as_str(),
+                exchange.as_str(),
+                routing_key.as_str(),
+                &arguments,
+            )
+        });
         Ok(())
     }
 
@@ -1104,9 +1909,16 @@ impl Channel {
         &self,
         method: protocol::basic::GetOk,
         resolver: PromiseResolver<Option<BasicGetMessage>>,
+        no_ack: bool,
     ) -> Result<()> {
         let class_id = method.get_amqp_class_id();
         let killswitch = self.status.set_will_receive(class_id, DeliveryCause::Get);
+        if let Some(metrics) = self.metrics.as_deref() {
+            metrics.message_delivered(self.id);
+        }
+        if !no_ack {
+            self.unacked_deliveries.register(method.delivery_tag);
+        }
         self.basic_get_delivery.start_new_delivery(
             BasicGetMessage::new(
                 self.id,
@@ -1172,6 +1984,12 @@ impl Channel {
         let killswitch = self
             .status
             .set_will_receive(class_id, DeliveryCause::Consume(consumer_tag.clone()));
+        if let Some(metrics) = self.metrics.as_deref() {
+            metrics.message_delivered(self.id);
+        }
+        if self.consumers.requires_ack(&consumer_tag) {
+            self.unacked_deliveries.register(method.delivery_tag);
+        }
         self.consumers.start_delivery(&consumer_tag, |error| {
             Delivery::new(
                 self.id,
@@ -1193,6 +2011,8 @@ impl Channel {
 
     fn on_basic_cancel_received(&self, method: protocol::basic::Cancel) -> Result<()> {
         self.deregister_consumer(method.consumer_tag.as_str());
+        self.events_sender
+            .consumer_cancelled(self.id, method.consumer_tag.clone());
         if !method.nowait {
             let channel = self.clone();
             self.internal_rpc
@@ -1270,9 +2090,19 @@ impl Channel {
 
     fn on_basic_return_received(&self, method: protocol::basic::Return) -> Result<()> {
         let class_id = method.get_amqp_class_id();
+        self.events_sender.basic_return(
+            self.id,
+            method.reply_code,
+            method.reply_text.clone(),
+            method.exchange.clone(),
+            method.routing_key.clone(),
+        );
         let killswitch = self
             .status
             .set_will_receive(class_id, DeliveryCause::Return);
+        if let Some(metrics) = self.metrics.as_deref() {
+            metrics.message_returned(self.id);
+        }
         self.returned_messages
             .start_new_delivery(BasicReturnMessage::new(
                 method.exchange,
@@ -1284,7 +2114,13 @@ impl Channel {
         Ok(())
     }
 
+    fn on_basic_qos_ok_received(&self, prefetch_count: ShortUInt, global: Boolean) -> Result<()> {
+        self.local_registry.register_qos(prefetch_count, global);
+        Ok(())
+    }
+
     fn on_basic_recover_ok_received(&self) -> Result<()> {
+        self.unacked_deliveries.clear();
         self.consumers.drop_prefetched_messages();
         Ok(())
     }
@@ -1294,6 +2130,21 @@ impl Channel {
         Ok(())
     }
 
+    fn on_tx_select_ok_received(&self) -> Result<()> {
+        self.status.set_transactional();
+        Ok(())
+    }
+
+    fn on_tx_commit_ok_received(&self) -> Result<()> {
+        self.status.end_transaction();
+        Ok(())
+    }
+
+    fn on_tx_rollback_ok_received(&self) -> Result<()> {
+        self.status.end_transaction();
+        Ok(())
+    }
+
     fn on_access_request_ok_received(&self, _: protocol::access::RequestOk) -> Result<()> {
         Ok(())
     }
@@ -1303,3 +2154,17 @@ impl Channel {
 include!(concat!(env!("OUT_DIR"), "/channel.rs"));
 #[cfg(not(feature = "codegen"))]
 include!("generated/channel.rs");
+
+// The definitions altered by a RecoveryListener: the original name of the renamed exchanges and
+// queues, and the original version of the bindings
+#[derive(Default)]
+pub(crate) struct AlteredTopology {
+    exchanges: Vec<ShortString>,
+    queues: Vec<ShortString>,
+    exchange_bindings: Vec<(ShortString, BindingDefinition)>,
+    queue_bindings: Vec<(ShortString, BindingDefinition)>,
+}
+
+fn is_not_found(error: &AMQPError) -> bool {
+    *error.kind() == AMQPErrorKind::Soft(AMQPSoftError::NOTFOUND)
+}
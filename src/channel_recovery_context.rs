@@ -45,9 +45,14 @@ impl ChannelRecoveryContext {
     }
 
     pub(crate) fn finalize_recovery(self) {
+        let cause = self.cause.clone();
+        self.abort_recovery(cause);
+    }
+
+    pub(crate) fn abort_recovery(self, error: Error) {
         self.notifier.notify_all();
         if let Some(replies) = self.expected_replies {
-            Frames::cancel_expected_replies(replies, self.cause);
+            Frames::cancel_expected_replies(replies, error);
         }
     }
 }
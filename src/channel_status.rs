@@ -38,6 +38,10 @@ impl ChannelStatus {
         self.read().state == ChannelState::Reconnecting
     }
 
+    pub fn errored(&self) -> bool {
+        self.read().state == ChannelState::Error
+    }
+
     pub(crate) fn connected_or_recovering(&self) -> bool {
         [ChannelState::Connected, ChannelState::Reconnecting].contains(&self.read().state)
     }
@@ -53,6 +57,10 @@ impl ChannelStatus {
         self.write().finalize_connection();
     }
 
+    pub(crate) fn abort_recovery(&self, error: Error) {
+        self.write().abort_recovery(error);
+    }
+
     pub(crate) fn can_receive_messages(&self) -> bool {
         [
             ChannelState::Closing,
@@ -73,10 +81,62 @@ impl ChannelStatus {
         inner.finalize_connection();
     }
 
+    /// The progress of the recovery of this channel, if it ever had to be recovered
+    pub fn recovery_state(&self) -> Option<ChannelRecoveryState> {
+        self.read().recovery_state
+    }
+
+    pub(crate) fn set_recovery_state(&self, state: ChannelRecoveryState) {
+        self.write().recovery_state = Some(state);
+    }
+
+    pub fn transactional(&self) -> bool {
+        self.read().transactional
+    }
+
+    pub(crate) fn set_transactional(&self) {
+        self.write().transactional = true;
+        trace!("Transactions activated");
+    }
+
+    // Something got published or acknowledged in the current transaction
+    pub(crate) fn touch_transaction(&self) {
+        let mut inner = self.write();
+        if inner.transactional {
+            inner.transaction_pending = true;
+        }
+    }
+
+    // The current transaction got committed or rolled back
+    pub(crate) fn end_transaction(&self) {
+        let mut inner = self.write();
+        inner.transaction_pending = false;
+        inner.transaction_lost = false;
+    }
+
+    // Whether the transaction open when the channel got recovered got rolled back without the user
+    // being notified yet
+    pub(crate) fn transaction_lost(&self) -> bool {
+        self.read().transaction_lost
+    }
+
+    pub fn state(&self) -> ChannelState {
+        self.read().state
+    }
+
     pub(crate) fn set_state(&self, state: ChannelState) {
         self.write().state = state;
     }
 
+    // Returns whether the channel wasn't already stopped, so that it only gets reported once
+    pub(crate) fn set_stopped(&self) -> bool {
+        !std::mem::replace(&mut self.write().stopped, true)
+    }
+
+    pub(crate) fn recovery_notifier(&self) -> Option<Notifier> {
+        self.read().notifier()
+    }
+
     pub(crate) fn state_error(&self, context: &'static str) -> Error {
         let inner = self.read();
         let error = Error::from(ErrorKind::InvalidChannelState(inner.state, context));
@@ -172,6 +232,7 @@ impl ChannelStatus {
 }
 
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ChannelState {
     #[default]
     Initial,
@@ -182,6 +243,20 @@ pub enum ChannelState {
     Error,
 }
 
+/// The progress of the recovery of a channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChannelRecoveryState {
+    /// Waiting for the connection to be recovered
+    Pending,
+    /// Reopening the channel and restoring its topology
+    Recovering,
+    /// The channel got recovered
+    Recovered,
+    /// We gave up on recovering the channel
+    Failed,
+}
+
 impl fmt::Debug for ChannelStatus {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut debug = f.debug_struct("ChannelStatus");
@@ -190,6 +265,8 @@ impl fmt::Debug for ChannelStatus {
                 .field("state", &inner.state)
                 .field("receiver_state", &inner.receiver_state)
                 .field("confirm", &inner.confirm)
+                .field("transactional", &inner.transactional)
+                .field("recovery_state", &inner.recovery_state)
                 .field("send_flow", &inner.send_flow);
         }
         debug.finish()
@@ -199,10 +276,15 @@ impl fmt::Debug for ChannelStatus {
 struct Inner {
     id: ChannelId,
     confirm: bool,
+    transactional: bool,
+    transaction_pending: bool,
+    transaction_lost: bool,
     send_flow: bool,
     state: ChannelState,
     receiver_state: ChannelReceiverStates,
     recovery_context: Option<ChannelRecoveryContext>,
+    recovery_state: Option<ChannelRecoveryState>,
+    stopped: bool,
     killswitch: KillSwitch,
     internal_rpc: InternalRPCHandle,
 }
@@ -212,10 +294,15 @@ impl Inner {
         let this = Self {
             id,
             confirm: false,
+            transactional: false,
+            transaction_pending: false,
+            transaction_lost: false,
             send_flow: true,
             state: ChannelState::default(),
             receiver_state: ChannelReceiverStates::default(),
             recovery_context: None,
+            recovery_state: None,
+            stopped: false,
             killswitch: KillSwitch::default(),
             internal_rpc,
         };
@@ -230,8 +317,13 @@ impl Inner {
 
     fn set_reconnecting(&mut self, error: Error, topology: ChannelDefinition) -> Error {
         self.state = ChannelState::Reconnecting;
+        self.recovery_state = Some(ChannelRecoveryState::Pending);
         std::mem::take(&mut self.killswitch).kill();
         self.receiver_state.reset();
+        if std::mem::take(&mut self.transaction_pending) {
+            // The server rolls back the transaction when the channel gets closed
+            self.transaction_lost = true;
+        }
         match self.recovery_context.as_ref() {
             Some(ctx) => ctx.cause(),
             None => {
@@ -243,6 +335,14 @@ impl Inner {
         }
     }
 
+    fn abort_recovery(&mut self, error: Error) {
+        self.state = ChannelState::Error;
+        self.recovery_state = Some(ChannelRecoveryState::Failed);
+        if let Some(ctx) = self.recovery_context.take() {
+            ctx.abort_recovery(error);
+        }
+    }
+
     pub(crate) fn finalize_connection(&mut self) {
         self.state = ChannelState::Connected;
         self.update_rpc_status();
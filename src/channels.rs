@@ -5,21 +5,52 @@ use crate::{
     connection_closer::ConnectionCloser,
     events::{Events, EventsSender},
     frames::Frames,
+    future,
     id_sequence::IdSequence,
     internal_rpc::InternalRPCHandle,
     killswitch::KillSwitch,
-    protocol::{AMQPClass, AMQPError, AMQPHardError},
+    metrics::Metrics,
+    protocol::{self, AMQPClass, AMQPError, AMQPHardError},
+    registry::Registry,
+    snapshot::ConnectionSnapshot,
     socket_state::SocketStateHandle,
+    topology::{ChannelDefinition, ConnectionTopology, ExchangeDefinition},
     types::{ChannelId, Identifier, PayloadSize},
 };
 use amq_protocol::frame::{AMQPFrame, ProtocolVersion};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt,
     sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::Duration,
 };
 use tracing::{debug, error, trace};
 
+// What a channel gets created for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ChannelKind {
+    // Opened by the user
+    Application,
+    // Only used to check what exists on the server, its declarations don't get recorded at the
+    // connection level
+    Throwaway,
+    // Used by the connection itself: it doesn't show up in the snapshots, the topology nor the
+    // metrics, and doesn't get recovered
+    Internal,
+    // Internal channel restoring the connection-wide topology, which keeps it up to date
+    Recovery,
+}
+
+impl ChannelKind {
+    fn records_topology(self) -> bool {
+        matches!(self, Self::Application | Self::Recovery)
+    }
+
+    fn is_internal(self) -> bool {
+        matches!(self, Self::Internal | Self::Recovery)
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct Channels {
     inner: Arc<RwLock<Inner>>,
@@ -44,6 +75,8 @@ impl Channels {
         let mut inner = Inner::new(
             configuration.negotiated_config.clone(),
             configuration.recovery_config(),
+            configuration.rpc_timeout,
+            configuration.metrics.clone(),
             waker,
         );
         let channel0 = inner.create_channel(
@@ -53,6 +86,8 @@ impl Channels {
             frames.clone(),
             events.sender(),
             None,
+            false,
+            configuration.metrics.clone(),
         );
         channel0.set_state(ChannelState::Connected);
 
@@ -68,13 +103,18 @@ impl Channels {
         }
     }
 
-    pub(crate) fn create(&self, connection_closer: Arc<ConnectionCloser>) -> Result<Channel> {
+    pub(crate) fn create(
+        &self,
+        connection_closer: Arc<ConnectionCloser>,
+        kind: ChannelKind,
+    ) -> Result<Channel> {
         self.write().create(
             self.connection_status.clone(),
             self.internal_rpc.clone(),
             self.frames.clone(),
             self.events.sender(),
-            connection_closer,
+            Some(connection_closer),
+            kind,
         )
     }
 
@@ -86,6 +126,39 @@ impl Channels {
         self.configuration.recovery_config().can_recover(error)
     }
 
+    pub(crate) fn topology(&self) -> ConnectionTopology {
+        let (exchanges, queues) = self
+            .configuration
+            .connection_registry
+            .as_ref()
+            .map(|registry| (registry.exchanges_topology(), registry.queues_topology()))
+            .unwrap_or_default();
+        let mut channels = self
+            .read()
+            .application_channels()
+            .map(Channel::topology)
+            .collect::<Vec<_>>();
+        channels.sort_by_key(|channel| channel.channel_id);
+        ConnectionTopology {
+            exchanges,
+            queues,
+            channels,
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> ConnectionSnapshot {
+        let mut channels = self
+            .read()
+            .application_channels()
+            .map(Channel::snapshot)
+            .collect::<Vec<_>>();
+        channels.sort_by_key(|channel| channel.channel_id);
+        ConnectionSnapshot {
+            state: self.connection_status.state(),
+            channels,
+        }
+    }
+
     pub(crate) fn get(&self, id: ChannelId) -> Option<Channel> {
         if id == 0 {
             Some(self.channel0())
@@ -101,7 +174,9 @@ impl Channels {
             return Ok(());
         }
 
-        if self.write().channels.remove(&id).is_some() {
+        let mut inner = self.write();
+        inner.internal_channels.remove(&id);
+        if inner.channels.remove(&id).is_some() {
             Ok(())
         } else {
             Err(ErrorKind::InvalidChannel(id).into())
@@ -149,6 +224,9 @@ impl Channels {
 
     pub(crate) fn set_connection_closed(&self, error: Error) {
         self.connection_status.set_state(ConnectionState::Closed);
+        if self.channel0.status().reconnecting() {
+            self.channel0.status().abort_recovery(error.clone());
+        }
         for channel in self.read().channels.values() {
             channel.set_closed(error.clone());
         }
@@ -169,12 +247,16 @@ impl Channels {
         }
 
         error!(%error, "Connection error");
+        self.connection_status.abort_recovery(error.clone());
         if let Some(resolver) = self.frames.connection_resolver(0) {
             resolver.reject(error.clone());
         }
 
         self.frames.drop_pending(error.clone(), &self.internal_rpc);
         self.events.sender().error(error.clone());
+        if self.channel0.status().reconnecting() {
+            self.channel0.status().abort_recovery(error.clone());
+        }
         for channel in self.read().channels.values() {
             channel.set_connection_error(error.clone());
         }
@@ -241,43 +323,208 @@ impl Channels {
 
     pub(crate) fn init_connection_recovery(&self, error: Error) -> Error {
         trace!("init connection recovery");
-        self.connection_status.set_reconnecting();
+        if let Some(listener) = self.configuration.recovery_listener.as_deref() {
+            listener.before_connection_recovery(&error);
+        }
+        self.connection_status.set_reconnecting(error.clone());
         self.frames.clear_connection_steps(None);
-        let error = self
-            .read()
-            .channels
-            .values()
-            .fold(error, |error, channel| channel.init_recovery(error));
-        self.channel0.init_recovery(error)
+        let mut inner = self.write();
+        // The internal channels get reopened on demand once the connection is back
+        let internal_channels = inner.internal_channels.iter().copied().collect::<Vec<_>>();
+        let dropped = internal_channels
+            .into_iter()
+            .filter_map(|id| inner.take_internal_channel(id))
+            .collect::<Vec<_>>();
+        let recovery_error = inner
+            .application_channels()
+            .fold(error.clone(), |error, channel| channel.init_recovery(error));
+        drop(inner);
+        for channel in dropped {
+            self.discard_channel_frames(channel.id(), error.clone());
+        }
+        self.channel0.init_recovery(recovery_error)
     }
 
     pub(crate) async fn start_recovery(&self) -> Result<()> {
-        let channels = self.read().channels.values().cloned().collect::<Vec<_>>();
+        let res = self.recover().await;
+        match res.as_ref() {
+            Ok(()) => {
+                self.connection_status.recovery_succeeded();
+                self.configuration.traffic_stats.reconnected();
+                if let Some(metrics) = self.configuration.metrics.as_deref() {
+                    metrics.connection_recovered();
+                }
+            }
+            Err(err) => self.connection_status.recovery_attempt_failed(err.clone()),
+        }
+        if let Some(listener) = self.configuration.recovery_listener.as_deref() {
+            listener.after_connection_recovery(&res);
+        }
+        res
+    }
+
+    async fn recover(&self) -> Result<()> {
+        let channels = self
+            .read()
+            .application_channels()
+            .cloned()
+            .collect::<Vec<_>>();
 
         self.connection_killswitch.reset();
         self.frames.drop_poison();
         self.channel0.update_recovery();
         self.channel0.finalize_connection();
 
-        Connection::for_reconnect(
+        if let Err(err) = Connection::for_reconnect(
             self.configuration.clone(),
             self.connection_status.clone(),
             self.internal_rpc.clone(),
             self.events.clone(),
+            self.clone(),
         )
         .start(self.channel0())
-        .await?;
+        .await
+        {
+            self.events.sender().recovery_failed(0, err.clone());
+            return Err(err);
+        }
+        self.events.sender().recovery_succeeded(0);
+
+        if let Some(registry) = self.configuration.connection_registry.as_ref() {
+            trace!("Connection recovered, now restoring the connection-wide topology");
+            self.recover_connection_topology(registry, &channels)
+                .await?;
+        }
 
         trace!("Connection recovered, now recovering channels");
 
-        // TODO: use future::join! when stable
-        for channel in channels {
-            channel.start_recovery().await?;
+        let results = future::join_bounded(
+            channels
+                .iter()
+                .map(|channel| channel.start_recovery())
+                .collect(),
+            self.configuration.recovery_parallelism,
+        )
+        .await;
+        for (channel, res) in channels.iter().zip(results) {
+            if let Err(err) = res {
+                // If we lost the connection again, the next recovery will take care of this channel
+                if !self.connection_status.connected() {
+                    return Err(err);
+                }
+                // Otherwise it is gone, but the other channels can go on
+                let _ = self.remove(channel.id(), err);
+            }
         }
 
         Ok(())
     }
 
+    // Restore the connection-wide topology on a dedicated channel, and let the other channels know
+    // about the server-named queues which got a new name.
+    // Each exchange, exchange bindings set and queue is restored on its own so that a failure
+    // only gets reported and doesn't prevent the rest of the topology and the channels from
+    // being recovered. The dedicated channel gets reopened when the server closed it, and never
+    // outlives the restore.
+    async fn recover_connection_topology(
+        &self,
+        registry: &Registry,
+        channels: &[Channel],
+    ) -> Result<()> {
+        let mut recovery_channel = None;
+        let res = self
+            .restore_connection_topology(registry, channels, &mut recovery_channel)
+            .await;
+        if let Some(channel) = recovery_channel {
+            let error = res.clone().err().unwrap_or_else(|| {
+                ErrorKind::InvalidConnectionState(self.connection_status.state()).into()
+            });
+            self.drop_internal_channel(channel.id(), error);
+        }
+        res
+    }
+
+    async fn restore_connection_topology(
+        &self,
+        registry: &Registry,
+        channels: &[Channel],
+        recovery_channel: &mut Option<Channel>,
+    ) -> Result<()> {
+        let mut channel = self.open_recovery_channel(recovery_channel).await?;
+        let mut topology = ChannelDefinition {
+            exchanges: registry.exchanges_topology(),
+            queues: registry.queues_topology(),
+            ..Default::default()
+        };
+        let altered = self
+            .configuration
+            .recovery_listener
+            .as_deref()
+            .map(|listener| channel.filter_topology(listener, &mut topology))
+            .unwrap_or_default();
+        let mut renamed_queues = HashMap::new();
+        let mut failed = false;
+        for piece in split_topology(topology) {
+            if !channel.status().connected() {
+                channel = self.open_recovery_channel(recovery_channel).await?;
+            }
+            match channel.restore_declarations(&piece).await {
+                Ok(renamed) => renamed_queues.extend(renamed),
+                Err(err) if self.connection_status.connected() => {
+                    error!(error=?err, "Failed to restore part of the connection-wide topology");
+                    self.events.sender().topology_recovery_failed(err);
+                    failed = true;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        // Keep the original version of the altered definitions if the new one may not be declared
+        if !failed {
+            channel.forget_altered_topology(altered, &renamed_queues);
+        }
+        for (old_name, new_name) in &renamed_queues {
+            for channel in channels {
+                channel.follow_queue_rename(old_name, new_name);
+            }
+        }
+        if !channel.status().connected() {
+            return Ok(());
+        }
+        channel
+            .close(protocol::constants::REPLY_SUCCESS, "OK".into())
+            .await
+    }
+
+    async fn open_recovery_channel(
+        &self,
+        recovery_channel: &mut Option<Channel>,
+    ) -> Result<Channel> {
+        let channel = self.write().create(
+            self.connection_status.clone(),
+            self.internal_rpc.clone(),
+            self.frames.clone(),
+            self.events.sender(),
+            None,
+            ChannelKind::Recovery,
+        )?;
+        *recovery_channel = Some(channel.clone());
+        channel.channel_open(channel.clone()).await?;
+        Ok(channel)
+    }
+
+    fn drop_internal_channel(&self, id: ChannelId, error: Error) {
+        let dropped = self.write().take_internal_channel(id);
+        if dropped.is_some() {
+            self.discard_channel_frames(id, error);
+        }
+    }
+
+    // Leftover frames would keep the io loop from reconnecting
+    fn discard_channel_frames(&self, id: ChannelId, error: Error) {
+        self.frames.drop_frames_for_channel(id, error.clone());
+        self.frames.clear_expected_replies(id, error);
+    }
+
     pub(crate) fn init_connection_shutdown(
         &self,
         error: Error,
@@ -302,6 +549,42 @@ impl Channels {
     }
 }
 
+// Split a topology in the exchanges, then the bindings between exchanges, then the queues with
+// their bindings, which can each be restored independently in this order
+fn split_topology(topology: ChannelDefinition) -> Vec<ChannelDefinition> {
+    let mut pieces = Vec::new();
+    let mut exchanges_bindings = Vec::new();
+    for mut exchange in topology.exchanges {
+        let bindings = std::mem::take(&mut exchange.bindings);
+        if !bindings.is_empty() {
+            exchanges_bindings.push(ExchangeDefinition {
+                bindings,
+                is_declared: false,
+                ..exchange.clone()
+            });
+        }
+        if exchange.is_declared {
+            pieces.push(ChannelDefinition {
+                exchanges: vec![exchange],
+                ..Default::default()
+            });
+        }
+    }
+    for exchange in exchanges_bindings {
+        pieces.push(ChannelDefinition {
+            exchanges: vec![exchange],
+            ..Default::default()
+        });
+    }
+    for queue in topology.queues {
+        pieces.push(ChannelDefinition {
+            queues: vec![queue],
+            ..Default::default()
+        });
+    }
+    pieces
+}
+
 impl fmt::Debug for Channels {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut debug = f.debug_struct("Channels");
@@ -320,9 +603,12 @@ impl fmt::Debug for Channels {
 
 struct Inner {
     channels: HashMap<ChannelId, Channel>,
+    internal_channels: HashSet<ChannelId>,
     channel_id: IdSequence<ChannelId>,
     configuration: NegotiatedConfig,
     recovery_config: RecoveryConfig,
+    rpc_timeout: Option<Duration>,
+    metrics: Option<Arc<dyn Metrics>>,
     waker: SocketStateHandle,
 }
 
@@ -330,13 +616,18 @@ impl Inner {
     fn new(
         configuration: NegotiatedConfig,
         recovery_config: RecoveryConfig,
+        rpc_timeout: Option<Duration>,
+        metrics: Option<Arc<dyn Metrics>>,
         waker: SocketStateHandle,
     ) -> Self {
         Self {
             channels: HashMap::default(),
+            internal_channels: HashSet::default(),
             channel_id: IdSequence::new(false),
             configuration,
             recovery_config,
+            rpc_timeout,
+            metrics,
             waker,
         }
     }
@@ -349,8 +640,14 @@ impl Inner {
         frames: Frames,
         events_sender: EventsSender,
         connection_closer: Option<Arc<ConnectionCloser>>,
+        record_topology: bool,
+        metrics: Option<Arc<dyn Metrics>>,
     ) -> Channel {
         debug!(%id, "create channel");
+        let mut recovery_config = self.recovery_config.clone();
+        if !record_topology {
+            recovery_config.registry = None;
+        }
         Channel::new(
             id,
             self.configuration.clone(),
@@ -359,18 +656,43 @@ impl Inner {
             internal_rpc,
             frames,
             connection_closer,
-            self.recovery_config,
+            recovery_config,
+            self.rpc_timeout,
+            metrics,
             events_sender,
         )
     }
 
+    // Internal channels get dropped right away rather than through the internal RPC, which would
+    // fail the connection when asked to remove them twice. Closed and failed channels already got
+    // scheduled for removal.
+    fn take_internal_channel(&mut self, id: ChannelId) -> Option<Channel> {
+        let channel = self.channels.get(&id)?;
+        if matches!(
+            channel.status().state(),
+            ChannelState::Closed | ChannelState::Error
+        ) {
+            return None;
+        }
+        channel.set_state(ChannelState::Error);
+        self.internal_channels.remove(&id);
+        self.channels.remove(&id)
+    }
+
+    fn application_channels(&self) -> impl Iterator<Item = &Channel> {
+        self.channels
+            .values()
+            .filter(|channel| !self.internal_channels.contains(&channel.id()))
+    }
+
     fn create(
         &mut self,
         connection_status: ConnectionStatus,
         internal_rpc: InternalRPCHandle,
         frames: Frames,
         events_sender: EventsSender,
-        connection_closer: Arc<ConnectionCloser>,
+        connection_closer: Option<Arc<ConnectionCloser>>,
+        kind: ChannelKind,
     ) -> Result<Channel> {
         debug!("create channel");
         self.channel_id.set_max(self.configuration.channel_max());
@@ -385,13 +707,24 @@ impl Inner {
                 met_first_id = true;
             }
             if !self.channels.contains_key(&id) {
+                let metrics = if kind.is_internal() {
+                    self.internal_channels.insert(id);
+                    None
+                } else {
+                    self.metrics.clone()
+                };
+                if let Some(metrics) = metrics.as_deref() {
+                    metrics.channel_opened(id);
+                }
                 let channel = self.create_channel(
                     id,
                     connection_status,
                     internal_rpc,
                     frames,
                     events_sender,
-                    Some(connection_closer),
+                    connection_closer,
+                    kind.records_topology(),
+                    metrics,
                 );
                 self.channels.insert(id, channel.clone_internal());
                 return Ok(channel);
@@ -1,7 +1,11 @@
 use crate::{
-    ConnectionProperties, Error,
+    ConnectionProperties, Error, RecoveryListener,
     auth::{AuthProvider, DefaultAuthProvider},
+    metrics::Metrics,
     protocol,
+    recorder::FrameRecorder,
+    registry::Registry,
+    stats::TrafficStats,
     types::{ChannelId, FieldTable, FrameSize, Heartbeat, ShortString},
     uri::AMQPUri,
 };
@@ -9,6 +13,7 @@ use backon::ExponentialBuilder;
 use std::{
     fmt,
     sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::Duration,
 };
 
 pub struct Configuration {
@@ -18,6 +23,15 @@ pub struct Configuration {
     pub(crate) backoff: ExponentialBuilder,
     pub(crate) negotiated_config: NegotiatedConfig,
     pub(crate) auto_recover: bool,
+    pub(crate) recovery_listener: Option<Arc<dyn RecoveryListener>>,
+    pub(crate) recovery_parallelism: usize,
+    pub(crate) manual_recovery: bool,
+    pub(crate) connection_registry: Option<Registry>,
+    pub(crate) handshake_timeout: Option<Duration>,
+    pub(crate) rpc_timeout: Option<Duration>,
+    pub(crate) frame_recorder: Option<FrameRecorder>,
+    pub(crate) metrics: Option<Arc<dyn Metrics>>,
+    pub(crate) traffic_stats: TrafficStats,
 }
 
 impl Configuration {
@@ -28,6 +42,14 @@ impl Configuration {
             auth_provider,
             backoff,
             auto_recover,
+            recovery_listener,
+            recovery_parallelism,
+            manual_recovery,
+            connection_topology,
+            handshake_timeout,
+            rpc_timeout,
+            frame_recorder,
+            metrics,
             ..
         } = options;
         Self {
@@ -37,6 +59,15 @@ impl Configuration {
             backoff,
             negotiated_config: NegotiatedConfig::new(uri),
             auto_recover,
+            recovery_listener,
+            recovery_parallelism,
+            manual_recovery,
+            connection_registry: connection_topology.then(Registry::default),
+            handshake_timeout,
+            rpc_timeout,
+            frame_recorder,
+            metrics,
+            traffic_stats: TrafficStats::default(),
         }
     }
 
@@ -53,7 +84,11 @@ impl Configuration {
     }
 
     pub(crate) fn recovery_config(&self) -> RecoveryConfig {
-        RecoveryConfig(self.auto_recover)
+        RecoveryConfig {
+            auto_recover: self.auto_recover,
+            listener: self.recovery_listener.clone(),
+            registry: self.connection_registry.clone(),
+        }
     }
 }
 
@@ -66,6 +101,15 @@ impl Clone for Configuration {
             backoff: self.backoff,
             negotiated_config: self.negotiated_config.clone(),
             auto_recover: self.auto_recover,
+            recovery_listener: self.recovery_listener.clone(),
+            recovery_parallelism: self.recovery_parallelism,
+            manual_recovery: self.manual_recovery,
+            connection_registry: self.connection_registry.clone(),
+            handshake_timeout: self.handshake_timeout,
+            rpc_timeout: self.rpc_timeout,
+            frame_recorder: self.frame_recorder.clone(),
+            metrics: self.metrics.clone(),
+            traffic_stats: self.traffic_stats.clone(),
         }
     }
 }
@@ -81,8 +125,12 @@ pub(crate) struct NegotiatedConfig {
     inner: Arc<RwLock<Inner>>,
 }
 
-#[derive(Default, Clone, Copy)]
-pub(crate) struct RecoveryConfig(pub(crate) bool);
+#[derive(Default, Clone)]
+pub(crate) struct RecoveryConfig {
+    pub(crate) auto_recover: bool,
+    pub(crate) listener: Option<Arc<dyn RecoveryListener>>,
+    pub(crate) registry: Option<Registry>,
+}
 
 struct Inner {
     channel_max: ChannelId,
@@ -148,6 +196,6 @@ impl fmt::Debug for NegotiatedConfig {
 
 impl RecoveryConfig {
     pub(crate) fn can_recover(&self, error: &Error) -> bool {
-        self.0 && error.can_be_recovered()
+        self.auto_recover && error.can_be_recovered()
     }
 }
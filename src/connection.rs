@@ -1,28 +1,39 @@
 use crate::{
-    AsyncTcpStream, ConnectionProperties, ConnectionStatus, Error, Event, Promise, Result,
+    ConnectionProperties, ConnectionState, ConnectionStatus, Error, ErrorKind, Event, ExchangeKind,
+    Promise, Result,
     channel::{Channel, Reply},
-    channels::Channels,
+    channels::{ChannelKind, Channels},
     configuration::Configuration,
     connection_closer::ConnectionCloser,
     connection_step::ConnectionStep,
     events::Events,
     frames::{ExpectedReply, Frames},
+    health::Health,
     heartbeat::Heartbeat,
     internal_rpc::{InternalRPC, InternalRPCHandle},
     io_loop::IoLoop,
+    options::ExchangeDeclareOptions,
     runtime,
     secret_update::SecretUpdate,
+    snapshot::ConnectionSnapshot,
     socket_state::SocketState,
-    tcp::{AMQPUriTcpExt, OwnedTLSConfig},
+    stats::ConnectionStats,
+    tcp::OwnedTLSConfig,
     thread::ThreadHandle,
-    types::{LongString, ReplyCode, ShortString},
+    topology::ConnectionTopology,
+    types::{FieldTable, LongString, ReplyCode, ShortString},
     uri::AMQPUri,
 };
 use amq_protocol::frame::{AMQPFrame, ProtocolVersion};
 use async_rs::{Runtime, traits::*};
 use async_trait::async_trait;
 use futures_core::Stream;
-use std::{fmt, sync::Arc};
+use futures_io::{AsyncRead, AsyncWrite};
+use std::{
+    fmt, io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 use tracing::trace;
 
 /// A TCP connection to the AMQP server.
@@ -41,8 +52,10 @@ pub struct Connection {
     status: ConnectionStatus,
     internal_rpc: InternalRPCHandle,
     events: Events,
+    channels: Channels,
     io_loop: ThreadHandle,
     closer: Arc<ConnectionCloser>,
+    health_probe: Mutex<Option<Channel>>,
 }
 
 impl Connection {
@@ -51,6 +64,7 @@ impl Connection {
         status: ConnectionStatus,
         internal_rpc: InternalRPCHandle,
         events: Events,
+        channels: Channels,
     ) -> Self {
         let closer = Arc::new(ConnectionCloser::new(status.clone(), internal_rpc.clone()));
         Self {
@@ -58,8 +72,10 @@ impl Connection {
             status,
             internal_rpc,
             events,
+            channels,
             io_loop: ThreadHandle::default(),
             closer,
+            health_probe: Mutex::default(),
         }
     }
 
@@ -68,8 +84,9 @@ impl Connection {
         status: ConnectionStatus,
         internal_rpc: InternalRPCHandle,
         events: Events,
+        channels: Channels,
     ) -> Self {
-        let conn = Self::new(configuration, status, internal_rpc, events);
+        let conn = Self::new(configuration, status, internal_rpc, events, channels);
         conn.closer.noop();
         conn
     }
@@ -142,7 +159,170 @@ impl Connection {
     /// [`InvalidConnectionState`]: ./enum.Error.html#variant.InvalidConnectionState
     pub async fn create_channel(&self) -> Result<Channel> {
         self.status.ensure_connected()?;
-        self.internal_rpc.create_channel(self.closer.clone()).await
+        self.internal_rpc
+            .create_channel(self.closer.clone(), ChannelKind::Application)
+            .await
+    }
+
+    /// Run the recovery process on demand: the connection gets reestablished and its channels
+    /// recovered, as if the connection had been lost.
+    ///
+    /// If automatic recovery gave up after exhausting its backoff and
+    /// [`ConnectionProperties::enable_manual_recovery`] is set, this makes it try again.
+    /// If a recovery is already in progress, this waits for it to complete.
+    ///
+    /// This requires automatic recovery to be enabled with
+    /// [`ConnectionProperties::enable_auto_recover`].
+    ///
+    /// [`ConnectionProperties::enable_auto_recover`]: ./struct.ConnectionProperties.html#method.enable_auto_recover
+    /// [`ConnectionProperties::enable_manual_recovery`]: ./struct.ConnectionProperties.html#method.enable_manual_recovery
+    pub async fn recover(&self) -> Result<()> {
+        if !self.configuration.auto_recover {
+            return Err(Error::other("automatic recovery is disabled"));
+        }
+        let recovery = match self.status.state() {
+            ConnectionState::Connected => {
+                let recovery = self.status.wait_for_recovery();
+                self.internal_rpc.set_connection_error(
+                    io::Error::new(io::ErrorKind::ConnectionAborted, "recovery requested").into(),
+                );
+                recovery
+            }
+            ConnectionState::Reconnecting => {
+                let recovery = self.status.wait_for_recovery();
+                if self.status.request_recovery() {
+                    self.internal_rpc.wake();
+                }
+                recovery
+            }
+            state => return Err(ErrorKind::InvalidConnectionState(state).into()),
+        };
+        recovery.await
+    }
+
+    /// Get a snapshot of everything recorded to recover this connection: the connection-wide
+    /// topology if enabled, and the exchanges, queues, bindings, consumers and prefetch settings
+    /// of each channel.
+    ///
+    /// With the `serde` feature, it can be serialized to dump what this connection believes it
+    /// declared.
+    pub fn topology(&self) -> ConnectionTopology {
+        self.channels.topology()
+    }
+
+    /// Get a snapshot of the runtime state of each channel of this connection: its state, its
+    /// consumers, and the operations still pending on it (publisher confirms, unacked
+    /// deliveries, queued frames and replies expected from the server).
+    ///
+    /// With the `serde` feature, it can be serialized to expose it on an admin endpoint.
+    pub fn snapshot(&self) -> ConnectionSnapshot {
+        self.channels.snapshot()
+    }
+
+    /// Get the traffic statistics of this connection: bytes and frames exchanged with the
+    /// server, last socket activity, reconnections and buffers occupancy.
+    pub fn stats(&self) -> ConnectionStats {
+        self.configuration.traffic_stats.snapshot()
+    }
+
+    /// Get the health of this connection from what it already knows, without talking to the
+    /// server: its state, whether the server blocked it, and whether we heard from the server
+    /// during the last `max_idle`.
+    ///
+    /// With heartbeats enabled, the server sends something at least every half heartbeat
+    /// interval, so `max_idle` should be a few times that.
+    pub fn health(&self, max_idle: Duration) -> Health {
+        let since_last_inbound = self.since_last_inbound();
+        match self.status.state() {
+            ConnectionState::Connected if since_last_inbound.is_none_or(|idle| idle > max_idle) => {
+                Health::Unresponsive {
+                    since_last_inbound,
+                    error: None,
+                }
+            }
+            ConnectionState::Connected if self.status.blocked() => Health::Blocked,
+            ConnectionState::Connected => Health::Healthy,
+            _ => self.unavailable_health(),
+        }
+    }
+
+    /// Check the health of this connection by making a round trip to the server: a passive
+    /// declaration of the `amq.direct` exchange on an internal channel, which has to complete
+    /// within `timeout`.
+    ///
+    /// The internal channel is kept open to be reused by the next checks. It doesn't show up in
+    /// the snapshots, the topology nor the metrics, and gets reopened instead of recovered after
+    /// a reconnection.
+    ///
+    /// Nothing gets sent while the server blocks the connection, so no round trip is attempted
+    /// in that case.
+    pub async fn health_check(&self, timeout: Duration) -> Health {
+        if !self.status.connected() {
+            return self.unavailable_health();
+        }
+        if self.status.blocked() {
+            return Health::Blocked;
+        }
+        let res = self
+            .internal_rpc
+            .timeout(Some(timeout), "health check", self.probe_server())
+            .await;
+        match res {
+            Ok(()) => Health::Healthy,
+            Err(error) if self.status.connected() => Health::Unresponsive {
+                since_last_inbound: self.since_last_inbound(),
+                error: Some(error),
+            },
+            Err(_) => self.unavailable_health(),
+        }
+    }
+
+    async fn probe_server(&self) -> Result<()> {
+        let probe = self
+            .health_probe
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        let channel = match probe {
+            Some(channel) if channel.status().connected() => channel,
+            _ => {
+                self.internal_rpc
+                    .create_channel(self.closer.clone(), ChannelKind::Internal)
+                    .await?
+            }
+        };
+        channel
+            .exchange_declare(
+                "amq.direct".into(),
+                ExchangeKind::Direct,
+                ExchangeDeclareOptions {
+                    passive: true,
+                    ..ExchangeDeclareOptions::default()
+                },
+                FieldTable::default(),
+            )
+            .await?;
+        *self.health_probe.lock().unwrap_or_else(|e| e.into_inner()) = Some(channel);
+        Ok(())
+    }
+
+    fn since_last_inbound(&self) -> Option<Duration> {
+        self.stats().last_read.map(|last_read| last_read.elapsed())
+    }
+
+    // Reconnection attempts go through the Connecting state
+    fn unavailable_health(&self) -> Health {
+        let recovery = self.status.recovery_status();
+        match self.status.state() {
+            ConnectionState::Reconnecting | ConnectionState::Connecting
+                if !recovery.exhausted() =>
+            {
+                Health::Recovering {
+                    attempts: recovery.attempts(),
+                }
+            }
+            state => Health::Down { state },
+        }
     }
 
     /// Get a Stream of connection Events
@@ -190,27 +370,33 @@ impl Connection {
         self.internal_rpc.update_secret(new_secret, reason).await
     }
 
-    pub async fn connector<RK: RuntimeKit + Clone + Send + 'static>(
+    /// Connect to an AMQP server using a custom transport.
+    ///
+    /// `connect` is called for the initial connection and for every reconnection attempt and can
+    /// return any bidirectional byte stream.
+    pub async fn connector<
+        RK: RuntimeKit + Clone + Send + 'static,
+        S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    >(
         uri: AMQPUri,
         runtime: Runtime<RK>,
-        connect: impl AsyncFn(
-            AMQPUri,
-            Runtime<RK>,
-        ) -> Result<AsyncTcpStream<<RK as Reactor>::TcpStream>>
-        + Send
-        + Sync
-        + 'static,
+        connect: impl AsyncFn(AMQPUri, Runtime<RK>) -> Result<S> + Send + Sync + 'static,
         options: ConnectionProperties,
     ) -> Result<Self> {
         let configuration = Configuration::new(&uri, options);
         let status = ConnectionStatus::new(&uri);
         let frames = Frames::default();
         let socket_state = SocketState::default();
-        let heartbeat = Heartbeat::new(status.clone(), runtime.clone());
+        let events = Events::new();
+        let heartbeat = Heartbeat::new(status.clone(), runtime.clone(), events.sender());
+        configuration
+            .traffic_stats
+            .set_socket_activity(heartbeat.activity());
         let secret_update = SecretUpdate::new(
             status.clone(),
             runtime.clone(),
             configuration.auth_provider.clone(),
+            events.sender(),
         );
         let internal_rpc = InternalRPC::new(
             runtime.clone(),
@@ -219,7 +405,6 @@ impl Connection {
             frames.clone(),
             socket_state.handle(),
         );
-        let events = Events::new();
         let channels = Channels::new(
             configuration.clone(),
             status.clone(),
@@ -229,7 +414,13 @@ impl Connection {
             events.clone(),
         );
         let channel0 = channels.channel0();
-        let conn = Connection::new(configuration, status, internal_rpc.handle(), events);
+        let conn = Connection::new(
+            configuration,
+            status,
+            internal_rpc.handle(),
+            events,
+            channels.clone(),
+        );
         let io_loop = IoLoop::new(
             conn.status.clone(),
             conn.configuration.negotiated_config.clone(),
@@ -242,6 +433,10 @@ impl Connection {
             connect,
             uri,
             conn.configuration().backoff,
+            conn.configuration().manual_recovery,
+            conn.configuration().frame_recorder.clone(),
+            conn.configuration().metrics.clone(),
+            conn.configuration().traffic_stats.clone(),
         );
 
         internal_rpc.start(channels);
@@ -251,6 +446,8 @@ impl Connection {
 
     pub(crate) async fn start(self, channel0: Channel) -> Result<Self> {
         let (promise, resolver) = Promise::new("ProtocolHeader");
+        let internal_rpc = self.internal_rpc.clone();
+        let handshake_timeout = self.configuration.handshake_timeout;
 
         trace!("Set connection as connecting");
         self.status.clone().set_connecting()?;
@@ -260,14 +457,26 @@ impl Connection {
             AMQPFrame::ProtocolHeader(ProtocolVersion::amqp_0_9_1()),
             Box::new(resolver.clone()),
             Some(ExpectedReply(
-                Reply::ConnectionStep(ConnectionStep::ProtocolHeader(resolver.clone(), self)),
+                Reply::ConnectionStep(ConnectionStep::ProtocolHeader(
+                    resolver.clone(),
+                    Box::new(self),
+                )),
                 Box::new(resolver),
             )),
             None,
         );
 
         trace!("Sent protocol header to server, waiting for connection flow");
-        promise.await
+        let res = internal_rpc
+            .timeout(handshake_timeout, "connection handshake", promise)
+            .await;
+        if let Err(err) = res.as_ref()
+            && let ErrorKind::Timeout(_) = err.kind()
+        {
+            // Drops the pending connection step and tears down the io loop
+            internal_rpc.set_connection_error(err.clone());
+        }
+        res
     }
 }
 
@@ -315,11 +524,13 @@ impl Connect for AMQPUri {
         config: OwnedTLSConfig,
         runtime: Runtime<RK>,
     ) -> Result<Connection> {
+        let socket_options = options.socket_options.clone();
         Connection::connector(
             self,
             runtime,
             async move |uri, runtime| {
-                AMQPUriTcpExt::connect_with_config_async(&uri, config.as_ref(), &runtime)
+                socket_options
+                    .connect(&uri, config.as_ref(), &runtime)
                     .await
                     .map_err(|err| Error::io(err, &runtime))
             },
@@ -337,6 +548,14 @@ impl Connect for &str {
         config: OwnedTLSConfig,
         runtime: Runtime<RK>,
     ) -> Result<Connection> {
+        #[cfg(feature = "websocket")]
+        if crate::websocket::is_websocket_uri(self) {
+            return match self.parse::<crate::websocket::WebSocketUri>() {
+                Ok(uri) => uri.connect_with_config(options, config, runtime).await,
+                Err(err) => Err(Error::other(err)),
+            };
+        }
+
         match self.parse::<AMQPUri>() {
             Ok(uri) => uri.connect_with_config(options, config, runtime).await,
             Err(err) => Err(Error::other(err)),
@@ -350,27 +569,40 @@ mod tests {
     use crate::{
         BasicProperties, ChannelState, ConnectionProperties, ConnectionState, ErrorKind,
         channel_receiver_state::{ChannelReceiverState, DeliveryCause},
-        options::BasicConsumeOptions,
+        options::{BasicConsumeOptions, BasicQosOptions, QueueBindOptions, QueueDeclareOptions},
         secret_update::SecretUpdate,
         types::{ChannelId, FieldTable, ShortString},
     };
     use amq_protocol::{
         frame::AMQPContentHeader,
-        protocol::{AMQPClass, basic},
+        protocol::{AMQPClass, basic, channel, queue, tx},
     };
+    use futures_lite::StreamExt;
+    use std::time::Duration;
 
     fn create_connection() -> (Connection, Channels, InternalRPCHandle) {
+        create_connection_with(ConnectionProperties::default())
+    }
+
+    fn create_connection_with(
+        options: ConnectionProperties,
+    ) -> (Connection, Channels, InternalRPCHandle) {
         let uri = AMQPUri::default();
         let runtime = runtime::default_runtime().unwrap();
-        let configuration = Configuration::new(&uri, ConnectionProperties::default());
+        let configuration = Configuration::new(&uri, options);
         let status = ConnectionStatus::new(&uri);
         let frames = Frames::default();
         let socket_state = SocketState::default();
-        let heartbeat = Heartbeat::new(status.clone(), runtime.clone());
+        let events = Events::new();
+        let heartbeat = Heartbeat::new(status.clone(), runtime.clone(), events.sender());
+        configuration
+            .traffic_stats
+            .set_socket_activity(heartbeat.activity());
         let secret_update = SecretUpdate::new(
             status.clone(),
             runtime.clone(),
             configuration.auth_provider.clone(),
+            events.sender(),
         );
         let internal_rpc = InternalRPC::new(
             runtime,
@@ -379,7 +611,6 @@ mod tests {
             frames.clone(),
             socket_state.handle(),
         );
-        let events = Events::new();
         let channels = Channels::new(
             configuration.clone(),
             status.clone(),
@@ -388,9 +619,17 @@ mod tests {
             frames.clone(),
             events.clone(),
         );
-        let conn = Connection::new(configuration, status, internal_rpc.handle(), events);
+        let conn = Connection::new(
+            configuration,
+            status,
+            internal_rpc.handle(),
+            events,
+            channels.clone(),
+        );
         conn.status.set_state(ConnectionState::Connected);
-        (conn, channels, internal_rpc.handle())
+        let handle = internal_rpc.handle();
+        internal_rpc.start(channels.clone());
+        (conn, channels, handle)
     }
 
     #[test]
@@ -403,11 +642,13 @@ mod tests {
             .negotiated_config
             .set_channel_max(ChannelId::MAX);
         for _ in 1..=ChannelId::MAX {
-            channels.create(conn.closer.clone()).unwrap();
+            channels
+                .create(conn.closer.clone(), ChannelKind::Application)
+                .unwrap();
         }
 
         assert_eq!(
-            channels.create(conn.closer.clone()),
+            channels.create(conn.closer.clone(), ChannelKind::Application),
             Err(ErrorKind::ChannelsLimitReached.into())
         );
     }
@@ -421,7 +662,9 @@ mod tests {
         // Bootstrap connection state to a consuming state
         let (conn, channels, internal_rpc) = create_connection();
         conn.configuration.negotiated_config.set_channel_max(2047);
-        let channel = channels.create(conn.closer.clone()).unwrap();
+        let channel = channels
+            .create(conn.closer.clone(), ChannelKind::Application)
+            .unwrap();
         channel.set_state(ChannelState::Connected);
         let queue_name = ShortString::from("consumed");
         let consumer_tag = ShortString::from("consumer-tag");
@@ -487,7 +730,9 @@ mod tests {
         // Bootstrap connection state to a consuming state
         let (conn, channels, internal_rpc) = create_connection();
         conn.configuration.negotiated_config.set_channel_max(2047);
-        let channel = channels.create(conn.closer.clone()).unwrap();
+        let channel = channels
+            .create(conn.closer.clone(), ChannelKind::Application)
+            .unwrap();
         channel.set_state(ChannelState::Connected);
         let queue_name = ShortString::from("consumed");
         let consumer_tag = ShortString::from("consumer-tag");
@@ -535,4 +780,551 @@ mod tests {
             assert!(channel.status().connected());
         }
     }
+
+    #[test]
+    fn rpc_timeout() {
+        let _ = tracing_subscriber::fmt::try_init();
+
+        let (conn, channels, _) = create_connection();
+        conn.configuration.negotiated_config.set_channel_max(2047);
+        let channel = channels
+            .create(conn.closer.clone(), ChannelKind::Application)
+            .unwrap();
+        channel.set_state(ChannelState::Connected);
+        let declare_ok = |queue: &str| {
+            AMQPFrame::Method(
+                channel.id(),
+                AMQPClass::Queue(queue::AMQPMethod::DeclareOk(queue::DeclareOk {
+                    queue: queue.into(),
+                    message_count: 0,
+                    consumer_count: 0,
+                })),
+            )
+        };
+
+        let runtime = runtime::default_runtime().unwrap();
+        let res = runtime.block_on(
+            channel
+                .with_rpc_timeout(Some(Duration::from_millis(10)))
+                .queue_declare(
+                    "late".into(),
+                    QueueDeclareOptions::default(),
+                    FieldTable::default(),
+                ),
+        );
+        assert_eq!(
+            res.map(|_| ()),
+            Err(ErrorKind::Timeout("queue.declare").into())
+        );
+
+        // The channel gets closed as the late reply could be mistaken for the one of a
+        // subsequent call
+        runtime.block_on(async {
+            while channel.status().connected() {
+                runtime.sleep(Duration::from_millis(1)).await;
+            }
+        });
+        assert_eq!(channel.status().state(), ChannelState::Closing);
+        assert_eq!(channel.snapshot().expected_replies, 2);
+
+        // The late reply is still swallowed by the timed out call
+        channels.handle_frame(declare_ok("late")).unwrap();
+        assert_eq!(channel.snapshot().expected_replies, 1);
+        channels
+            .handle_frame(AMQPFrame::Method(
+                channel.id(),
+                AMQPClass::Channel(channel::AMQPMethod::CloseOk(channel::CloseOk {})),
+            ))
+            .unwrap();
+        assert_eq!(channel.status().state(), ChannelState::Closed);
+        assert_eq!(channel.snapshot().expected_replies, 0);
+    }
+
+    #[test]
+    fn channel_events() {
+        let _ = tracing_subscriber::fmt::try_init();
+
+        let (conn, channels, _) = create_connection();
+        conn.configuration.negotiated_config.set_channel_max(2047);
+        let mut events = conn.events_listener();
+        let channel = channels
+            .create(conn.closer.clone(), ChannelKind::Application)
+            .unwrap();
+        channel.set_state(ChannelState::Connected);
+        let other_channel = channels
+            .create(conn.closer.clone(), ChannelKind::Application)
+            .unwrap();
+        other_channel.set_state(ChannelState::Connected);
+        let mut other_events = other_channel.events_listener();
+
+        channels
+            .handle_frame(AMQPFrame::Method(
+                channel.id(),
+                AMQPClass::Basic(basic::AMQPMethod::Cancel(basic::Cancel {
+                    consumer_tag: "consumer-tag".into(),
+                    nowait: true,
+                })),
+            ))
+            .unwrap();
+        channels
+            .handle_frame(AMQPFrame::Method(
+                channel.id(),
+                AMQPClass::Channel(channel::AMQPMethod::Close(channel::Close {
+                    reply_code: 404,
+                    reply_text: "NOT_FOUND".into(),
+                    class_id: 50,
+                    method_id: 10,
+                })),
+            ))
+            .unwrap();
+
+        let event = futures_lite::future::block_on(events.next()).unwrap();
+        assert!(matches!(
+            event,
+            Event::ConsumerCancelled { channel_id, ref consumer_tag }
+                if channel_id == channel.id() && consumer_tag.as_str() == "consumer-tag"
+        ));
+        let event = futures_lite::future::block_on(events.next()).unwrap();
+        assert_eq!(event.channel_id(), Some(channel.id()));
+        assert!(matches!(
+            event,
+            Event::ChannelClosed {
+                reply_code: 404,
+                ..
+            }
+        ));
+
+        // Only the events of this channel reach its own listener
+        channels
+            .handle_frame(AMQPFrame::Method(
+                other_channel.id(),
+                AMQPClass::Channel(channel::AMQPMethod::Flow(channel::Flow { active: false })),
+            ))
+            .unwrap();
+        let event = futures_lite::future::block_on(other_events.next()).unwrap();
+        assert!(matches!(
+            event,
+            Event::SendFlow { channel_id, active: false } if channel_id == other_channel.id()
+        ));
+        assert!(
+            futures_lite::future::block_on(futures_lite::future::poll_once(other_events.next()))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn basic_qos_topology() {
+        let _ = tracing_subscriber::fmt::try_init();
+
+        let (conn, channels, _) = create_connection();
+        conn.configuration.negotiated_config.set_channel_max(2047);
+        let channel = channels
+            .create(conn.closer.clone(), ChannelKind::Application)
+            .unwrap();
+        channel.set_state(ChannelState::Connected);
+        let qos_ok = AMQPFrame::Method(
+            channel.id(),
+            AMQPClass::Basic(basic::AMQPMethod::QosOk(basic::QosOk {})),
+        );
+
+        for (prefetch_count, global) in [(10, false), (100, true), (20, false)] {
+            let (res, _) = futures_lite::future::block_on(futures_lite::future::zip(
+                channel.basic_qos(prefetch_count, BasicQosOptions { global }),
+                async { channels.handle_frame(qos_ok.clone()).unwrap() },
+            ));
+            res.unwrap();
+        }
+
+        let qos = channel.topology().qos;
+        assert_eq!(qos.global_prefetch_count, Some(100));
+        assert_eq!(qos.consumer_prefetch_count, Some(20));
+    }
+
+    #[test]
+    fn server_named_queue_rename() {
+        let _ = tracing_subscriber::fmt::try_init();
+
+        use crate::consumer::Consumer;
+
+        let (conn, channels, internal_rpc) = create_connection();
+        conn.configuration.negotiated_config.set_channel_max(2047);
+        let channel = channels
+            .create(conn.closer.clone(), ChannelKind::Application)
+            .unwrap();
+        channel.set_state(ChannelState::Connected);
+        let mut events = channel.events_listener();
+        let declare_ok = AMQPFrame::Method(
+            channel.id(),
+            AMQPClass::Queue(queue::AMQPMethod::DeclareOk(queue::DeclareOk {
+                queue: "amq.gen-1".into(),
+                message_count: 0,
+                consumer_count: 0,
+            })),
+        );
+        let (queue, _) = futures_lite::future::block_on(futures_lite::future::zip(
+            channel.queue_declare(
+                "".into(),
+                QueueDeclareOptions::default(),
+                FieldTable::default(),
+            ),
+            async { channels.handle_frame(declare_ok).unwrap() },
+        ));
+        let queue = queue.unwrap();
+        let bind_ok = AMQPFrame::Method(
+            channel.id(),
+            AMQPClass::Queue(queue::AMQPMethod::BindOk(queue::BindOk {})),
+        );
+        let (res, _) = futures_lite::future::block_on(futures_lite::future::zip(
+            channel.queue_bind(
+                queue.name().clone(),
+                "amq.fanout".into(),
+                "".into(),
+                QueueBindOptions::default(),
+                FieldTable::default(),
+            ),
+            async { channels.handle_frame(bind_ok).unwrap() },
+        ));
+        res.unwrap();
+        let consumer = Consumer::new(
+            "consumer-tag".into(),
+            internal_rpc,
+            None,
+            queue.name().clone(),
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        );
+        channel.register_consumer("consumer-tag".into(), consumer.clone());
+        let topology = channel.topology();
+        assert!(topology.queues[0].server_named);
+
+        channel.rename_queue(queue.name(), "amq.gen-2".into());
+
+        assert_eq!(queue.name().as_str(), "amq.gen-1");
+        assert_eq!(queue.current_name().as_str(), "amq.gen-2");
+        assert_eq!(consumer.queue().as_str(), "amq.gen-2");
+        let topology = channel.topology();
+        assert_eq!(topology.queues.len(), 1);
+        assert_eq!(topology.queues[0].name.as_str(), "amq.gen-2");
+        assert!(topology.queues[0].server_named);
+        assert_eq!(topology.queues[0].bindings.len(), 1);
+        let event = futures_lite::future::block_on(events.next()).unwrap();
+        assert!(matches!(
+            event,
+            Event::QueueRenamed { ref old_name, ref new_name, .. }
+                if old_name.as_str() == "amq.gen-1" && new_name.as_str() == "amq.gen-2"
+        ));
+    }
+
+    #[test]
+    fn recovery_listener_filters_topology() {
+        let _ = tracing_subscriber::fmt::try_init();
+
+        use crate::{
+            RecoveryListener,
+            topology::{BindingDefinition, QueueDefinition},
+        };
+
+        struct DropTemporaryQueues;
+
+        impl RecoveryListener for DropTemporaryQueues {
+            fn filter_queue(&self, _channel_id: ChannelId, queue: &mut QueueDefinition) -> bool {
+                !queue.name.as_str().starts_with("temp")
+            }
+
+            fn filter_queue_binding(
+                &self,
+                _channel_id: ChannelId,
+                _queue: &str,
+                binding: &mut BindingDefinition,
+            ) -> bool {
+                binding.routing_key = "new-key".into();
+                true
+            }
+        }
+
+        let (conn, channels, _) = create_connection();
+        conn.configuration.negotiated_config.set_channel_max(2047);
+        let channel = channels
+            .create(conn.closer.clone(), ChannelKind::Application)
+            .unwrap();
+        channel.set_state(ChannelState::Connected);
+        for name in ["temp-queue", "queue"] {
+            let declare_ok = AMQPFrame::Method(
+                channel.id(),
+                AMQPClass::Queue(queue::AMQPMethod::DeclareOk(queue::DeclareOk {
+                    queue: name.into(),
+                    message_count: 0,
+                    consumer_count: 0,
+                })),
+            );
+            let (res, _) = futures_lite::future::block_on(futures_lite::future::zip(
+                channel.queue_declare(
+                    name.into(),
+                    QueueDeclareOptions::default(),
+                    FieldTable::default(),
+                ),
+                async { channels.handle_frame(declare_ok).unwrap() },
+            ));
+            res.unwrap();
+            let bind_ok = AMQPFrame::Method(
+                channel.id(),
+                AMQPClass::Queue(queue::AMQPMethod::BindOk(queue::BindOk {})),
+            );
+            let (res, _) = futures_lite::future::block_on(futures_lite::future::zip(
+                channel.queue_bind(
+                    name.into(),
+                    "amq.direct".into(),
+                    "old-key".into(),
+                    QueueBindOptions::default(),
+                    FieldTable::default(),
+                ),
+                async { channels.handle_frame(bind_ok).unwrap() },
+            ));
+            res.unwrap();
+        }
+
+        let mut topology = channel.topology_definition();
+        let altered = channel.filter_topology(&DropTemporaryQueues, &mut topology);
+
+        assert_eq!(topology.queues.len(), 1);
+        assert_eq!(topology.queues[0].name.as_str(), "queue");
+        assert_eq!(
+            topology.queues[0].bindings[0].routing_key.as_str(),
+            "new-key"
+        );
+        // The skipped queue is forgotten, the original binding is kept until the altered one got
+        // declared
+        let topology = channel.topology();
+        assert_eq!(topology.queues.len(), 1);
+        assert_eq!(
+            topology.queues[0].bindings[0].routing_key.as_str(),
+            "old-key"
+        );
+        channel.forget_altered_topology(altered, &Default::default());
+        assert!(channel.topology().queues[0].bindings.is_empty());
+    }
+
+    #[test]
+    fn recovery_listener_renames_queue() {
+        let _ = tracing_subscriber::fmt::try_init();
+
+        use crate::{RecoveryListener, topology::QueueDefinition};
+
+        struct RenameQueues;
+
+        impl RecoveryListener for RenameQueues {
+            fn filter_queue(&self, _channel_id: ChannelId, queue: &mut QueueDefinition) -> bool {
+                let keep = queue.name.as_str() != "skipped";
+                queue.name = format!("new-{}", queue.name).into();
+                keep
+            }
+        }
+
+        let (conn, channels, _) = create_connection();
+        conn.configuration.negotiated_config.set_channel_max(2047);
+        let channel = channels
+            .create(conn.closer.clone(), ChannelKind::Application)
+            .unwrap();
+        channel.set_state(ChannelState::Connected);
+        for name in ["skipped", "queue"] {
+            let declare_ok = AMQPFrame::Method(
+                channel.id(),
+                AMQPClass::Queue(queue::AMQPMethod::DeclareOk(queue::DeclareOk {
+                    queue: name.into(),
+                    message_count: 0,
+                    consumer_count: 0,
+                })),
+            );
+            let (res, _) = futures_lite::future::block_on(futures_lite::future::zip(
+                channel.queue_declare(
+                    name.into(),
+                    QueueDeclareOptions::default(),
+                    FieldTable::default(),
+                ),
+                async { channels.handle_frame(declare_ok).unwrap() },
+            ));
+            res.unwrap();
+        }
+
+        let mut topology = channel.topology_definition();
+        let altered = channel.filter_topology(&RenameQueues, &mut topology);
+
+        assert_eq!(topology.queues.len(), 1);
+        assert_eq!(topology.queues[0].name.as_str(), "new-queue");
+        // The skipped queue is forgotten by its original name, the renamed one is kept until the
+        // altered one got declared
+        let topology = channel.topology();
+        assert_eq!(topology.queues.len(), 1);
+        assert_eq!(topology.queues[0].name.as_str(), "queue");
+        channel.forget_altered_topology(altered, &Default::default());
+        assert!(channel.topology().queues.is_empty());
+    }
+
+    #[test]
+    fn manual_recovery() {
+        let _ = tracing_subscriber::fmt::try_init();
+
+        let (conn, _, _) =
+            create_connection_with(ConnectionProperties::default().enable_auto_recover());
+        let status = conn.status().clone();
+
+        // Automatic recovery gives up
+        status.set_reconnecting(ErrorKind::Timeout("test").into());
+        status.start_recovery_attempt();
+        let recovery = status.wait_for_recovery();
+        status.recovery_exhausted();
+        assert_eq!(
+            futures_lite::future::block_on(recovery),
+            Err(ErrorKind::Timeout("test").into())
+        );
+        let recovery_status = status.recovery_status();
+        assert_eq!(recovery_status.attempts(), 1);
+        assert!(recovery_status.exhausted());
+
+        // Try again manually
+        let (res, _) =
+            futures_lite::future::block_on(futures_lite::future::zip(conn.recover(), async {
+                assert!(status.take_recovery_request());
+                assert!(!status.recovery_status().exhausted());
+                status.start_recovery_attempt();
+                status.recovery_succeeded();
+            }));
+        res.unwrap();
+        assert_eq!(status.recovery_status().attempts(), 1);
+
+        // Nothing to recover once closed
+        status.set_state(ConnectionState::Closed);
+        let res = futures_lite::future::block_on(conn.recover());
+        assert_eq!(
+            res,
+            Err(ErrorKind::InvalidConnectionState(ConnectionState::Closed).into())
+        );
+    }
+
+    #[test]
+    fn transaction_lost_on_recovery() {
+        let _ = tracing_subscriber::fmt::try_init();
+
+        let (conn, channels, _) = create_connection();
+        conn.configuration.negotiated_config.set_channel_max(2047);
+        let channel = channels
+            .create(conn.closer.clone(), ChannelKind::Application)
+            .unwrap();
+        channel.set_state(ChannelState::Connected);
+        let tx_frame = |method| AMQPFrame::Method(channel.id(), AMQPClass::Tx(method));
+        let (res, _) =
+            futures_lite::future::block_on(futures_lite::future::zip(channel.tx_select(), async {
+                channels
+                    .handle_frame(tx_frame(tx::AMQPMethod::SelectOk(tx::SelectOk {})))
+                    .unwrap()
+            }));
+        res.unwrap();
+        assert!(channel.status().transactional());
+
+        // The channel gets recovered while a transaction is open
+        channel.status().touch_transaction();
+        let _ = channel.status().set_reconnecting(
+            ErrorKind::MissingHeartbeatError.into(),
+            channel.topology_definition(),
+        );
+        channel.status().finalize_connection();
+
+        // Committing rolls back what's left of the transaction
+        let (res, _) =
+            futures_lite::future::block_on(futures_lite::future::zip(channel.tx_commit(), async {
+                channels
+                    .handle_frame(tx_frame(tx::AMQPMethod::RollbackOk(tx::RollbackOk {})))
+                    .unwrap()
+            }));
+        assert_eq!(res, Err(ErrorKind::TransactionRolledBack.into()));
+        assert!(!res.unwrap_err().can_be_recovered());
+
+        // The next transaction gets committed as usual
+        let (res, _) =
+            futures_lite::future::block_on(futures_lite::future::zip(channel.tx_commit(), async {
+                channels
+                    .handle_frame(tx_frame(tx::AMQPMethod::CommitOk(tx::CommitOk {})))
+                    .unwrap()
+            }));
+        res.unwrap();
+    }
+
+    #[test]
+    fn connection_topology() {
+        let _ = tracing_subscriber::fmt::try_init();
+
+        let (conn, channels, _) =
+            create_connection_with(ConnectionProperties::default().enable_connection_topology());
+        conn.configuration.negotiated_config.set_channel_max(2047);
+        let channel = channels
+            .create(conn.closer.clone(), ChannelKind::Application)
+            .unwrap();
+        channel.set_state(ChannelState::Connected);
+        let declare_ok = AMQPFrame::Method(
+            channel.id(),
+            AMQPClass::Queue(queue::AMQPMethod::DeclareOk(queue::DeclareOk {
+                queue: "queue".into(),
+                message_count: 0,
+                consumer_count: 0,
+            })),
+        );
+        let (res, _) = futures_lite::future::block_on(futures_lite::future::zip(
+            channel.queue_declare(
+                "queue".into(),
+                QueueDeclareOptions::default(),
+                FieldTable::default(),
+            ),
+            async { channels.handle_frame(declare_ok).unwrap() },
+        ));
+        res.unwrap();
+        channel.set_state(ChannelState::Closed);
+        drop(channel);
+
+        // The queue outlives the channel which declared it
+        let registry = conn.configuration.connection_registry.as_ref().unwrap();
+        let queues = registry.queues_topology();
+        assert_eq!(queues.len(), 1);
+        assert_eq!(queues[0].name.as_str(), "queue");
+    }
+
+    #[test]
+    fn topology_snapshot() {
+        let _ = tracing_subscriber::fmt::try_init();
+
+        use crate::consumer::Consumer;
+
+        let (conn, channels, internal_rpc) = create_connection();
+        conn.configuration.negotiated_config.set_channel_max(2047);
+        let channel = channels
+            .create(conn.closer.clone(), ChannelKind::Application)
+            .unwrap();
+        channel.set_state(ChannelState::Connected);
+        channel.register_queue("queue".into(), Default::default(), Default::default());
+        channel.register_consumer(
+            "consumer-tag".into(),
+            Consumer::new(
+                "consumer-tag".into(),
+                internal_rpc,
+                None,
+                "queue".into(),
+                BasicConsumeOptions::default(),
+                FieldTable::default(),
+            ),
+        );
+
+        let topology = conn.topology();
+        assert!(topology.queues.is_empty());
+        assert_eq!(topology.channels.len(), 1);
+        let channel_topology = &topology.channels[0];
+        assert_eq!(channel_topology.channel_id, channel.id());
+        assert_eq!(channel_topology.queues[0].name.as_str(), "queue");
+        assert_eq!(channel_topology.consumers[0].tag().as_str(), "consumer-tag");
+        assert_eq!(channel_topology.consumers[0].queue.as_str(), "queue");
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_value(&topology).unwrap();
+            assert_eq!(json["channels"][0]["consumers"][0]["tag"], "consumer-tag");
+        }
+    }
 }
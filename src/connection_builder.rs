@@ -18,6 +18,8 @@ pub type DefaultConnectionBuilder = ConnectionBuilder<runtime::DefaultRuntimeKit
 enum UriBuilder {
     Str(String),
     Uri(AMQPUri),
+    #[cfg(feature = "websocket")]
+    WebSocket(crate::websocket::WebSocketUri),
     #[default]
     Unset,
 }
@@ -48,6 +50,12 @@ impl<RK: RuntimeKit + Send + Sync + Clone + 'static> ConnectionBuilder<RK> {
         self
     }
 
+    #[cfg(feature = "websocket")]
+    pub fn with_websocket_uri(mut self, uri: crate::websocket::WebSocketUri) -> Self {
+        self.uri = UriBuilder::WebSocket(uri);
+        self
+    }
+
     pub fn with_properties(mut self, properties: ConnectionProperties) -> Self {
         self.properties = Some(properties);
         self
@@ -72,6 +80,11 @@ impl<RK: RuntimeKit + Send + Sync + Clone + 'static> ConnectionBuilder<RK> {
                 uri.connect_with_config(properties, tls_config, runtime)
                     .await
             }
+            #[cfg(feature = "websocket")]
+            UriBuilder::WebSocket(uri) => {
+                uri.connect_with_config(properties, tls_config, runtime)
+                    .await
+            }
             UriBuilder::Unset => Err(Error::other("No AMQPUri given to ConnectionBuilder")),
         }
     }
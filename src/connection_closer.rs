@@ -1,4 +1,6 @@
-use crate::{ConnectionStatus, internal_rpc::InternalRPCHandle, protocol};
+use crate::{
+    ConnectionState, ConnectionStatus, ErrorKind, internal_rpc::InternalRPCHandle, protocol,
+};
 use std::sync::atomic::{AtomicBool, Ordering};
 
 #[derive(Debug)]
@@ -35,6 +37,11 @@ impl Drop for ConnectionCloser {
                 0,
                 0,
             );
+        } else if self.status.reconnecting() && self.status.recovery_status().exhausted() {
+            // Let the io loop know it doesn't need to wait for a manual recovery anymore
+            self.internal_rpc.set_connection_closed(
+                ErrorKind::InvalidConnectionState(ConnectionState::Closed).into(),
+            );
         }
     }
 }
@@ -1,10 +1,14 @@
 use crate::{
+    RecoveryListener, SocketOptions,
     auth::AuthProvider,
+    metrics::Metrics,
+    recorder::FrameRecorder,
     types::{AMQPValue, FieldTable, LongString, ShortString},
 };
 use backon::ExponentialBuilder;
 use std::fmt;
 use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Clone)]
 pub struct ConnectionProperties {
@@ -13,6 +17,15 @@ pub struct ConnectionProperties {
     pub(crate) auth_provider: Option<Arc<dyn AuthProvider>>,
     pub(crate) backoff: ExponentialBuilder,
     pub(crate) auto_recover: bool,
+    pub(crate) recovery_listener: Option<Arc<dyn RecoveryListener>>,
+    pub(crate) recovery_parallelism: usize,
+    pub(crate) manual_recovery: bool,
+    pub(crate) connection_topology: bool,
+    pub(crate) socket_options: SocketOptions,
+    pub(crate) handshake_timeout: Option<Duration>,
+    pub(crate) rpc_timeout: Option<Duration>,
+    pub(crate) frame_recorder: Option<FrameRecorder>,
+    pub(crate) metrics: Option<Arc<dyn Metrics>>,
     backoff_configured: bool,
 }
 
@@ -24,6 +37,15 @@ impl Default for ConnectionProperties {
             auth_provider: None,
             backoff: ExponentialBuilder::default().with_max_times(0 /* no retry by default */),
             auto_recover: false,
+            recovery_listener: None,
+            recovery_parallelism: 8,
+            manual_recovery: false,
+            connection_topology: false,
+            socket_options: SocketOptions::default(),
+            handshake_timeout: None,
+            rpc_timeout: None,
+            frame_recorder: None,
+            metrics: None,
             backoff_configured: false,
         }
     }
@@ -82,6 +104,107 @@ impl ConnectionProperties {
         }
         self
     }
+
+    /// Hook into the automatic recovery process, see [`RecoveryListener`].
+    ///
+    /// This has no effect unless automatic recovery is enabled with `enable_auto_recover`.
+    ///
+    /// [`RecoveryListener`]: ./trait.RecoveryListener.html
+    #[must_use]
+    pub fn with_recovery_listener<RL: RecoveryListener>(mut self, listener: RL) -> Self {
+        self.recovery_listener = Some(Arc::new(listener));
+        self
+    }
+
+    /// How many channels can be recovered concurrently during connection recovery (default: 8).
+    ///
+    /// A channel failing to recover is put in error on its own, without preventing the other
+    /// ones from being recovered.
+    #[must_use]
+    pub fn with_recovery_parallelism(mut self, parallelism: usize) -> Self {
+        self.recovery_parallelism = parallelism;
+        self
+    }
+
+    /// Once automatic recovery exhausted its backoff, wait for [`Connection::recover`] to be
+    /// called instead of failing the connection.
+    ///
+    /// Until then, or until the connection gets dropped, the connection and its channels stay in
+    /// the `Reconnecting` state: [`Connection::run`] doesn't return and
+    /// [`Channel::wait_for_recovery`] keeps waiting.
+    ///
+    /// This has no effect unless automatic recovery is enabled with `enable_auto_recover`.
+    ///
+    /// [`Connection::recover`]: ./struct.Connection.html#method.recover
+    /// [`Connection::run`]: ./struct.Connection.html#method.run
+    /// [`Channel::wait_for_recovery`]: ./struct.Channel.html#method.wait_for_recovery
+    #[must_use]
+    pub fn enable_manual_recovery(mut self) -> Self {
+        self.manual_recovery = true;
+        self
+    }
+
+    /// Record the exchanges, queues and bindings declared on any channel at the connection level.
+    ///
+    /// During connection recovery, they are then restored on a dedicated channel before the
+    /// channels get recovered, even if the channel which declared them has been closed since.
+    /// The channels then only restore their own state (publisher confirms, prefetch settings
+    /// and consumers).
+    #[must_use]
+    pub fn enable_connection_topology(mut self) -> Self {
+        self.connection_topology = true;
+        self
+    }
+
+    #[must_use]
+    pub fn with_socket_options(mut self, socket_options: SocketOptions) -> Self {
+        self.socket_options = socket_options;
+        self
+    }
+
+    /// Fail the connection with [`ErrorKind::Timeout`] if the AMQP handshake (from the protocol
+    /// header to `connection.open-ok`) takes longer than `timeout`.
+    ///
+    /// [`ErrorKind::Timeout`]: ./enum.ErrorKind.html#variant.Timeout
+    #[must_use]
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = Some(timeout);
+        self
+    }
+
+    /// Default timeout for synchronous methods (the ones waiting for a reply from the server,
+    /// such as `queue.declare`), after which they fail with [`ErrorKind::Timeout`].
+    ///
+    /// The channel the method was called on then gets closed. A timeout on the connection
+    /// itself (channel 0) or on `channel.close` fails the whole connection.
+    ///
+    /// Can be overridden per channel with [`Channel::with_rpc_timeout`].
+    ///
+    /// [`ErrorKind::Timeout`]: ./enum.ErrorKind.html#variant.Timeout
+    /// [`Channel::with_rpc_timeout`]: ./struct.Channel.html#method.with_rpc_timeout
+    #[must_use]
+    pub fn with_rpc_timeout(mut self, timeout: Duration) -> Self {
+        self.rpc_timeout = Some(timeout);
+        self
+    }
+
+    /// Record every frame received from or sent to the server, see [`FrameRecorder`].
+    ///
+    /// [`FrameRecorder`]: ./recorder/struct.FrameRecorder.html
+    #[must_use]
+    pub fn with_frame_recorder(mut self, recorder: FrameRecorder) -> Self {
+        self.frame_recorder = Some(recorder);
+        self
+    }
+
+    /// Collect metrics about the connection, see [`Metrics`].
+    ///
+    /// [`Metrics`]: ./metrics/trait.Metrics.html
+    #[must_use]
+    pub fn with_metrics<M: Metrics>(mut self, metrics: M) -> Self {
+        self.metrics = Some(Arc::new(metrics));
+        self
+    }
 }
 
 impl fmt::Debug for ConnectionProperties {
@@ -91,6 +214,13 @@ impl fmt::Debug for ConnectionProperties {
             .field("client_properties", &self.client_properties)
             .field("backoff", &self.backoff)
             .field("auto_recover", &self.auto_recover)
+            .field("recovery_parallelism", &self.recovery_parallelism)
+            .field("manual_recovery", &self.manual_recovery)
+            .field("connection_topology", &self.connection_topology)
+            .field("socket_options", &self.socket_options)
+            .field("handshake_timeout", &self.handshake_timeout)
+            .field("rpc_timeout", &self.rpc_timeout)
+            .field("frame_recorder", &self.frame_recorder)
             .finish()
     }
 }
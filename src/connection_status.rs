@@ -1,7 +1,8 @@
-use crate::{Error, ErrorKind, Result, types::ShortString, uri::AMQPUri};
+use crate::{Error, ErrorKind, Promise, PromiseResolver, Result, types::ShortString, uri::AMQPUri};
 use std::{
     fmt,
     sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::{Duration, Instant},
 };
 
 #[derive(Clone, Default)]
@@ -28,8 +29,81 @@ impl ConnectionStatus {
         self.write().set_connecting()
     }
 
-    pub(crate) fn set_reconnecting(&self) {
-        self.write().set_reconnecting();
+    pub(crate) fn set_reconnecting(&self, error: Error) {
+        self.write().set_reconnecting(error);
+    }
+
+    /// The progress of the current or last connection recovery
+    pub fn recovery_status(&self) -> RecoveryStatus {
+        self.read().recovery.clone()
+    }
+
+    pub(crate) fn schedule_recovery_attempt(&self, delay: Duration) {
+        self.write().recovery.next_retry = Some(Instant::now() + delay);
+    }
+
+    pub(crate) fn start_recovery_attempt(&self) {
+        let mut inner = self.write();
+        inner.recovery.attempts += 1;
+        inner.recovery.next_retry = None;
+    }
+
+    pub(crate) fn recovery_attempt_failed(&self, error: Error) {
+        self.write().recovery.last_error = Some(error);
+    }
+
+    pub(crate) fn recovery_succeeded(&self) {
+        let mut inner = self.write();
+        inner.recovery.next_retry = None;
+        inner.recovery.exhausted = false;
+        for resolver in inner.recovery_waiters.drain(..) {
+            resolver.resolve(());
+        }
+    }
+
+    // Automatic recovery gave up, until we get asked to try again
+    pub(crate) fn recovery_exhausted(&self) {
+        let mut inner = self.write();
+        inner.recovery.next_retry = None;
+        inner.recovery.exhausted = true;
+        let error = inner
+            .recovery
+            .last_error
+            .clone()
+            .unwrap_or_else(|| ErrorKind::InvalidConnectionState(inner.state).into());
+        for resolver in inner.recovery_waiters.drain(..) {
+            resolver.reject(error.clone());
+        }
+    }
+
+    pub(crate) fn abort_recovery(&self, error: Error) {
+        for resolver in self.write().recovery_waiters.drain(..) {
+            resolver.reject(error.clone());
+        }
+    }
+
+    pub(crate) fn wait_for_recovery(&self) -> Promise<()> {
+        let (promise, resolver) = Promise::new("connection recovery");
+        self.write().recovery_waiters.push(resolver);
+        promise
+    }
+
+    // Ask the io loop to try again if automatic recovery gave up
+    pub(crate) fn request_recovery(&self) -> bool {
+        let mut inner = self.write();
+        if !inner.recovery.exhausted {
+            return false;
+        }
+        inner.recovery = RecoveryStatus {
+            last_error: inner.recovery.last_error.take(),
+            ..RecoveryStatus::default()
+        };
+        inner.recovery_requested = true;
+        true
+    }
+
+    pub(crate) fn take_recovery_request(&self) -> bool {
+        std::mem::take(&mut self.write().recovery_requested)
     }
 
     pub fn vhost(&self) -> ShortString {
@@ -109,6 +183,7 @@ impl ConnectionStatus {
 }
 
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ConnectionState {
     #[default]
     Initial,
@@ -120,6 +195,47 @@ pub enum ConnectionState {
     Error,
 }
 
+/// The progress of the recovery of a connection.
+///
+/// The progress of each channel can be read from its own [`ChannelStatus`].
+///
+/// [`ChannelStatus`]: ./struct.ChannelStatus.html#method.recovery_state
+#[derive(Clone, Debug, Default)]
+pub struct RecoveryStatus {
+    attempts: usize,
+    last_error: Option<Error>,
+    next_retry: Option<Instant>,
+    exhausted: bool,
+}
+
+impl RecoveryStatus {
+    /// The number of reconnection attempts since the connection got lost
+    pub fn attempts(&self) -> usize {
+        self.attempts
+    }
+
+    /// The error which made us lose the connection, or made the last reconnection attempt fail
+    pub fn last_error(&self) -> Option<&Error> {
+        self.last_error.as_ref()
+    }
+
+    /// When the next reconnection attempt is scheduled
+    pub fn next_retry(&self) -> Option<Instant> {
+        self.next_retry
+    }
+
+    /// Whether automatic recovery gave up after exhausting its backoff.
+    ///
+    /// The connection then fails, unless [`ConnectionProperties::enable_manual_recovery`] is set,
+    /// in which case [`Connection::recover`] can be used to try again.
+    ///
+    /// [`ConnectionProperties::enable_manual_recovery`]: ./struct.ConnectionProperties.html#method.enable_manual_recovery
+    /// [`Connection::recover`]: ./struct.Connection.html#method.recover
+    pub fn exhausted(&self) -> bool {
+        self.exhausted
+    }
+}
+
 impl fmt::Debug for ConnectionStatus {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut debug = f.debug_struct("ConnectionStatus");
@@ -128,7 +244,8 @@ impl fmt::Debug for ConnectionStatus {
                 .field("state", &inner.state)
                 .field("vhost", &inner.vhost)
                 .field("username", &inner.username)
-                .field("blocked", &inner.blocked);
+                .field("blocked", &inner.blocked)
+                .field("recovery", &inner.recovery);
         }
         debug.finish()
     }
@@ -140,6 +257,9 @@ struct Inner {
     username: String,
     blocked: bool,
     poison: Option<Error>,
+    recovery: RecoveryStatus,
+    recovery_waiters: Vec<PromiseResolver<()>>,
+    recovery_requested: bool,
 }
 
 impl Default for Inner {
@@ -150,6 +270,9 @@ impl Default for Inner {
             username: "guest".into(),
             blocked: false,
             poison: None,
+            recovery: RecoveryStatus::default(),
+            recovery_waiters: Vec::new(),
+            recovery_requested: false,
         }
     }
 }
@@ -160,10 +283,14 @@ impl Inner {
         self.poison.take().map(Err).unwrap_or(Ok(()))
     }
 
-    fn set_reconnecting(&mut self) {
+    fn set_reconnecting(&mut self, error: Error) {
         let _ = self.poison.take();
         self.state = ConnectionState::Reconnecting;
         self.blocked = false;
+        self.recovery = RecoveryStatus {
+            last_error: Some(error),
+            ..RecoveryStatus::default()
+        };
     }
 
     fn poison(&mut self, err: Error) {
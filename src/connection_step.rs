@@ -2,15 +2,15 @@ use crate::{Connection, PromiseResolver, auth::AuthProvider};
 use std::{fmt, sync::Arc};
 
 pub(crate) enum ConnectionStep {
-    ProtocolHeader(PromiseResolver<Connection>, Connection),
+    ProtocolHeader(PromiseResolver<Connection>, Box<Connection>),
     StartOk(
         PromiseResolver<Connection>,
-        Connection,
+        Box<Connection>,
         Arc<dyn AuthProvider>,
     ),
     SecureOk(
         PromiseResolver<Connection>,
-        Connection,
+        Box<Connection>,
         Arc<dyn AuthProvider>,
     ),
 }
@@ -29,10 +29,10 @@ impl ConnectionStep {
     ) -> (PromiseResolver<Connection>, Option<Connection>) {
         match self {
             ConnectionStep::ProtocolHeader(resolver, connection, ..) => {
-                (resolver, Some(connection))
+                (resolver, Some(*connection))
             }
-            ConnectionStep::StartOk(resolver, connection, ..) => (resolver, Some(connection)),
-            ConnectionStep::SecureOk(resolver, connection, ..) => (resolver, Some(connection)),
+            ConnectionStep::StartOk(resolver, connection, ..) => (resolver, Some(*connection)),
+            ConnectionStep::SecureOk(resolver, connection, ..) => (resolver, Some(*connection)),
         }
     }
 }
@@ -7,6 +7,8 @@ use crate::{
     internal_rpc::InternalRPCHandle,
     message::{Delivery, DeliveryResult},
     options::BasicConsumeOptions,
+    queue::QueueName,
+    snapshot::ConsumerSnapshot,
     types::{ChannelId, PayloadSize},
     types::{FieldTable, ShortString},
     wakers::Wakers,
@@ -144,7 +146,7 @@ pub struct Consumer {
     internal_rpc: InternalRPCHandle,
     channel_closer: Option<Arc<ChannelCloser>>,
     consumer_canceler: Option<Arc<ConsumerCanceler>>,
-    queue: ShortString,
+    queue: QueueName,
     options: BasicConsumeOptions,
     arguments: FieldTable,
     deliveries_in: Sender<DeliveryResult>,
@@ -174,7 +176,7 @@ impl Consumer {
             internal_rpc,
             channel_closer,
             consumer_canceler: None,
-            queue,
+            queue: QueueName::new(queue),
             options,
             arguments,
             deliveries_in: sender,
@@ -207,8 +209,17 @@ impl Consumer {
     }
 
     /// Get the name of the queue we're consuming
+    ///
+    /// This follows the new name given by the server to a server-named queue when it gets
+    /// redeclared during connection recovery.
     pub fn queue(&self) -> ShortString {
-        self.queue.clone()
+        self.queue.get()
+    }
+
+    pub(crate) fn rename_queue(&self, old_name: &str, new_name: &ShortString) {
+        if self.queue.get().as_str() == old_name {
+            self.queue.set(new_name.clone());
+        }
     }
 
     pub(crate) fn options(&self) -> BasicConsumeOptions {
@@ -219,6 +230,15 @@ impl Consumer {
         self.arguments.clone()
     }
 
+    pub(crate) fn snapshot(&self) -> ConsumerSnapshot {
+        ConsumerSnapshot {
+            tag: self.tag(),
+            queue: self.queue(),
+            no_ack: self.options.no_ack,
+            buffered_deliveries: self.lock_inner().deliveries_out.len(),
+        }
+    }
+
     /// Automatically spawns the delegate on the executor for each message.
     ///
     /// Enables parallel handling of the messages.
@@ -381,7 +401,7 @@ impl Inner {
         properties: BasicProperties,
     ) -> Option<Delivery> {
         if let Some(delivery) = self.current_message.as_mut() {
-            delivery.properties = properties;
+            delivery.receive_properties(properties);
         }
         self.check_new_delivery_complete(size == 0)
     }
@@ -459,7 +479,7 @@ mod test {
     use super::*;
 
     use crate::{
-        ConnectionStatus, ErrorKind, auth::DefaultAuthProvider, frames::Frames,
+        ConnectionStatus, ErrorKind, auth::DefaultAuthProvider, events::Events, frames::Frames,
         heartbeat::Heartbeat, internal_rpc::InternalRPC, runtime, secret_update::SecretUpdate,
         socket_state::SocketState, uri::AMQPUri,
     };
@@ -486,9 +506,15 @@ mod test {
         let uri = AMQPUri::default();
         let status = ConnectionStatus::new(&uri);
         let runtime = runtime::default_runtime().unwrap();
-        let heartbeat = Heartbeat::new(status.clone(), runtime.clone());
+        let events = Events::new();
+        let heartbeat = Heartbeat::new(status.clone(), runtime.clone(), events.sender());
         let auth_provider = Arc::new(DefaultAuthProvider::new(&uri));
-        let secret_update = SecretUpdate::new(status.clone(), runtime.clone(), auth_provider);
+        let secret_update = SecretUpdate::new(
+            status.clone(),
+            runtime.clone(),
+            auth_provider,
+            events.sender(),
+        );
         let socket_state = SocketState::default();
         let internal_rpc = InternalRPC::new(
             runtime,
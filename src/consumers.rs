@@ -3,6 +3,7 @@ use crate::{
     consumer::Consumer,
     error_holder::ErrorHolder,
     message::Delivery,
+    snapshot::ConsumerSnapshot,
     types::{PayloadSize, ShortString},
 };
 use std::{
@@ -32,6 +33,12 @@ impl Consumers {
         }
     }
 
+    pub(crate) fn rename_queue(&self, old_name: &str, new_name: &ShortString) {
+        for consumer in self.read().values() {
+            consumer.rename_queue(old_name, new_name);
+        }
+    }
+
     pub(crate) fn start_cancel_one<S: Hash + Eq + ?Sized>(&self, consumer_tag: &S)
     where
         ShortString: Borrow<S>,
@@ -41,6 +48,15 @@ impl Consumers {
         }
     }
 
+    pub(crate) fn requires_ack<S: Hash + Eq + ?Sized>(&self, consumer_tag: &S) -> bool
+    where
+        ShortString: Borrow<S>,
+    {
+        self.read()
+            .get(consumer_tag)
+            .is_some_and(|consumer| !consumer.options().no_ack)
+    }
+
     pub(crate) fn start_delivery<S: Hash + Eq + ?Sized, F: FnOnce(ErrorHolder) -> Delivery>(
         &self,
         consumer_tag: &S,
@@ -111,6 +127,16 @@ impl Consumers {
         self.read().values().cloned().collect()
     }
 
+    pub(crate) fn snapshot(&self) -> Vec<ConsumerSnapshot> {
+        let mut consumers = self
+            .read()
+            .values()
+            .map(Consumer::snapshot)
+            .collect::<Vec<_>>();
+        consumers.sort_by(|a, b| a.tag.as_str().cmp(b.tag.as_str()));
+        consumers
+    }
+
     fn read(&self) -> RwLockReadGuard<'_, Inner> {
         self.0.read().unwrap_or_else(|e| e.into_inner())
     }
@@ -40,6 +40,8 @@ pub enum ErrorKind {
     AuthProviderError(String),
 
     MissingHeartbeatError,
+    Timeout(&'static str),
+    TransactionRolledBack,
 }
 
 impl Error {
@@ -140,6 +142,8 @@ impl Error {
             ErrorKind::AuthProviderError(_) => false,
 
             ErrorKind::MissingHeartbeatError => true,
+            ErrorKind::Timeout(_) => false,
+            ErrorKind::TransactionRolledBack => false,
         }
     }
 }
@@ -173,6 +177,11 @@ impl fmt::Display for Error {
             ErrorKind::MissingHeartbeatError => {
                 write!(f, "no heartbeat received from server for too long")
             }
+            ErrorKind::Timeout(operation) => write!(f, "timed out waiting for {operation}"),
+            ErrorKind::TransactionRolledBack => write!(
+                f,
+                "the transaction got rolled back by the server while recovering the channel"
+            ),
         }
     }
 }
@@ -240,6 +249,9 @@ impl PartialEq for Error {
                 false
             }
 
+            (Timeout(left_inner), Timeout(right_inner)) => left_inner == right_inner,
+            (TransactionRolledBack, TransactionRolledBack) => true,
+
             _ => false,
         }
     }
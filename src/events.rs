@@ -1,4 +1,7 @@
-use crate::Error;
+use crate::{
+    Error,
+    types::{ChannelId, ReplyCode, ShortString},
+};
 use flume::{Receiver, Sender};
 use futures_core::Stream;
 use std::sync::Arc;
@@ -20,7 +23,10 @@ impl Events {
     }
 
     pub(crate) fn sender(&self) -> EventsSender {
-        EventsSender(self.0.sender.clone())
+        EventsSender {
+            sender: self.0.sender.clone(),
+            channel_sender: None,
+        }
     }
 
     pub(crate) fn listener(&self) -> impl Stream<Item = Event> + Send + 'static {
@@ -29,15 +35,33 @@ impl Events {
 }
 
 #[derive(Clone, Debug)]
-pub(crate) struct EventsSender(Sender<Event>);
+pub(crate) struct EventsSender {
+    sender: Sender<Event>,
+    // Also forward the events to the listeners of a single channel
+    channel_sender: Option<Sender<Event>>,
+}
 
 impl EventsSender {
+    pub(crate) fn with_channel_events(&self, channel_events: &Events) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            channel_sender: Some(channel_events.0.sender.clone()),
+        }
+    }
+
     fn send(&self, event: Event) {
+        if let Some(channel_sender) = self.channel_sender.as_ref() {
+            Self::send_to(channel_sender, event.clone());
+        }
+        Self::send_to(&self.sender, event);
+    }
+
+    fn send_to(sender: &Sender<Event>, event: Event) {
         // Do nothing if we don't have at least one external receiver
-        if self.0.receiver_count() > 1 {
+        if sender.receiver_count() > 1 {
             // The only possibility of error is if we have several external receivers and the
             // connection was already dropped, so we can safely ignore this.
-            let _ = self.0.send(event);
+            let _ = sender.send(event);
         }
     }
 
@@ -53,22 +77,196 @@ impl EventsSender {
         self.send(Event::ConnectionUnblocked);
     }
 
-    pub(crate) fn send_flow(&self, active: bool) {
-        self.send(Event::SendFlow(active));
+    pub(crate) fn send_flow(&self, channel_id: ChannelId, active: bool) {
+        self.send(Event::SendFlow { channel_id, active });
     }
 
     pub(crate) fn error(&self, error: Error) {
         self.send(Event::Error(error));
     }
+
+    pub(crate) fn recovery_started(&self, channel_id: ChannelId) {
+        self.send(Event::RecoveryStarted { channel_id });
+    }
+
+    pub(crate) fn recovery_succeeded(&self, channel_id: ChannelId) {
+        self.send(Event::RecoverySucceeded { channel_id });
+    }
+
+    pub(crate) fn recovery_failed(&self, channel_id: ChannelId, error: Error) {
+        self.send(Event::RecoveryFailed { channel_id, error });
+    }
+
+    pub(crate) fn topology_recovery_failed(&self, error: Error) {
+        self.send(Event::TopologyRecoveryFailed { error });
+    }
+
+    pub(crate) fn channel_opened(&self, channel_id: ChannelId) {
+        self.send(Event::ChannelOpened { channel_id });
+    }
+
+    pub(crate) fn channel_closed(
+        &self,
+        channel_id: ChannelId,
+        reply_code: ReplyCode,
+        reply_text: ShortString,
+    ) {
+        self.send(Event::ChannelClosed {
+            channel_id,
+            reply_code,
+            reply_text,
+        });
+    }
+
+    pub(crate) fn consumer_cancelled(&self, channel_id: ChannelId, consumer_tag: ShortString) {
+        self.send(Event::ConsumerCancelled {
+            channel_id,
+            consumer_tag,
+        });
+    }
+
+    pub(crate) fn queue_renamed(
+        &self,
+        channel_id: ChannelId,
+        old_name: ShortString,
+        new_name: ShortString,
+    ) {
+        self.send(Event::QueueRenamed {
+            channel_id,
+            old_name,
+            new_name,
+        });
+    }
+
+    pub(crate) fn heartbeat_missed(&self) {
+        self.send(Event::HeartbeatMissed { channel_id: 0 });
+    }
+
+    pub(crate) fn secret_refreshed(&self) {
+        self.send(Event::SecretRefreshed { channel_id: 0 });
+    }
+
+    pub(crate) fn secret_refresh_failed(&self, error: Error) {
+        self.send(Event::SecretRefreshFailed {
+            channel_id: 0,
+            error,
+        });
+    }
+
+    pub(crate) fn basic_return(
+        &self,
+        channel_id: ChannelId,
+        reply_code: ReplyCode,
+        reply_text: ShortString,
+        exchange: ShortString,
+        routing_key: ShortString,
+    ) {
+        self.send(Event::BasicReturn {
+            channel_id,
+            reply_code,
+            reply_text,
+            exchange,
+            routing_key,
+        });
+    }
 }
 
 /// An event happening on the connection
+///
+/// Connection level events are reported on channel 0.
 #[derive(Clone, Debug)]
 #[non_exhaustive]
 pub enum Event {
     Connected,
     ConnectionBlocked(String),
     ConnectionUnblocked,
-    SendFlow(bool),
+    /// The server asked us to stop (or resume) publishing on a channel
+    SendFlow {
+        channel_id: ChannelId,
+        active: bool,
+    },
     Error(Error),
+    /// The connection or a channel lost its link to the server and is being recovered
+    RecoveryStarted {
+        channel_id: ChannelId,
+    },
+    RecoverySucceeded {
+        channel_id: ChannelId,
+    },
+    RecoveryFailed {
+        channel_id: ChannelId,
+        error: Error,
+    },
+    /// Part of the connection-wide topology couldn't be restored during recovery, the rest of
+    /// the recovery went on
+    TopologyRecoveryFailed {
+        error: Error,
+    },
+    ChannelOpened {
+        channel_id: ChannelId,
+    },
+    /// The channel was closed, either by us or by the server
+    ChannelClosed {
+        channel_id: ChannelId,
+        reply_code: ReplyCode,
+        reply_text: ShortString,
+    },
+    /// The server cancelled a consumer (e.g. because its queue was deleted)
+    ConsumerCancelled {
+        channel_id: ChannelId,
+        consumer_tag: ShortString,
+    },
+    /// A server-named queue got a new name from the server when redeclared during recovery
+    QueueRenamed {
+        channel_id: ChannelId,
+        old_name: ShortString,
+        new_name: ShortString,
+    },
+    /// Nothing was received from the server for too long
+    HeartbeatMissed {
+        channel_id: ChannelId,
+    },
+    /// The secret was refreshed through the `AuthProvider`
+    SecretRefreshed {
+        channel_id: ChannelId,
+    },
+    SecretRefreshFailed {
+        channel_id: ChannelId,
+        error: Error,
+    },
+    /// A published message was returned by the server as unroutable
+    BasicReturn {
+        channel_id: ChannelId,
+        reply_code: ReplyCode,
+        reply_text: ShortString,
+        exchange: ShortString,
+        routing_key: ShortString,
+    },
+}
+
+impl Event {
+    /// The channel this event relates to, 0 for connection level events.
+    ///
+    /// `Error` doesn't carry this information.
+    pub fn channel_id(&self) -> Option<ChannelId> {
+        match self {
+            Event::Connected
+            | Event::ConnectionBlocked(_)
+            | Event::ConnectionUnblocked
+            | Event::TopologyRecoveryFailed { .. } => Some(0),
+            Event::Error(_) => None,
+            Event::SendFlow { channel_id, .. }
+            | Event::RecoveryStarted { channel_id }
+            | Event::RecoverySucceeded { channel_id }
+            | Event::RecoveryFailed { channel_id, .. }
+            | Event::ChannelOpened { channel_id }
+            | Event::ChannelClosed { channel_id, .. }
+            | Event::ConsumerCancelled { channel_id, .. }
+            | Event::QueueRenamed { channel_id, .. }
+            | Event::HeartbeatMissed { channel_id }
+            | Event::SecretRefreshed { channel_id }
+            | Event::SecretRefreshFailed { channel_id, .. }
+            | Event::BasicReturn { channel_id, .. } => Some(*channel_id),
+        }
+    }
 }
@@ -116,6 +116,17 @@ impl Frames {
             .next_expected_close_ok_reply(channel_id, error)
     }
 
+    pub(crate) fn queued_frames(&self, channel_id: ChannelId) -> usize {
+        self.lock_inner().queued_frames(channel_id)
+    }
+
+    pub(crate) fn expected_replies(&self, channel_id: ChannelId) -> usize {
+        self.lock_inner()
+            .expected_replies
+            .get(&channel_id)
+            .map_or(0, VecDeque::len)
+    }
+
     pub(crate) fn has_pending(&self) -> bool {
         self.lock_inner().has_pending()
     }
@@ -332,6 +343,16 @@ impl Inner {
             .cloned()
     }
 
+    fn queued_frames(&self, channel_id: ChannelId) -> usize {
+        self.retry_frames
+            .iter()
+            .chain(&self.publish_frames)
+            .chain(&self.frames)
+            .chain(&self.low_prio_frames)
+            .filter(|frame| frame.channel_id() == channel_id)
+            .count()
+    }
+
     fn pop(&mut self, flow: bool) -> Option<FrameEntry> {
         if let Some(frame) = self
             .retry_frames
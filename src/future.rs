@@ -1,7 +1,7 @@
 use crate::Result;
 use std::{
     fmt,
-    future::Future,
+    future::{Future, poll_fn},
     pin::Pin,
     task::{Context, Poll},
 };
@@ -23,3 +23,65 @@ impl fmt::Debug for InternalFuture {
         f.debug_tuple("InternalFuture").finish()
     }
 }
+
+/// Run all the futures concurrently, with at most `limit` of them being polled at the same time,
+/// and collect their outputs in the same order
+pub(crate) async fn join_bounded<F: Future>(futures: Vec<F>, limit: usize) -> Vec<F::Output> {
+    let mut outputs = futures.iter().map(|_| None).collect::<Vec<_>>();
+    let mut pending = futures.into_iter().map(Box::pin).enumerate();
+    let mut running = Vec::new();
+    poll_fn(|cx| {
+        loop {
+            while running.len() < limit.max(1) {
+                match pending.next() {
+                    Some(future) => running.push(future),
+                    None => break,
+                }
+            }
+            if running.is_empty() {
+                return Poll::Ready(());
+            }
+            let before = running.len();
+            running.retain_mut(|(idx, future)| match future.as_mut().poll(cx) {
+                Poll::Ready(output) => {
+                    outputs[*idx] = Some(output);
+                    false
+                }
+                Poll::Pending => true,
+            });
+            if running.len() == before {
+                return Poll::Pending;
+            }
+        }
+    })
+    .await;
+    outputs.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn join_bounded_limits_concurrency() {
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+        let futures = (0..10)
+            .map(|i| {
+                let running = &running;
+                let max_running = &max_running;
+                async move {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_running.fetch_max(now, Ordering::SeqCst);
+                    futures_lite::future::yield_now().await;
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    i
+                }
+            })
+            .collect();
+        let outputs = futures_lite::future::block_on(join_bounded(futures, 3));
+        assert_eq!(outputs, (0..10).collect::<Vec<_>>());
+        assert_eq!(max_running.load(Ordering::SeqCst), 3);
+    }
+}
@@ -2,11 +2,21 @@ pub mod options {
     use super::*;
 
     #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(default)
+    )]
     pub struct BasicQosOptions {
         pub global: Boolean,
     }
 
     #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(default)
+    )]
     pub struct BasicConsumeOptions {
         pub no_local: Boolean,
         pub no_ack: Boolean,
@@ -15,68 +25,133 @@ pub mod options {
     }
 
     #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(default)
+    )]
     pub struct BasicCancelOptions {
         pub nowait: Boolean,
     }
 
     #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(default)
+    )]
     pub struct BasicPublishOptions {
         pub mandatory: Boolean,
         pub immediate: Boolean,
     }
 
     #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(default)
+    )]
     pub struct BasicDeliverOptions {
         pub redelivered: Boolean,
     }
 
     #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(default)
+    )]
     pub struct BasicGetOptions {
         pub no_ack: Boolean,
     }
 
     #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(default)
+    )]
     pub struct BasicGetOkOptions {
         pub redelivered: Boolean,
     }
 
     #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(default)
+    )]
     pub struct BasicAckOptions {
         pub multiple: Boolean,
     }
 
     #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(default)
+    )]
     pub struct BasicRejectOptions {
         pub requeue: Boolean,
     }
 
     #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(default)
+    )]
     pub struct BasicRecoverAsyncOptions {
         pub requeue: Boolean,
     }
 
     #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(default)
+    )]
     pub struct BasicRecoverOptions {
         pub requeue: Boolean,
     }
 
     #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(default)
+    )]
     pub struct BasicNackOptions {
         pub multiple: Boolean,
         pub requeue: Boolean,
     }
 
     #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(default)
+    )]
     pub struct ChannelFlowOptions {
         pub active: Boolean,
     }
 
     #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(default)
+    )]
     pub struct ChannelFlowOkOptions {
         pub active: Boolean,
     }
 
     #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(default)
+    )]
     pub struct AccessRequestOptions {
         pub exclusive: Boolean,
         pub passive: Boolean,
@@ -86,6 +161,11 @@ pub mod options {
     }
 
     #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(default)
+    )]
     pub struct ExchangeDeclareOptions {
         pub passive: Boolean,
         pub durable: Boolean,
@@ -95,22 +175,42 @@ pub mod options {
     }
 
     #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(default)
+    )]
     pub struct ExchangeDeleteOptions {
         pub if_unused: Boolean,
         pub nowait: Boolean,
     }
 
     #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(default)
+    )]
     pub struct ExchangeBindOptions {
         pub nowait: Boolean,
     }
 
     #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(default)
+    )]
     pub struct ExchangeUnbindOptions {
         pub nowait: Boolean,
     }
 
     #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(default)
+    )]
     pub struct QueueDeclareOptions {
         pub passive: Boolean,
         pub durable: Boolean,
@@ -120,16 +220,31 @@ pub mod options {
     }
 
     #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(default)
+    )]
     pub struct QueueBindOptions {
         pub nowait: Boolean,
     }
 
     #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(default)
+    )]
     pub struct QueuePurgeOptions {
         pub nowait: Boolean,
     }
 
     #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(default)
+    )]
     pub struct QueueDeleteOptions {
         pub if_unused: Boolean,
         pub if_empty: Boolean,
@@ -137,6 +252,11 @@ pub mod options {
     }
 
     #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(default)
+    )]
     pub struct ConfirmSelectOptions {
         pub nowait: Boolean,
     }
@@ -148,7 +268,7 @@ use options::*;
 #[allow(clippy::enum_variant_names)]
 pub(crate) enum Reply {
     ConnectionStep(ConnectionStep),
-    BasicQosOk(PromiseResolver<()>),
+    BasicQosOk(PromiseResolver<()>, ShortUInt, Boolean),
     BasicConsumeOk(
         PromiseResolver<Consumer>,
         Option<Arc<ChannelCloser>>,
@@ -158,7 +278,7 @@ pub(crate) enum Reply {
         Option<Consumer>,
     ),
     BasicCancelOk(PromiseResolver<()>),
-    BasicGetOk(PromiseResolver<Option<BasicGetMessage>>),
+    BasicGetOk(PromiseResolver<Option<BasicGetMessage>>, bool),
     BasicRecoverOk(PromiseResolver<()>),
     ConnectionOpenOk(
         PromiseResolver<()>,
@@ -193,7 +313,12 @@ pub(crate) enum Reply {
         ShortString,
         FieldTable,
     ),
-    QueueDeclareOk(PromiseResolver<Queue>, QueueDeclareOptions, FieldTable),
+    QueueDeclareOk(
+        PromiseResolver<Queue>,
+        ShortString,
+        QueueDeclareOptions,
+        FieldTable,
+    ),
     QueueBindOk(
         PromiseResolver<()>,
         ShortString,
@@ -346,7 +471,7 @@ impl Channel {
 
         let BasicQosOptions { global } = options;
         let (promise, resolver) = Promise::new("basic.qos");
-        let reply = Reply::BasicQosOk(resolver.clone());
+        let reply = Reply::BasicQosOk(resolver.clone(), prefetch_count, global);
         let method = AMQPClass::Basic(protocol::basic::AMQPMethod::Qos(protocol::basic::Qos {
             prefetch_count,
             global,
@@ -358,7 +483,7 @@ impl Channel {
             Some(ExpectedReply(reply, Box::new(resolver))),
             None,
         );
-        promise.await
+        self.wait_for_reply("basic.qos", promise).await
     }
     fn receive_basic_qos_ok(&self, method: protocol::basic::QosOk) -> Result<()> {
         if !self.status.can_receive_messages() {
@@ -369,8 +494,8 @@ impl Channel {
             .frames
             .find_expected_reply(self.id, |reply| matches!(&reply.0, Reply::BasicQosOk(..)))
         {
-            Some(Reply::BasicQosOk(resolver)) => {
-                let res = Ok(());
+            Some(Reply::BasicQosOk(resolver, prefetch_count, global)) => {
+                let res = self.on_basic_qos_ok_received(prefetch_count, global);
                 resolver.complete(res.clone());
                 res
             }
@@ -436,7 +561,7 @@ impl Channel {
         if let Some(nowait_reply) = nowait_reply {
             self.receive_basic_consume_ok(nowait_reply)?;
         }
-        promise.await
+        self.wait_for_reply("basic.consume", promise).await
     }
     fn receive_basic_consume_ok(&self, method: protocol::basic::ConsumeOk) -> Result<()> {
         if !self.status.can_receive_messages() {
@@ -504,7 +629,7 @@ impl Channel {
         if let Some(nowait_reply) = nowait_reply {
             self.receive_basic_cancel_ok(nowait_reply)?;
         }
-        promise.await
+        self.wait_for_reply("basic.cancel", promise).await
     }
     fn receive_basic_cancel(&self, method: protocol::basic::Cancel) -> Result<()> {
         if !self.status.can_receive_messages() {
@@ -607,7 +732,7 @@ impl Channel {
 
         let BasicGetOptions { no_ack } = options;
         let (promise, resolver) = Promise::new("basic.get");
-        let reply = Reply::BasicGetOk(resolver.clone());
+        let reply = Reply::BasicGetOk(resolver.clone(), no_ack);
         let method = AMQPClass::Basic(protocol::basic::AMQPMethod::Get(protocol::basic::Get {
             queue,
             no_ack,
@@ -620,7 +745,7 @@ impl Channel {
             Some(ExpectedReply(reply, Box::new(resolver))),
             None,
         );
-        promise.await
+        self.wait_for_reply("basic.get", promise).await
     }
     fn receive_basic_get_ok(&self, method: protocol::basic::GetOk) -> Result<()> {
         if !self.status.can_receive_messages() {
@@ -631,7 +756,9 @@ impl Channel {
             .frames
             .find_expected_reply(self.id, |reply| matches!(&reply.0, Reply::BasicGetOk(..)))
         {
-            Some(Reply::BasicGetOk(resolver)) => self.on_basic_get_ok_received(method, resolver),
+            Some(Reply::BasicGetOk(resolver, no_ack)) => {
+                self.on_basic_get_ok_received(method, resolver, no_ack)
+            }
             unexpected => self.handle_invalid_contents(
                 format!(
                     "unexpected basic get-ok received on channel {}, was awaiting for {:?}",
@@ -693,6 +820,7 @@ impl Channel {
         ));
 
         self.send_method_frame(method, Box::new(resolver.clone()), None, Some(resolver));
+        self.on_basic_reject_sent(delivery_tag);
         promise.await
     }
     pub async fn basic_recover_async(&self, options: BasicRecoverAsyncOptions) -> Result<()> {
@@ -728,7 +856,7 @@ impl Channel {
             Some(ExpectedReply(reply, Box::new(resolver))),
             None,
         );
-        promise.await
+        self.wait_for_reply("basic.recover", promise).await
     }
     fn receive_basic_recover_ok(&self, method: protocol::basic::RecoverOk) -> Result<()> {
         if !self.status.can_receive_messages() {
@@ -798,7 +926,7 @@ impl Channel {
         response: LongString,
         locale: ShortString,
         conn_resolver: PromiseResolver<Connection>,
-        connection: Connection,
+        connection: Box<Connection>,
         auth_provider: Arc<dyn AuthProvider>,
     ) -> Result<()> {
         let (promise, resolver) = Promise::new("connection.start-ok");
@@ -841,7 +969,7 @@ impl Channel {
         &self,
         response: LongString,
         conn_resolver: PromiseResolver<Connection>,
-        connection: Connection,
+        connection: Box<Connection>,
         auth_provider: Arc<dyn AuthProvider>,
     ) -> Result<()> {
         let (promise, resolver) = Promise::new("connection.secure-ok");
@@ -911,7 +1039,7 @@ impl Channel {
             Some(ExpectedReply(reply, Box::new(resolver))),
             None,
         );
-        promise.await
+        self.wait_for_reply("connection.open", promise).await
     }
     fn receive_connection_open_ok(&self, method: protocol::connection::OpenOk) -> Result<()> {
         self.assert_channel0(method.get_amqp_class_id(), method.get_amqp_method_id())?;
@@ -961,7 +1089,7 @@ impl Channel {
             Some(ExpectedReply(reply, Box::new(resolver))),
             None,
         );
-        promise.await
+        self.wait_for_reply("connection.close", promise).await
     }
     fn receive_connection_close(&self, method: protocol::connection::Close) -> Result<()> {
         self.assert_channel0(method.get_amqp_class_id(), method.get_amqp_method_id())?;
@@ -1035,7 +1163,8 @@ impl Channel {
             Some(ExpectedReply(reply, Box::new(resolver))),
             None,
         );
-        promise.await
+        self.wait_for_reply("connection.update-secret", promise)
+            .await
     }
     fn receive_connection_update_secret_ok(
         &self,
@@ -1075,7 +1204,7 @@ impl Channel {
             Some(ExpectedReply(reply, Box::new(resolver))),
             None,
         );
-        promise.await
+        self.wait_for_reply("channel.open", promise).await
     }
     fn receive_channel_open_ok(&self, method: protocol::channel::OpenOk) -> Result<()> {
         if !self.status.initializing() {
@@ -1116,7 +1245,7 @@ impl Channel {
             Some(ExpectedReply(reply, Box::new(resolver))),
             None,
         );
-        promise.await
+        self.wait_for_reply("channel.flow", promise).await
     }
     fn receive_channel_flow(&self, method: protocol::channel::Flow) -> Result<()> {
         if !self.status.can_receive_messages() {
@@ -1188,7 +1317,7 @@ impl Channel {
             Some(ExpectedReply(reply, Box::new(resolver))),
             None,
         );
-        promise.await
+        self.wait_for_reply("channel.close", promise).await
     }
     fn receive_channel_close(&self, method: protocol::channel::Close) -> Result<()> {
         if !self.status.can_receive_messages() {
@@ -1266,7 +1395,7 @@ impl Channel {
             Some(ExpectedReply(reply, Box::new(resolver))),
             None,
         );
-        promise.await
+        self.wait_for_reply("access.request", promise).await
     }
     fn receive_access_request_ok(&self, method: protocol::access::RequestOk) -> Result<()> {
         if !self.status.can_receive_messages() {
@@ -1342,7 +1471,7 @@ impl Channel {
         if let Some(nowait_reply) = nowait_reply {
             self.receive_exchange_declare_ok(nowait_reply)?;
         }
-        promise.await
+        self.wait_for_reply("exchange.declare", promise).await
     }
     fn receive_exchange_declare_ok(&self, method: protocol::exchange::DeclareOk) -> Result<()> {
         if !self.status.can_receive_messages() {
@@ -1406,7 +1535,7 @@ impl Channel {
         if let Some(nowait_reply) = nowait_reply {
             self.receive_exchange_delete_ok(nowait_reply)?;
         }
-        promise.await
+        self.wait_for_reply("exchange.delete", promise).await
     }
     fn receive_exchange_delete_ok(&self, method: protocol::exchange::DeleteOk) -> Result<()> {
         if !self.status.can_receive_messages() {
@@ -1473,7 +1602,7 @@ impl Channel {
         if let Some(nowait_reply) = nowait_reply {
             self.receive_exchange_bind_ok(nowait_reply)?;
         }
-        promise.await
+        self.wait_for_reply("exchange.bind", promise).await
     }
     fn receive_exchange_bind_ok(&self, method: protocol::exchange::BindOk) -> Result<()> {
         if !self.status.can_receive_messages() {
@@ -1551,7 +1680,7 @@ impl Channel {
         if let Some(nowait_reply) = nowait_reply {
             self.receive_exchange_unbind_ok(nowait_reply)?;
         }
-        promise.await
+        self.wait_for_reply("exchange.unbind", promise).await
     }
     fn receive_exchange_unbind_ok(&self, method: protocol::exchange::UnbindOk) -> Result<()> {
         if !self.status.can_receive_messages() {
@@ -1606,7 +1735,8 @@ impl Channel {
             nowait,
         } = options;
         let (promise, resolver) = Promise::new("queue.declare");
-        let reply = Reply::QueueDeclareOk(resolver.clone(), options, creation_arguments);
+        let reply =
+            Reply::QueueDeclareOk(resolver.clone(), queue.clone(), options, creation_arguments);
         let nowait_reply = nowait.then(|| protocol::queue::DeclareOk {
             queue: queue.clone(),
             ..Default::default()
@@ -1632,7 +1762,7 @@ impl Channel {
         if let Some(nowait_reply) = nowait_reply {
             self.receive_queue_declare_ok(nowait_reply)?;
         }
-        promise.await
+        self.wait_for_reply("queue.declare", promise).await
     }
     fn receive_queue_declare_ok(&self, method: protocol::queue::DeclareOk) -> Result<()> {
         if !self.status.can_receive_messages() {
@@ -1642,9 +1772,8 @@ impl Channel {
         match self.frames.find_expected_reply(self.id, |reply| {
             matches!(&reply.0, Reply::QueueDeclareOk(..))
         }) {
-            Some(Reply::QueueDeclareOk(resolver, options, creation_arguments)) => {
-                self.on_queue_declare_ok_received(method, resolver, options, creation_arguments)
-            }
+            Some(Reply::QueueDeclareOk(resolver, queue, options, creation_arguments)) => self
+                .on_queue_declare_ok_received(method, resolver, queue, options, creation_arguments),
             unexpected => self.handle_invalid_contents(
                 format!(
                     "unexpected queue declare-ok received on channel {}, was awaiting for {:?}",
@@ -1695,7 +1824,7 @@ impl Channel {
         if let Some(nowait_reply) = nowait_reply {
             self.receive_queue_bind_ok(nowait_reply)?;
         }
-        promise.await
+        self.wait_for_reply("queue.bind", promise).await
     }
     fn receive_queue_bind_ok(&self, method: protocol::queue::BindOk) -> Result<()> {
         if !self.status.can_receive_messages() {
@@ -1755,7 +1884,7 @@ impl Channel {
             Some(ExpectedReply(reply, Box::new(resolver))),
             None,
         );
-        promise.await
+        self.wait_for_reply("queue.purge", promise).await
     }
     fn receive_queue_purge_ok(&self, method: protocol::queue::PurgeOk) -> Result<()> {
         if !self.status.can_receive_messages() {
@@ -1816,7 +1945,7 @@ impl Channel {
         if let Some(nowait_reply) = nowait_reply {
             self.receive_queue_delete_ok(nowait_reply)?;
         }
-        promise.await
+        self.wait_for_reply("queue.delete", promise).await
     }
     fn receive_queue_delete_ok(&self, method: protocol::queue::DeleteOk) -> Result<()> {
         if !self.status.can_receive_messages() {
@@ -1874,7 +2003,7 @@ impl Channel {
             Some(ExpectedReply(reply, Box::new(resolver))),
             None,
         );
-        promise.await
+        self.wait_for_reply("queue.unbind", promise).await
     }
     fn receive_queue_unbind_ok(&self, method: protocol::queue::UnbindOk) -> Result<()> {
         if !self.status.can_receive_messages() {
@@ -1911,7 +2040,7 @@ impl Channel {
         }
     }
     pub async fn tx_select(&self) -> Result<()> {
-        if !self.status.connected() {
+        if !self.status.connected_or_recovering() {
             return Err(self.status.state_error("tx.select"));
         }
 
@@ -1925,7 +2054,7 @@ impl Channel {
             Some(ExpectedReply(reply, Box::new(resolver))),
             None,
         );
-        promise.await
+        self.wait_for_reply("tx.select", promise).await
     }
     fn receive_tx_select_ok(&self, method: protocol::tx::SelectOk) -> Result<()> {
         if !self.status.can_receive_messages() {
@@ -1937,7 +2066,7 @@ impl Channel {
             .find_expected_reply(self.id, |reply| matches!(&reply.0, Reply::TxSelectOk(..)))
         {
             Some(Reply::TxSelectOk(resolver)) => {
-                let res = Ok(());
+                let res = self.on_tx_select_ok_received();
                 resolver.complete(res.clone());
                 res
             }
@@ -1951,7 +2080,7 @@ impl Channel {
             ),
         }
     }
-    pub async fn tx_commit(&self) -> Result<()> {
+    async fn do_tx_commit(&self) -> Result<()> {
         if !self.status.connected() {
             return Err(self.status.state_error("tx.commit"));
         }
@@ -1966,7 +2095,7 @@ impl Channel {
             Some(ExpectedReply(reply, Box::new(resolver))),
             None,
         );
-        promise.await
+        self.wait_for_reply("tx.commit", promise).await
     }
     fn receive_tx_commit_ok(&self, method: protocol::tx::CommitOk) -> Result<()> {
         if !self.status.can_receive_messages() {
@@ -1978,7 +2107,7 @@ impl Channel {
             .find_expected_reply(self.id, |reply| matches!(&reply.0, Reply::TxCommitOk(..)))
         {
             Some(Reply::TxCommitOk(resolver)) => {
-                let res = Ok(());
+                let res = self.on_tx_commit_ok_received();
                 resolver.complete(res.clone());
                 res
             }
@@ -2009,7 +2138,7 @@ impl Channel {
             Some(ExpectedReply(reply, Box::new(resolver))),
             None,
         );
-        promise.await
+        self.wait_for_reply("tx.rollback", promise).await
     }
     fn receive_tx_rollback_ok(&self, method: protocol::tx::RollbackOk) -> Result<()> {
         if !self.status.can_receive_messages() {
@@ -2021,7 +2150,7 @@ impl Channel {
             .find_expected_reply(self.id, |reply| matches!(&reply.0, Reply::TxRollbackOk(..)))
         {
             Some(Reply::TxRollbackOk(resolver)) => {
-                let res = Ok(());
+                let res = self.on_tx_rollback_ok_received();
                 resolver.complete(res.clone());
                 res
             }
@@ -2053,7 +2182,7 @@ impl Channel {
             Some(ExpectedReply(reply, Box::new(resolver))),
             None,
         );
-        promise.await
+        self.wait_for_reply("confirm.select", promise).await
     }
     fn receive_confirm_select_ok(&self, method: protocol::confirm::SelectOk) -> Result<()> {
         if !self.status.can_receive_messages() {
@@ -0,0 +1,69 @@
+//! Summarize the health of a connection, to back Kubernetes readiness and liveness probes for
+//! example.
+//!
+//! [`Connection::health`] only looks at what the connection already knows, while
+//! [`Connection::health_check`] also makes a round trip to the server.
+//!
+//! ```rust,no_run
+//! # use std::time::Duration;
+//! # async fn probes(connection: &lapin::Connection) {
+//! // liveness: restart the process when the connection is gone for good
+//! let live = connection.health(Duration::from_secs(60)).is_live();
+//! // readiness: only accept traffic when the server answers
+//! let ready = connection
+//!     .health_check(Duration::from_secs(5))
+//!     .await
+//!     .is_ready();
+//! # }
+//! ```
+//!
+//! [`Connection::health`]: ../struct.Connection.html#method.health
+//! [`Connection::health_check`]: ../struct.Connection.html#method.health_check
+
+use crate::{ConnectionState, Error};
+use std::time::Duration;
+
+/// The health of a connection, as returned by [`Connection::health`] and
+/// [`Connection::health_check`].
+///
+/// [`Connection::health`]: ../struct.Connection.html#method.health
+/// [`Connection::health_check`]: ../struct.Connection.html#method.health_check
+#[derive(Clone, Debug, PartialEq)]
+pub enum Health {
+    /// The connection is established and the server is responsive
+    Healthy,
+    /// The server blocked the connection from publishing (`connection.blocked`), usually
+    /// because of a resource alarm
+    Blocked,
+    /// The connection got lost and automatic recovery is reestablishing it
+    Recovering {
+        /// The number of reconnection attempts so far
+        attempts: usize,
+    },
+    /// The connection is established but the server didn't answer in time, or we didn't hear
+    /// from it for too long
+    Unresponsive {
+        /// The time since we last received something from the server
+        since_last_inbound: Option<Duration>,
+        /// Why the round trip to the server failed, if one was made
+        error: Option<Error>,
+    },
+    /// The connection is closed, failed, or automatic recovery gave up
+    Down {
+        /// The state of the connection
+        state: ConnectionState,
+    },
+}
+
+impl Health {
+    /// Whether the connection can be used right now, suitable for a readiness probe.
+    pub fn is_ready(&self) -> bool {
+        matches!(self, Self::Healthy)
+    }
+
+    /// Whether the connection is usable or can become usable again without restarting anything,
+    /// suitable for a liveness probe.
+    pub fn is_live(&self) -> bool {
+        !matches!(self, Self::Down { .. })
+    }
+}
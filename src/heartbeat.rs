@@ -1,4 +1,7 @@
-use crate::{ConnectionStatus, ErrorKind, internal_rpc::InternalRPCHandle, killswitch::KillSwitch};
+use crate::{
+    ConnectionStatus, ErrorKind, events::EventsSender, internal_rpc::InternalRPCHandle,
+    killswitch::KillSwitch,
+};
 use async_rs::{Runtime, traits::*};
 use std::{
     fmt,
@@ -12,17 +15,23 @@ pub struct Heartbeat<RK: RuntimeKit + Clone + Send + 'static> {
     connection_status: ConnectionStatus,
     killswitch: KillSwitch,
     runtime: Runtime<RK>,
+    events_sender: EventsSender,
     inner: Arc<Mutex<Inner>>,
 }
 
 impl<RK: RuntimeKit + Clone + Send + 'static> Heartbeat<RK> {
-    pub(crate) fn new(connection_status: ConnectionStatus, runtime: Runtime<RK>) -> Self {
+    pub(crate) fn new(
+        connection_status: ConnectionStatus,
+        runtime: Runtime<RK>,
+        events_sender: EventsSender,
+    ) -> Self {
         let killswitch = Default::default();
         let inner = Default::default();
         Self {
             connection_status,
             killswitch,
             runtime,
+            events_sender,
             inner,
         }
     }
@@ -60,7 +69,7 @@ impl<RK: RuntimeKit + Clone + Send + 'static> Heartbeat<RK> {
         }
 
         self.lock_inner()
-            .poll_timeout(internal_rpc, &self.killswitch)
+            .poll_timeout(internal_rpc, &self.events_sender, &self.killswitch)
     }
 
     pub(crate) fn update_last_write(&self) {
@@ -75,6 +84,10 @@ impl<RK: RuntimeKit + Clone + Send + 'static> Heartbeat<RK> {
         self.lock_inner().cancel();
     }
 
+    pub(crate) fn activity(&self) -> SocketActivity {
+        SocketActivity(self.inner.clone())
+    }
+
     pub(crate) fn reset(&self) {
         self.killswitch.reset();
         self.lock_inner().reset();
@@ -91,6 +104,24 @@ impl<RK: RuntimeKit + Clone + Send + 'static> fmt::Debug for Heartbeat<RK> {
     }
 }
 
+/// Read access to the last socket activity tracked by the heartbeat
+#[derive(Clone)]
+pub(crate) struct SocketActivity(Arc<Mutex<Inner>>);
+
+impl SocketActivity {
+    pub(crate) fn last_read(&self) -> Instant {
+        self.lock_inner().last_read
+    }
+
+    pub(crate) fn last_write(&self) -> Instant {
+        self.lock_inner().last_write
+    }
+
+    fn lock_inner(&self) -> MutexGuard<'_, Inner> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
 struct Inner {
     last_read: Instant,
     last_write: Instant,
@@ -113,6 +144,7 @@ impl Inner {
     fn poll_timeout(
         &mut self,
         internal_rpc: &InternalRPCHandle,
+        events_sender: &EventsSender,
         killswitch: &KillSwitch,
     ) -> Option<Duration> {
         let timeout = self.timeout?;
@@ -125,6 +157,7 @@ impl Inner {
             );
             self.timeout = None;
             killswitch.kill();
+            events_sender.heartbeat_missed();
             internal_rpc.set_connection_error(ErrorKind::MissingHeartbeatError.into());
             return None;
         }
@@ -1,6 +1,6 @@
 use crate::{
     Channel, Connection, Error, ErrorKind, Promise, PromiseResolver, Result,
-    channels::Channels,
+    channels::{ChannelKind, Channels},
     connection_closer::ConnectionCloser,
     consumer_status::ConsumerStatus,
     error_holder::ErrorHolder,
@@ -16,8 +16,19 @@ use crate::{
 use amq_protocol::frame::AMQPFrame;
 use async_rs::{Runtime, traits::*};
 use flume::{Receiver, Sender};
-use std::{collections::HashMap, fmt, future::Future, sync::Arc, time::Duration};
-use tracing::{debug, trace};
+use std::{
+    collections::HashMap,
+    fmt,
+    future::{Future, poll_fn},
+    pin::{Pin, pin},
+    sync::{Arc, Mutex},
+    task::Poll,
+    time::Duration,
+};
+use tracing::{debug, error, trace};
+
+type Sleep = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+type Sleeper = Arc<dyn Fn(Duration) -> Sleep + Send + Sync>;
 
 pub(crate) struct InternalRPC<RK: RuntimeKit + Clone + Send + 'static> {
     rpc: Receiver<Option<InternalCommand>>,
@@ -33,6 +44,7 @@ pub(crate) struct InternalRPC<RK: RuntimeKit + Clone + Send + 'static> {
 pub(crate) struct InternalRPCHandle {
     sender: Sender<Option<InternalCommand>>,
     waker: SocketStateHandle,
+    sleep: Sleeper,
 }
 
 impl InternalRPCHandle {
@@ -146,9 +158,14 @@ impl InternalRPCHandle {
     pub(crate) async fn create_channel(
         &self,
         connection_closer: Arc<ConnectionCloser>,
+        kind: ChannelKind,
     ) -> Result<Channel> {
         let (promise, resolver) = Promise::new("channel.create");
-        self.send(InternalCommand::CreateChannel(connection_closer, resolver));
+        self.send(InternalCommand::CreateChannel(
+            connection_closer,
+            kind,
+            resolver,
+        ));
         promise.await
     }
 
@@ -236,6 +253,32 @@ impl InternalRPCHandle {
         promise.await
     }
 
+    /// Run `fut` to completion, failing with `ErrorKind::Timeout(operation)` if it takes longer
+    /// than `timeout`.
+    pub(crate) async fn timeout<T>(
+        &self,
+        timeout: Option<Duration>,
+        operation: &'static str,
+        fut: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let Some(timeout) = timeout else {
+            return fut.await;
+        };
+        let mut fut = pin!(fut);
+        // Polled here rather than spawned so that the timer goes away with the call
+        let mut sleep = (self.sleep)(timeout);
+        poll_fn(|cx| {
+            if let Poll::Ready(res) = fut.as_mut().poll(cx) {
+                return Poll::Ready(res);
+            }
+            sleep.as_mut().poll(cx).map(|_| {
+                error!(%operation, ?timeout, "Timed out waiting for reply");
+                Err(ErrorKind::Timeout(operation).into())
+            })
+        })
+        .await
+    }
+
     pub(crate) fn stop(&self) {
         trace!("Stopping internal RPC command");
         let _ = self.sender.send(None);
@@ -245,6 +288,10 @@ impl InternalRPCHandle {
         self.sender.is_empty()
     }
 
+    pub(crate) fn wake(&self) {
+        self.waker.wake();
+    }
+
     fn send(&self, command: InternalCommand) {
         trace!(?command, "Queuing internal RPC command");
         // The only scenario where this can fail if this is the IoLoop already exited
@@ -291,7 +338,7 @@ enum InternalCommand {
         Identifier,
         Option<PromiseResolver<()>>,
     ),
-    CreateChannel(Arc<ConnectionCloser>, PromiseResolver<Channel>),
+    CreateChannel(Arc<ConnectionCloser>, ChannelKind, PromiseResolver<Channel>),
     DeregisterConsumer(ChannelId, ShortString),
     FinishConnectionShutdown,
     InitConnectionRecovery(Error),
@@ -318,7 +365,18 @@ impl<RK: RuntimeKit + Clone + Send + 'static> InternalRPC<RK> {
         waker: SocketStateHandle,
     ) -> Self {
         let (sender, rpc) = flume::unbounded();
-        let handle = InternalRPCHandle { sender, waker };
+        let sleeper = Mutex::new(runtime.clone());
+        let sleep = Arc::new(move |dur| {
+            let sleep = sleeper.lock().unwrap_or_else(|e| e.into_inner()).sleep(dur);
+            Box::pin(async move {
+                sleep.await;
+            }) as Sleep
+        });
+        let handle = InternalRPCHandle {
+            sender,
+            waker,
+            sleep,
+        };
         Self {
             rpc,
             handle,
@@ -464,7 +522,7 @@ impl<RK: RuntimeKit + Clone + Send + 'static> InternalRPC<RK> {
                         self.register_internal_future(fut)
                     }
                 }
-                CreateChannel(closer, resolver) => match channels.create(closer) {
+                CreateChannel(closer, kind, resolver) => match channels.create(closer, kind) {
                     Ok(channel) => self.register_internal_future_with_resolver(
                         async move { channel.clone().channel_open(channel).await },
                         resolver,
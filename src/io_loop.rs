@@ -1,5 +1,5 @@
 use crate::{
-    AsyncTcpStream, ConnectionState, ConnectionStatus, Error, ErrorKind, Result,
+    ConnectionState, ConnectionStatus, Error, ErrorKind, Result,
     buffer::Buffer,
     channels::Channels,
     configuration::NegotiatedConfig,
@@ -7,8 +7,11 @@ use crate::{
     heartbeat::Heartbeat,
     internal_rpc::InternalRPCHandle,
     killswitch::KillSwitch,
+    metrics::Metrics,
     protocol::{self, AMQPError, AMQPHardError},
+    recorder::{FrameDirection, FrameRecorder},
     socket_state::SocketState,
+    stats::TrafficStats,
     thread::JoinHandle,
     types::FrameSize,
     uri::AMQPUri,
@@ -39,10 +42,8 @@ enum Status {
 
 pub struct IoLoop<
     RK: RuntimeKit + Clone + Send + 'static,
-    C: AsyncFn(AMQPUri, Runtime<RK>) -> Result<AsyncTcpStream<<RK as Reactor>::TcpStream>>
-        + Send
-        + Sync
-        + 'static,
+    S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    C: AsyncFn(AMQPUri, Runtime<RK>) -> Result<S> + Send + Sync + 'static,
 > {
     connection_status: ConnectionStatus,
     configuration: NegotiatedConfig,
@@ -56,6 +57,10 @@ pub struct IoLoop<
     uri: AMQPUri,
     backoff: ExponentialBuilder,
     global_backoff: ExponentialBackoff,
+    manual_recovery: bool,
+    frame_recorder: Option<FrameRecorder>,
+    metrics: Option<Arc<dyn Metrics>>,
+    traffic_stats: TrafficStats,
     status: Status,
     frame_size: FrameSize,
     receive_buffer: Buffer,
@@ -67,11 +72,9 @@ pub struct IoLoop<
 
 impl<
     RK: RuntimeKit + Clone + Send + 'static,
-    C: AsyncFn(AMQPUri, Runtime<RK>) -> Result<AsyncTcpStream<<RK as Reactor>::TcpStream>>
-        + Send
-        + Sync
-        + 'static,
-> IoLoop<RK, C>
+    S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    C: AsyncFn(AMQPUri, Runtime<RK>) -> Result<S> + Send + Sync + 'static,
+> IoLoop<RK, S, C>
 {
     #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
@@ -86,6 +89,10 @@ impl<
         connect: C,
         uri: AMQPUri,
         backoff: ExponentialBuilder,
+        manual_recovery: bool,
+        frame_recorder: Option<FrameRecorder>,
+        metrics: Option<Arc<dyn Metrics>>,
+        traffic_stats: TrafficStats,
     ) -> Self {
         let frame_size = std::cmp::max(
             protocol::constants::FRAME_MIN_SIZE,
@@ -106,6 +113,10 @@ impl<
             uri,
             backoff,
             global_backoff,
+            manual_recovery,
+            frame_recorder,
+            metrics,
+            traffic_stats,
             status: Status::Initial,
             frame_size,
             receive_buffer: Buffer::with_capacity(FRAMES_STORAGE * frame_size as usize),
@@ -121,6 +132,7 @@ impl<
         self.receive_buffer.reset();
         self.send_buffer.reset();
         self.socket_state.reset();
+        self.update_buffers_stats();
     }
 
     fn finish_setup(&mut self) -> Result<bool> {
@@ -193,7 +205,9 @@ impl<
                     || !self.internal_rpc.is_empty()
                     || self.frames.has_pending()
                     || !connection_killswitch.killed()
-                    || !self.serialized_frames.is_empty()
+                    // Once the stream broke, these frames will never get through: don't wait for
+                    // them before reconnecting, they get rejected when we exit for reconnection
+                    || (!self.serialized_frames.is_empty() && !self.reconnecting())
             }
         }
     }
@@ -216,11 +230,23 @@ impl<
                     let connect = || (self.connect)(self.uri.clone(), self.runtime.clone());
                     let runtime = self.runtime.clone();
                     let connect = connect.retry(self.backoff).sleep(move |dur| runtime.sleep(dur));
-                    let mut stream = self.runtime.block_on(connect).inspect_err(|err| {
-                        trace!("Poison connection attempt");
-                        self.connection_status.poison(err.clone());
-                        self.frames.clear_connection_steps(Some(err));
-                    })?;
+                    if !self.first_connection {
+                        self.connection_status.start_recovery_attempt();
+                    }
+                    let mut stream = match self.runtime.block_on(connect) {
+                        Ok(stream) => stream,
+                        Err(err) => {
+                            trace!("Poison connection attempt");
+                            self.connection_status.poison(err.clone());
+                            self.frames.clear_connection_steps(Some(&err));
+                            if self.first_connection || !self.recovery_exhausted(err.clone()) {
+                                return Err(err);
+                            }
+                            self.reset();
+                            self.internal_rpc.start_channels_recovery();
+                            continue;
+                        }
+                    };
                     self.half_closed = false;
                     let mut res = Ok(());
 
@@ -248,12 +274,25 @@ impl<
 
                     let throttle = match self.global_backoff.next() {
                         Some(throttle) => throttle,
+                        None if self.first_connection => {
+                            error!("Exponential backoff attempts exhausted, aborting connection");
+                            break (stream, res);
+                        }
                         None => {
                             error!("Exponential backoff attempts exhausted, aborting recovery");
-                            break (stream, res);
+                            let error = res.clone().err().or_else(|| self.connection_status.recovery_status().last_error().cloned()).unwrap_or_else(|| {
+                                ErrorKind::InvalidConnectionState(ConnectionState::Reconnecting).into()
+                            });
+                            if !self.recovery_exhausted(error.clone()) {
+                                break (stream, Err(error));
+                            }
+                            Duration::ZERO
                         }
                     };
                     debug!("Throttling {:?} before reconnection to avoid flooding", throttle);
+                    if reconnect {
+                        self.connection_status.schedule_recovery_attempt(throttle);
+                    }
                     std::thread::sleep(throttle);
 
                     self.reset();
@@ -277,6 +316,44 @@ impl<
         Ok(handle)
     }
 
+    // Automatic recovery gave up. If enabled, wait until we get asked to try again through
+    // Connection::recover. Otherwise, or if the connection got dropped in the meantime, fail the
+    // connection and everything which was waiting for the recovery.
+    // Returns whether we should try to reconnect again.
+    fn recovery_exhausted(&mut self, error: Error) -> bool {
+        if self.connection_status.connecting() {
+            // The last reconnection attempt failed
+            self.connection_status
+                .set_state(ConnectionState::Reconnecting);
+        }
+        self.connection_status
+            .recovery_attempt_failed(error.clone());
+        self.connection_status.recovery_exhausted();
+        if self.manual_recovery && self.wait_for_recovery_request() {
+            return true;
+        }
+        // If the connection got dropped in the meantime, the channels already got closed
+        if !self.connection_status.closed() {
+            self.channels.set_connection_error(error);
+        }
+        false
+    }
+
+    // Wait until we get asked to try again through Connection::recover, or until the connection
+    // gets dropped
+    fn wait_for_recovery_request(&mut self) -> bool {
+        loop {
+            if self.connection_status.take_recovery_request() {
+                self.global_backoff = self.backoff.build();
+                return true;
+            }
+            if !self.connection_status.reconnecting() {
+                return false;
+            }
+            self.socket_state.wait();
+        }
+    }
+
     fn stop(&mut self) {
         self.status = Status::Stop;
     }
@@ -323,6 +400,7 @@ impl<
         }
         self.handle_frames(connection_killswitch)?;
         self.check_connection_state();
+        self.update_buffers_stats();
         trace!(
             can_read=%self.socket_state.readable(),
             can_write=%self.socket_state.writable(),
@@ -333,6 +411,13 @@ impl<
         Ok(())
     }
 
+    fn update_buffers_stats(&self) {
+        self.traffic_stats.set_buffers(
+            self.send_buffer.available_data(),
+            self.receive_buffer.available_data(),
+        );
+    }
+
     fn critical_error(&mut self, connection_killswitch: &KillSwitch, error: Error) -> Result<()> {
         if error.is_io_error() {
             connection_killswitch.kill();
@@ -452,6 +537,10 @@ impl<
 
                 trace!("wrote {} bytes", sz);
                 self.send_buffer.consume(sz);
+                self.traffic_stats.bytes_sent(sz);
+                if let Some(metrics) = self.metrics.as_deref() {
+                    metrics.bytes_written(sz);
+                }
 
                 let mut written = sz as FrameSize;
                 while written > 0 {
@@ -511,6 +600,10 @@ impl<
 
                         trace!("read {} bytes", sz);
                         self.receive_buffer.fill(sz);
+                        self.traffic_stats.bytes_received(sz);
+                        if let Some(metrics) = self.metrics.as_deref() {
+                            metrics.bytes_read(sz);
+                        }
                     } else if self.half_closed {
                         return self.handle_half_closed_connection(false);
                     } else {
@@ -554,9 +647,20 @@ impl<
             let checkpoint = self.send_buffer.checkpoint();
             let res = gen_frame(&next_msg)((&mut self.send_buffer).into());
             match res.map(|w| w.into_inner().1) {
-                Ok(sz) => self
-                    .serialized_frames
-                    .push_back(next_msg.into_serialized_frame(sz as FrameSize)),
+                Ok(sz) => {
+                    if let Some(recorder) = self.frame_recorder.as_ref() {
+                        recorder.record(
+                            FrameDirection::Outbound,
+                            self.send_buffer.written_since(&checkpoint, sz as usize),
+                        );
+                    }
+                    self.traffic_stats.frame_sent((&*next_msg).into());
+                    if let Some(metrics) = self.metrics.as_deref() {
+                        metrics.frame_sent((&*next_msg).into());
+                    }
+                    self.serialized_frames
+                        .push_back(next_msg.into_serialized_frame(sz as FrameSize))
+                }
                 Err(e) => {
                     self.send_buffer.rollback(checkpoint);
                     match e {
@@ -610,7 +714,14 @@ impl<
                         .report_protocol_violation(error, 0, 0)
                         .or_else(|err| self.critical_error(connection_killswitch, err))?;
                 }
+                if let Some(recorder) = self.frame_recorder.as_ref() {
+                    recorder.record(FrameDirection::Inbound, self.receive_buffer.peek(consumed));
+                }
                 self.receive_buffer.consume(consumed);
+                self.traffic_stats.frame_received((&f).into());
+                if let Some(metrics) = self.metrics.as_deref() {
+                    metrics.frame_received((&f).into());
+                }
                 Ok(Some(f))
             }
             Err(e) => {
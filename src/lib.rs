@@ -16,6 +16,13 @@
 //! * `rustls` (*default*): enable amqps support through rustls (uses rustls-native-certs by default)
 //! * `rustls-native-certs`: same as rustls, be ensure we'll still use rustls-native-certs even if the default for rustls changes
 //! * `rustls-webpki-roots-certs`: same as rustls but using webkit-roots instead of rustls-native-certs
+//! * `opentelemetry`: export the connection metrics as OpenTelemetry instruments
+//! * `prometheus`: export the connection metrics to a Prometheus registry
+//! * `serde`: make the topology definitions and the methods options (de)serializable
+//! * `socket-options`: apply the socket level options of [`SocketOptions`] (unix platforms only)
+//! * `testing`: enable the in-memory fake broker and the scripted peer from the [`testing`] module
+//! * `trace-context`: propagate the W3C trace context through the messages headers, see the `trace_context` module
+//! * `websocket`: enable AMQP over WebSocket support (`ws://` and `wss://` URIs)
 //!
 //! ## Example
 //!
@@ -108,28 +115,45 @@ pub use amq_protocol::{
 };
 
 pub use acker::Acker;
+pub use acknowledger::Acknowledger;
 pub use channel::{Channel, options};
-pub use channel_status::{ChannelState, ChannelStatus};
+pub use channel_status::{ChannelRecoveryState, ChannelState, ChannelStatus};
 pub use configuration::Configuration;
 pub use connection::{Connect, Connection};
 pub use connection_builder::{ConnectionBuilder, DefaultConnectionBuilder};
 pub use connection_properties::ConnectionProperties;
-pub use connection_status::{ConnectionState, ConnectionStatus};
+pub use connection_status::{ConnectionState, ConnectionStatus, RecoveryStatus};
 pub use consumer::{Consumer, ConsumerDelegate};
 pub use error::{Error, ErrorKind, Result};
 pub use events::Event;
 pub use exchange::ExchangeKind;
+pub use publisher::Publisher;
 pub use publisher_confirm::{Confirmation, PublisherConfirm};
 pub use queue::Queue;
+pub use recovery_listener::RecoveryListener;
+pub use socket_options::SocketOptions;
 
 pub mod auth;
+pub mod health;
 pub mod message;
+pub mod metrics;
+pub mod recorder;
 pub mod runtime;
+pub mod snapshot;
+pub mod stats;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod topology;
+#[cfg(feature = "trace-context")]
+pub mod trace_context;
+#[cfg(feature = "websocket")]
+pub mod websocket;
 
 use promise::{Promise, PromiseResolver};
 
 mod acker;
 mod acknowledgement;
+mod acknowledger;
 mod basic_get_delivery;
 mod buffer;
 mod channel;
@@ -163,12 +187,15 @@ mod killswitch;
 mod notifier;
 mod parsing;
 mod promise;
+mod publisher;
 mod publisher_confirm;
 mod queue;
+mod recovery_listener;
 mod registry;
 mod returned_messages;
 mod secret_update;
+mod socket_options;
 mod socket_state;
 mod thread;
-mod topology;
+mod unacked_deliveries;
 mod wakers;
@@ -98,6 +98,13 @@ impl Delivery {
         }
     }
 
+    pub(crate) fn receive_properties(&mut self, properties: BasicProperties) {
+        #[cfg(feature = "trace-context")]
+        self.acker
+            .receive_span(self.exchange.clone(), self.routing_key.clone(), &properties);
+        self.properties = properties;
+    }
+
     pub(crate) fn receive_content(&mut self, data: Vec<u8>) {
         self.data.extend(data);
     }
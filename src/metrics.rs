@@ -0,0 +1,350 @@
+//! Collect metrics about what a connection does, by implementing the [`Metrics`] trait and
+//! registering it with [`ConnectionProperties::with_metrics`].
+//!
+//! [`AtomicMetrics`] is a ready to use implementation keeping plain counters, and adapters for
+//! Prometheus and OpenTelemetry are available with the `prometheus` and `opentelemetry`
+//! features.
+//!
+//! ```rust
+//! use lapin::{ConnectionProperties, metrics::AtomicMetrics};
+//! use std::sync::Arc;
+//!
+//! let metrics = Arc::new(AtomicMetrics::default());
+//! let options = ConnectionProperties::default().with_metrics(metrics.clone());
+//! // ... use the connection
+//! println!("published {} messages", metrics.snapshot().published);
+//! ```
+//!
+//! [`ConnectionProperties::with_metrics`]: ../struct.ConnectionProperties.html#method.with_metrics
+
+use crate::types::ChannelId;
+use amq_protocol::frame::AMQPFrame;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+#[cfg(feature = "opentelemetry")]
+pub use self::opentelemetry::OpenTelemetryMetrics;
+#[cfg(feature = "prometheus")]
+pub use self::prometheus::PrometheusMetrics;
+
+#[cfg(feature = "opentelemetry")]
+mod opentelemetry;
+#[cfg(feature = "prometheus")]
+mod prometheus;
+
+/// The type of an AMQP frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FrameType {
+    /// The protocol header, sent by the client when opening the connection.
+    ProtocolHeader,
+    /// A method frame, carrying an AMQP method such as `basic.publish`.
+    Method,
+    /// A content header frame, carrying the properties of a message.
+    Header,
+    /// A content body frame, carrying (part of) the payload of a message.
+    Body,
+    /// A heartbeat frame.
+    Heartbeat,
+}
+
+impl FrameType {
+    /// A lowercase name for this frame type, suitable as a metric label.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ProtocolHeader => "protocol_header",
+            Self::Method => "method",
+            Self::Header => "header",
+            Self::Body => "body",
+            Self::Heartbeat => "heartbeat",
+        }
+    }
+}
+
+impl From<&AMQPFrame> for FrameType {
+    fn from(frame: &AMQPFrame) -> Self {
+        match frame {
+            AMQPFrame::ProtocolHeader(_) => Self::ProtocolHeader,
+            AMQPFrame::Method(..) => Self::Method,
+            AMQPFrame::Header(..) => Self::Header,
+            AMQPFrame::Body(..) => Self::Body,
+            AMQPFrame::Heartbeat | AMQPFrame::InvalidHeartbeat(_) => Self::Heartbeat,
+        }
+    }
+}
+
+/// A trait used to collect metrics about a connection.
+///
+/// Every method has a default implementation doing nothing, so you only need to implement the
+/// ones you're interested in. They are called from the connection internals (the IO loop for
+/// the bytes and frames), so they must be cheap and must not block.
+pub trait Metrics: Send + Sync + 'static {
+    /// Called when bytes were read from the socket
+    fn bytes_read(&self, _bytes: usize) {}
+
+    /// Called when bytes were written to the socket
+    fn bytes_written(&self, _bytes: usize) {}
+
+    /// Called when a frame was received from the server
+    fn frame_received(&self, _frame_type: FrameType) {}
+
+    /// Called when a frame was serialized to be sent to the server
+    fn frame_sent(&self, _frame_type: FrameType) {}
+
+    /// Called when a message gets published
+    fn message_published(&self, _channel_id: ChannelId) {}
+
+    /// Called when the server confirmed a message published with publisher confirms enabled,
+    /// with the time elapsed since it was published
+    fn publish_confirmed(&self, _channel_id: ChannelId, _latency: Duration, _acked: bool) {}
+
+    /// Called when a message was received, from a consumer or `basic.get`
+    fn message_delivered(&self, _channel_id: ChannelId) {}
+
+    /// Called when a delivery got acked (once per `basic.ack`, even with `multiple`)
+    fn message_acked(&self, _channel_id: ChannelId) {}
+
+    /// Called when a delivery got nacked (once per `basic.nack`, even with `multiple`)
+    fn message_nacked(&self, _channel_id: ChannelId) {}
+
+    /// Called when a delivery got rejected
+    fn message_rejected(&self, _channel_id: ChannelId) {}
+
+    /// Called when the server returned a message published as mandatory
+    fn message_returned(&self, _channel_id: ChannelId) {}
+
+    /// Called when the connection got successfully recovered
+    fn connection_recovered(&self) {}
+
+    /// Called when a channel gets created
+    fn channel_opened(&self, _channel_id: ChannelId) {}
+
+    /// Called when a channel gets closed or fails, including when the whole connection does
+    fn channel_closed(&self, _channel_id: ChannelId) {}
+}
+
+impl<M: Metrics> Metrics for Arc<M> {
+    fn bytes_read(&self, bytes: usize) {
+        (**self).bytes_read(bytes)
+    }
+
+    fn bytes_written(&self, bytes: usize) {
+        (**self).bytes_written(bytes)
+    }
+
+    fn frame_received(&self, frame_type: FrameType) {
+        (**self).frame_received(frame_type)
+    }
+
+    fn frame_sent(&self, frame_type: FrameType) {
+        (**self).frame_sent(frame_type)
+    }
+
+    fn message_published(&self, channel_id: ChannelId) {
+        (**self).message_published(channel_id)
+    }
+
+    fn publish_confirmed(&self, channel_id: ChannelId, latency: Duration, acked: bool) {
+        (**self).publish_confirmed(channel_id, latency, acked)
+    }
+
+    fn message_delivered(&self, channel_id: ChannelId) {
+        (**self).message_delivered(channel_id)
+    }
+
+    fn message_acked(&self, channel_id: ChannelId) {
+        (**self).message_acked(channel_id)
+    }
+
+    fn message_nacked(&self, channel_id: ChannelId) {
+        (**self).message_nacked(channel_id)
+    }
+
+    fn message_rejected(&self, channel_id: ChannelId) {
+        (**self).message_rejected(channel_id)
+    }
+
+    fn message_returned(&self, channel_id: ChannelId) {
+        (**self).message_returned(channel_id)
+    }
+
+    fn connection_recovered(&self) {
+        (**self).connection_recovered()
+    }
+
+    fn channel_opened(&self, channel_id: ChannelId) {
+        (**self).channel_opened(channel_id)
+    }
+
+    fn channel_closed(&self, channel_id: ChannelId) {
+        (**self).channel_closed(channel_id)
+    }
+}
+
+/// A [`Metrics`] implementation keeping atomic counters, which can be read using
+/// [`AtomicMetrics::snapshot`].
+#[derive(Debug, Default)]
+pub struct AtomicMetrics {
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    frames_received: AtomicU64,
+    frames_sent: AtomicU64,
+    published: AtomicU64,
+    confirmed: AtomicU64,
+    publish_nacked: AtomicU64,
+    confirm_latency_micros: AtomicU64,
+    delivered: AtomicU64,
+    acked: AtomicU64,
+    nacked: AtomicU64,
+    rejected: AtomicU64,
+    returned: AtomicU64,
+    recoveries: AtomicU64,
+    channels: AtomicU64,
+}
+
+/// The values of an [`AtomicMetrics`] at some point.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MetricsSnapshot {
+    /// Bytes read from the socket
+    pub bytes_read: u64,
+    /// Bytes written to the socket
+    pub bytes_written: u64,
+    /// Frames received from the server
+    pub frames_received: u64,
+    /// Frames sent to the server
+    pub frames_sent: u64,
+    /// Messages published
+    pub published: u64,
+    /// Published messages confirmed by the server, acked or nacked
+    pub confirmed: u64,
+    /// Published messages nacked by the server
+    pub publish_nacked: u64,
+    /// The sum of the confirmation latencies, in microseconds
+    pub confirm_latency_micros: u64,
+    /// Messages received
+    pub delivered: u64,
+    /// Deliveries acked
+    pub acked: u64,
+    /// Deliveries nacked
+    pub nacked: u64,
+    /// Deliveries rejected
+    pub rejected: u64,
+    /// Messages returned by the server
+    pub returned: u64,
+    /// Successful connection recoveries
+    pub recoveries: u64,
+    /// Channels currently open
+    pub channels: u64,
+}
+
+impl AtomicMetrics {
+    /// Read the current values of the counters.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            frames_received: self.frames_received.load(Ordering::Relaxed),
+            frames_sent: self.frames_sent.load(Ordering::Relaxed),
+            published: self.published.load(Ordering::Relaxed),
+            confirmed: self.confirmed.load(Ordering::Relaxed),
+            publish_nacked: self.publish_nacked.load(Ordering::Relaxed),
+            confirm_latency_micros: self.confirm_latency_micros.load(Ordering::Relaxed),
+            delivered: self.delivered.load(Ordering::Relaxed),
+            acked: self.acked.load(Ordering::Relaxed),
+            nacked: self.nacked.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            returned: self.returned.load(Ordering::Relaxed),
+            recoveries: self.recoveries.load(Ordering::Relaxed),
+            channels: self.channels.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl MetricsSnapshot {
+    /// The average time the server took to confirm a published message.
+    pub fn average_confirm_latency(&self) -> Option<Duration> {
+        (self.confirmed > 0)
+            .then(|| Duration::from_micros(self.confirm_latency_micros / self.confirmed))
+    }
+}
+
+fn increment(counter: &AtomicU64) {
+    add(counter, 1);
+}
+
+fn add(counter: &AtomicU64, value: u64) {
+    counter.fetch_add(value, Ordering::Relaxed);
+}
+
+impl Metrics for AtomicMetrics {
+    fn bytes_read(&self, bytes: usize) {
+        add(&self.bytes_read, bytes as u64);
+    }
+
+    fn bytes_written(&self, bytes: usize) {
+        add(&self.bytes_written, bytes as u64);
+    }
+
+    fn frame_received(&self, _frame_type: FrameType) {
+        increment(&self.frames_received);
+    }
+
+    fn frame_sent(&self, _frame_type: FrameType) {
+        increment(&self.frames_sent);
+    }
+
+    fn message_published(&self, _channel_id: ChannelId) {
+        increment(&self.published);
+    }
+
+    fn publish_confirmed(&self, _channel_id: ChannelId, latency: Duration, acked: bool) {
+        increment(&self.confirmed);
+        if !acked {
+            increment(&self.publish_nacked);
+        }
+        add(&self.confirm_latency_micros, latency.as_micros() as u64);
+    }
+
+    fn message_delivered(&self, _channel_id: ChannelId) {
+        increment(&self.delivered);
+    }
+
+    fn message_acked(&self, _channel_id: ChannelId) {
+        increment(&self.acked);
+    }
+
+    fn message_nacked(&self, _channel_id: ChannelId) {
+        increment(&self.nacked);
+    }
+
+    fn message_rejected(&self, _channel_id: ChannelId) {
+        increment(&self.rejected);
+    }
+
+    fn message_returned(&self, _channel_id: ChannelId) {
+        increment(&self.returned);
+    }
+
+    fn connection_recovered(&self) {
+        increment(&self.recoveries);
+    }
+
+    fn channel_opened(&self, _channel_id: ChannelId) {
+        increment(&self.channels);
+    }
+
+    fn channel_closed(&self, _channel_id: ChannelId) {
+        // Never go below 0 if we get closed twice somehow
+        let _ = self
+            .channels
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |channels| {
+                channels.checked_sub(1)
+            });
+    }
+}
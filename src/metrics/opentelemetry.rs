@@ -0,0 +1,160 @@
+use super::{FrameType, Metrics};
+use crate::types::ChannelId;
+use opentelemetry::{
+    KeyValue,
+    metrics::{Counter, Histogram, Meter, UpDownCounter},
+};
+use std::time::Duration;
+
+/// A [`Metrics`] implementation recording OpenTelemetry instruments, prefixed with `lapin.`.
+///
+/// The channel ids are attached to the per channel measurements as the `channel` attribute.
+#[derive(Clone, Debug)]
+pub struct OpenTelemetryMetrics {
+    bytes_read: Counter<u64>,
+    bytes_written: Counter<u64>,
+    frames_received: Counter<u64>,
+    frames_sent: Counter<u64>,
+    published: Counter<u64>,
+    confirms: Counter<u64>,
+    confirm_latency: Histogram<f64>,
+    delivered: Counter<u64>,
+    acknowledgements: Counter<u64>,
+    returned: Counter<u64>,
+    recoveries: Counter<u64>,
+    channels: UpDownCounter<i64>,
+}
+
+impl OpenTelemetryMetrics {
+    /// Create the instruments using the given meter.
+    pub fn new(meter: &Meter) -> Self {
+        Self {
+            bytes_read: meter
+                .u64_counter("lapin.bytes_read")
+                .with_unit("By")
+                .with_description("Bytes read from the socket")
+                .build(),
+            bytes_written: meter
+                .u64_counter("lapin.bytes_written")
+                .with_unit("By")
+                .with_description("Bytes written to the socket")
+                .build(),
+            frames_received: meter
+                .u64_counter("lapin.frames_received")
+                .with_description("Frames received from the server")
+                .build(),
+            frames_sent: meter
+                .u64_counter("lapin.frames_sent")
+                .with_description("Frames sent to the server")
+                .build(),
+            published: meter
+                .u64_counter("lapin.published")
+                .with_description("Messages published")
+                .build(),
+            confirms: meter
+                .u64_counter("lapin.publish_confirms")
+                .with_description("Published messages confirmed by the server")
+                .build(),
+            confirm_latency: meter
+                .f64_histogram("lapin.publish_confirm_latency")
+                .with_unit("s")
+                .with_description(
+                    "Time between publishing a message and its confirmation by the server",
+                )
+                .build(),
+            delivered: meter
+                .u64_counter("lapin.delivered")
+                .with_description("Messages received")
+                .build(),
+            acknowledgements: meter
+                .u64_counter("lapin.acknowledgements")
+                .with_description("Deliveries acked, nacked or rejected")
+                .build(),
+            returned: meter
+                .u64_counter("lapin.returned")
+                .with_description("Messages returned by the server")
+                .build(),
+            recoveries: meter
+                .u64_counter("lapin.recoveries")
+                .with_description("Successful connection recoveries")
+                .build(),
+            channels: meter
+                .i64_up_down_counter("lapin.channels")
+                .with_description("Channels currently open")
+                .build(),
+        }
+    }
+}
+
+fn channel(channel_id: ChannelId) -> KeyValue {
+    KeyValue::new("channel", i64::from(channel_id))
+}
+
+impl Metrics for OpenTelemetryMetrics {
+    fn bytes_read(&self, bytes: usize) {
+        self.bytes_read.add(bytes as u64, &[]);
+    }
+
+    fn bytes_written(&self, bytes: usize) {
+        self.bytes_written.add(bytes as u64, &[]);
+    }
+
+    fn frame_received(&self, frame_type: FrameType) {
+        self.frames_received
+            .add(1, &[KeyValue::new("type", frame_type.as_str())]);
+    }
+
+    fn frame_sent(&self, frame_type: FrameType) {
+        self.frames_sent
+            .add(1, &[KeyValue::new("type", frame_type.as_str())]);
+    }
+
+    fn message_published(&self, channel_id: ChannelId) {
+        self.published.add(1, &[channel(channel_id)]);
+    }
+
+    fn publish_confirmed(&self, channel_id: ChannelId, latency: Duration, acked: bool) {
+        let attributes = [
+            channel(channel_id),
+            KeyValue::new("result", if acked { "ack" } else { "nack" }),
+        ];
+        self.confirms.add(1, &attributes);
+        self.confirm_latency
+            .record(latency.as_secs_f64(), &attributes);
+    }
+
+    fn message_delivered(&self, channel_id: ChannelId) {
+        self.delivered.add(1, &[channel(channel_id)]);
+    }
+
+    fn message_acked(&self, channel_id: ChannelId) {
+        self.acknowledgements
+            .add(1, &[channel(channel_id), KeyValue::new("kind", "ack")]);
+    }
+
+    fn message_nacked(&self, channel_id: ChannelId) {
+        self.acknowledgements
+            .add(1, &[channel(channel_id), KeyValue::new("kind", "nack")]);
+    }
+
+    fn message_rejected(&self, channel_id: ChannelId) {
+        self.acknowledgements
+            .add(1, &[channel(channel_id), KeyValue::new("kind", "reject")]);
+    }
+
+    fn message_returned(&self, channel_id: ChannelId) {
+        self.returned.add(1, &[channel(channel_id)]);
+    }
+
+    fn connection_recovered(&self) {
+        self.recoveries.add(1, &[]);
+    }
+
+    fn channel_opened(&self, _channel_id: ChannelId) {
+        self.channels.add(1, &[]);
+    }
+
+    fn channel_closed(&self, _channel_id: ChannelId) {
+        self.channels.add(-1, &[]);
+    }
+}
@@ -0,0 +1,159 @@
+use super::{FrameType, Metrics};
+use crate::types::ChannelId;
+use prometheus::{
+    Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts, Registry, core::Collector,
+};
+use std::time::Duration;
+
+/// A [`Metrics`] implementation exporting Prometheus metrics, prefixed with `lapin_`.
+///
+/// The metrics are registered in the given registry. To monitor several connections with the
+/// same registry, share the same instance between them.
+#[derive(Clone, Debug)]
+pub struct PrometheusMetrics {
+    bytes_read: IntCounter,
+    bytes_written: IntCounter,
+    frames_received: IntCounterVec,
+    frames_sent: IntCounterVec,
+    published: IntCounter,
+    confirms: IntCounterVec,
+    confirm_latency: Histogram,
+    delivered: IntCounter,
+    acknowledgements: IntCounterVec,
+    returned: IntCounter,
+    recoveries: IntCounter,
+    channels: IntGauge,
+}
+
+impl PrometheusMetrics {
+    /// Create the metrics and register them in the given registry.
+    pub fn new(registry: &Registry) -> prometheus::Result<Self> {
+        let metrics = Self {
+            bytes_read: IntCounter::new("lapin_bytes_read_total", "Bytes read from the socket")?,
+            bytes_written: IntCounter::new(
+                "lapin_bytes_written_total",
+                "Bytes written to the socket",
+            )?,
+            frames_received: IntCounterVec::new(
+                Opts::new(
+                    "lapin_frames_received_total",
+                    "Frames received from the server",
+                ),
+                &["type"],
+            )?,
+            frames_sent: IntCounterVec::new(
+                Opts::new("lapin_frames_sent_total", "Frames sent to the server"),
+                &["type"],
+            )?,
+            published: IntCounter::new("lapin_published_total", "Messages published")?,
+            confirms: IntCounterVec::new(
+                Opts::new(
+                    "lapin_publish_confirms_total",
+                    "Published messages confirmed by the server",
+                ),
+                &["result"],
+            )?,
+            confirm_latency: Histogram::with_opts(HistogramOpts::new(
+                "lapin_publish_confirm_latency_seconds",
+                "Time between publishing a message and its confirmation by the server",
+            ))?,
+            delivered: IntCounter::new("lapin_delivered_total", "Messages received")?,
+            acknowledgements: IntCounterVec::new(
+                Opts::new(
+                    "lapin_acknowledgements_total",
+                    "Deliveries acked, nacked or rejected",
+                ),
+                &["kind"],
+            )?,
+            returned: IntCounter::new("lapin_returned_total", "Messages returned by the server")?,
+            recoveries: IntCounter::new(
+                "lapin_recoveries_total",
+                "Successful connection recoveries",
+            )?,
+            channels: IntGauge::new("lapin_channels", "Channels currently open")?,
+        };
+        let collectors: [Box<dyn Collector>; 12] = [
+            Box::new(metrics.bytes_read.clone()),
+            Box::new(metrics.bytes_written.clone()),
+            Box::new(metrics.frames_received.clone()),
+            Box::new(metrics.frames_sent.clone()),
+            Box::new(metrics.published.clone()),
+            Box::new(metrics.confirms.clone()),
+            Box::new(metrics.confirm_latency.clone()),
+            Box::new(metrics.delivered.clone()),
+            Box::new(metrics.acknowledgements.clone()),
+            Box::new(metrics.returned.clone()),
+            Box::new(metrics.recoveries.clone()),
+            Box::new(metrics.channels.clone()),
+        ];
+        for collector in collectors {
+            registry.register(collector)?;
+        }
+        Ok(metrics)
+    }
+}
+
+impl Metrics for PrometheusMetrics {
+    fn bytes_read(&self, bytes: usize) {
+        self.bytes_read.inc_by(bytes as u64);
+    }
+
+    fn bytes_written(&self, bytes: usize) {
+        self.bytes_written.inc_by(bytes as u64);
+    }
+
+    fn frame_received(&self, frame_type: FrameType) {
+        self.frames_received
+            .with_label_values(&[frame_type.as_str()])
+            .inc();
+    }
+
+    fn frame_sent(&self, frame_type: FrameType) {
+        self.frames_sent
+            .with_label_values(&[frame_type.as_str()])
+            .inc();
+    }
+
+    fn message_published(&self, _channel_id: ChannelId) {
+        self.published.inc();
+    }
+
+    fn publish_confirmed(&self, _channel_id: ChannelId, latency: Duration, acked: bool) {
+        self.confirms
+            .with_label_values(&[if acked { "ack" } else { "nack" }])
+            .inc();
+        self.confirm_latency.observe(latency.as_secs_f64());
+    }
+
+    fn message_delivered(&self, _channel_id: ChannelId) {
+        self.delivered.inc();
+    }
+
+    fn message_acked(&self, _channel_id: ChannelId) {
+        self.acknowledgements.with_label_values(&["ack"]).inc();
+    }
+
+    fn message_nacked(&self, _channel_id: ChannelId) {
+        self.acknowledgements.with_label_values(&["nack"]).inc();
+    }
+
+    fn message_rejected(&self, _channel_id: ChannelId) {
+        self.acknowledgements.with_label_values(&["reject"]).inc();
+    }
+
+    fn message_returned(&self, _channel_id: ChannelId) {
+        self.returned.inc();
+    }
+
+    fn connection_recovered(&self) {
+        self.recoveries.inc();
+    }
+
+    fn channel_opened(&self, _channel_id: ChannelId) {
+        self.channels.inc();
+    }
+
+    fn channel_closed(&self, _channel_id: ChannelId) {
+        self.channels.dec();
+    }
+}
@@ -0,0 +1,41 @@
+use crate::{
+    BasicProperties, Channel, Result, options::BasicPublishOptions,
+    publisher_confirm::PublisherConfirm, types::ShortString,
+};
+use async_trait::async_trait;
+
+/// Something messages can be published to.
+///
+/// It is implemented by [`Channel`], so that application code publishing messages can take a
+/// `&dyn Publisher` (or be generic over it) and be unit tested without a broker, using the
+/// `RecordingPublisher` from the `testing` module for example.
+///
+/// [`Channel`]: ./struct.Channel.html
+#[async_trait]
+pub trait Publisher: Send + Sync {
+    /// Publish a message, see [`Channel::basic_publish`].
+    ///
+    /// [`Channel::basic_publish`]: ./struct.Channel.html#method.basic_publish
+    async fn basic_publish(
+        &self,
+        exchange: ShortString,
+        routing_key: ShortString,
+        options: BasicPublishOptions,
+        payload: &[u8],
+        properties: BasicProperties,
+    ) -> Result<PublisherConfirm>;
+}
+
+#[async_trait]
+impl Publisher for Channel {
+    async fn basic_publish(
+        &self,
+        exchange: ShortString,
+        routing_key: ShortString,
+        options: BasicPublishOptions,
+        payload: &[u8],
+        properties: BasicProperties,
+    ) -> Result<PublisherConfirm> {
+        Channel::basic_publish(self, exchange, routing_key, options, payload, properties).await
+    }
+}
@@ -56,6 +56,19 @@ impl PublisherConfirm {
     }
 }
 
+#[cfg(feature = "testing")]
+impl PublisherConfirm {
+    pub(crate) fn ready(confirmation: Confirmation) -> Self {
+        Self {
+            inner: Some(Promise::new_with_data(
+                "publisher-confirms.ready",
+                Ok(confirmation),
+            )),
+            returned_messages: ReturnedMessages::default(),
+        }
+    }
+}
+
 impl fmt::Debug for PublisherConfirm {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("PublisherConfirm").finish()
@@ -1,9 +1,14 @@
 use crate::types::{ConsumerCount, MessageCount, ShortString};
-use std::borrow::Borrow;
+use std::{
+    borrow::Borrow,
+    fmt,
+    sync::{Arc, RwLock},
+};
 
 #[derive(Clone, Debug)]
 pub struct Queue {
     name: ShortString,
+    current_name: QueueName,
     message_count: MessageCount,
     consumer_count: ConsumerCount,
 }
@@ -11,20 +16,33 @@ pub struct Queue {
 impl Queue {
     pub(crate) fn new(
         name: ShortString,
+        current_name: QueueName,
         message_count: MessageCount,
         consumer_count: ConsumerCount,
     ) -> Self {
         Self {
             name,
+            current_name,
             message_count,
             consumer_count,
         }
     }
 
+    /// The name of the queue, as returned by the server when it got declared.
     pub fn name(&self) -> &ShortString {
         &self.name
     }
 
+    /// The up to date name of the queue.
+    ///
+    /// This differs from [`name`] when a server-named queue got a new name from the server
+    /// after being redeclared during connection recovery.
+    ///
+    /// [`name`]: #method.name
+    pub fn current_name(&self) -> ShortString {
+        self.current_name.get()
+    }
+
     pub fn message_count(&self) -> MessageCount {
         self.message_count
     }
@@ -39,3 +57,27 @@ impl Borrow<str> for Queue {
         self.name.as_str()
     }
 }
+
+/// Shared queue name, updated when a server-named queue gets renamed during recovery.
+#[derive(Clone)]
+pub(crate) struct QueueName(Arc<RwLock<ShortString>>);
+
+impl QueueName {
+    pub(crate) fn new(name: ShortString) -> Self {
+        Self(Arc::new(RwLock::new(name)))
+    }
+
+    pub(crate) fn get(&self) -> ShortString {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub(crate) fn set(&self, name: ShortString) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = name;
+    }
+}
+
+impl fmt::Debug for QueueName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.get(), f)
+    }
+}
@@ -0,0 +1,340 @@
+//! Record the frames exchanged with the server, to investigate what happened on the wire.
+//!
+//! Recording is opt-in, using [`ConnectionProperties::with_frame_recorder`]:
+//!
+//! ```rust,no_run
+//! use lapin::{
+//!     ConnectionProperties,
+//!     recorder::{FrameRecorder, PayloadPolicy},
+//! };
+//!
+//! # fn run() -> std::io::Result<()> {
+//! let recorder = FrameRecorder::create("/tmp/lapin.rec")?
+//!     .with_payload_policy(PayloadPolicy::Truncate(64));
+//! let options = ConnectionProperties::default().with_frame_recorder(recorder.clone());
+//! // ... use the connection, then make sure everything reached the disk
+//! recorder.flush()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The recording can then be read back with a [`FrameRecordReader`], and, with the `testing`
+//! feature, be replayed against a [`Connection`] using `Script::replay`.
+//!
+//! The file starts with the `LAPINREC` magic followed by a format version byte, then each
+//! record is made of the direction (1 byte), the timestamp in microseconds since the UNIX
+//! epoch (8 bytes), the channel id (2 bytes), the number of truncated payload bytes (4 bytes),
+//! the size of the frame (4 bytes) and finally the frame as it is sent on the wire. All the
+//! integers are big endian.
+//!
+//! [`ConnectionProperties::with_frame_recorder`]: ../struct.ConnectionProperties.html#method.with_frame_recorder
+//! [`Connection`]: ../struct.Connection.html
+
+use crate::{protocol::constants, types::ChannelId};
+use amq_protocol::frame::{AMQPFrame, parse_frame};
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+    },
+    thread::Builder as ThreadBuilder,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::error;
+
+const MAGIC: &[u8; 8] = b"LAPINREC";
+const VERSION: u8 = 1;
+
+/// Whether a frame was received from the server or sent to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameDirection {
+    /// Received from the server
+    Inbound,
+    /// Sent to the server
+    Outbound,
+}
+
+/// What to do with the messages payloads (the body frames) when recording them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PayloadPolicy {
+    /// Record the payloads as is
+    #[default]
+    Keep,
+    /// Only record the first bytes of each body frame
+    Truncate(usize),
+    /// Replace the payloads with zeros, only keeping their size
+    Redact,
+}
+
+/// A frame read from a recording.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameRecord {
+    /// Whether the frame was received or sent
+    pub direction: FrameDirection,
+    /// When the frame was parsed (inbound) or serialized (outbound)
+    pub timestamp: SystemTime,
+    /// The channel the frame was for
+    pub channel_id: ChannelId,
+    /// How many bytes were dropped from the payload of this body frame by
+    /// [`PayloadPolicy::Truncate`]
+    pub truncated: usize,
+    /// The frame itself
+    pub frame: AMQPFrame,
+}
+
+/// Write the frames going through a connection to a file (or any writer), see the [module
+/// documentation](self) for more details.
+///
+/// The io loop only copies the bytes it sent or received, the payload policy is applied and the
+/// records are written by a dedicated thread, so that recording doesn't slow down the connection.
+///
+/// Cloning a recorder gives another handle to the same output.
+#[derive(Clone)]
+pub struct FrameRecorder {
+    sender: Sender<Command>,
+    failed: Arc<AtomicBool>,
+    payload_policy: PayloadPolicy,
+}
+
+enum Command {
+    Record(Record),
+    Flush(Sender<io::Result<()>>),
+}
+
+struct Record {
+    direction: FrameDirection,
+    timestamp: SystemTime,
+    payload_policy: PayloadPolicy,
+    bytes: Vec<u8>,
+}
+
+impl FrameRecorder {
+    /// Record the frames to the given writer, which gets buffered.
+    pub fn new<W: Write + Send + 'static>(writer: W) -> io::Result<Self> {
+        let mut writer = BufWriter::new(writer);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        let (sender, receiver) = mpsc::channel();
+        let failed = Arc::<AtomicBool>::default();
+        let writer_failed = failed.clone();
+        ThreadBuilder::new()
+            .name("lapin-frame-recorder".to_owned())
+            .spawn(move || write_records(writer, receiver, writer_failed))?;
+        Ok(Self {
+            sender,
+            failed,
+            payload_policy: PayloadPolicy::default(),
+        })
+    }
+
+    /// Record the frames to the given file, truncating it if it already exists.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(File::create(path)?)
+    }
+
+    /// Choose what to do with the messages payloads (default: [`PayloadPolicy::Keep`]).
+    #[must_use]
+    pub fn with_payload_policy(mut self, payload_policy: PayloadPolicy) -> Self {
+        self.payload_policy = payload_policy;
+        self
+    }
+
+    /// Wait for the frames recorded so far to be written, and flush them to the underlying
+    /// writer.
+    pub fn flush(&self) -> io::Result<()> {
+        let (sender, receiver) = mpsc::channel();
+        self.sender
+            .send(Command::Flush(sender))
+            .map_err(|_| recording_stopped())?;
+        receiver.recv().map_err(|_| recording_stopped())?
+    }
+
+    // The bytes of a whole frame, as sent or received on the wire
+    pub(crate) fn record(&self, direction: FrameDirection, bytes: Vec<u8>) {
+        if self.failed.load(Ordering::Relaxed) {
+            return;
+        }
+        let _ = self.sender.send(Command::Record(Record {
+            direction,
+            timestamp: SystemTime::now(),
+            payload_policy: self.payload_policy,
+            bytes,
+        }));
+    }
+}
+
+impl fmt::Debug for FrameRecorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FrameRecorder")
+            .field("payload_policy", &self.payload_policy)
+            .field("failed", &self.failed.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+// Runs until every handle to the recorder got dropped
+fn write_records(mut writer: impl Write, receiver: Receiver<Command>, failed: Arc<AtomicBool>) {
+    for command in receiver {
+        match command {
+            Command::Record(record) => {
+                if failed.load(Ordering::Relaxed) {
+                    continue;
+                }
+                if let Err(err) = write_record(&mut writer, record) {
+                    // Don't fail the connection because of the recorder, but don't spam the logs either
+                    error!(?err, "failed to record frame, recording stopped");
+                    failed.store(true, Ordering::Relaxed);
+                }
+            }
+            Command::Flush(sender) => {
+                let _ = sender.send(if failed.load(Ordering::Relaxed) {
+                    Err(recording_stopped())
+                } else {
+                    writer.flush()
+                });
+            }
+        }
+    }
+    let _ = writer.flush();
+}
+
+fn write_record(writer: &mut impl Write, mut record: Record) -> io::Result<()> {
+    let bytes = &mut record.bytes;
+    let channel_id = match bytes.get(1..3) {
+        Some(channel_id) => ChannelId::from_be_bytes(channel_id.try_into().unwrap()),
+        None => return Err(invalid_data("truncated frame")),
+    };
+    let truncated = apply_payload_policy(record.payload_policy, bytes);
+    let timestamp = record
+        .timestamp
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64;
+    writer.write_all(&[match record.direction {
+        FrameDirection::Inbound => 0,
+        FrameDirection::Outbound => 1,
+    }])?;
+    writer.write_all(&timestamp.to_be_bytes())?;
+    writer.write_all(&channel_id.to_be_bytes())?;
+    writer.write_all(&(truncated as u32).to_be_bytes())?;
+    writer.write_all(&(bytes.len() as u32).to_be_bytes())?;
+    writer.write_all(bytes)
+}
+
+// Body frames are made of the type, the channel id, the payload size, the payload and the
+// frame end octet. Returns the number of payload bytes dropped.
+fn apply_payload_policy(payload_policy: PayloadPolicy, bytes: &mut Vec<u8>) -> usize {
+    const HEADER_SIZE: usize = 7;
+    if bytes.first() != Some(&constants::FRAME_BODY) || bytes.len() <= HEADER_SIZE {
+        return 0;
+    }
+    let payload = HEADER_SIZE..bytes.len() - 1;
+    match payload_policy {
+        PayloadPolicy::Keep => 0,
+        PayloadPolicy::Truncate(max) if payload.len() > max => {
+            bytes.drain(HEADER_SIZE + max..payload.end);
+            bytes[3..HEADER_SIZE].copy_from_slice(&(max as u32).to_be_bytes());
+            payload.len() - max
+        }
+        PayloadPolicy::Truncate(_) => 0,
+        PayloadPolicy::Redact => {
+            bytes[payload].fill(0);
+            0
+        }
+    }
+}
+
+fn recording_stopped() -> io::Error {
+    io::Error::other("frame recording stopped")
+}
+
+/// Read the records written by a [`FrameRecorder`].
+///
+/// ```rust,no_run
+/// use lapin::recorder::{FrameDirection, FrameRecordReader};
+///
+/// # fn run() -> std::io::Result<()> {
+/// for record in FrameRecordReader::open("/tmp/lapin.rec")? {
+///     let record = record?;
+///     if record.direction == FrameDirection::Inbound {
+///         println!("{:?}: {:?}", record.timestamp, record.frame);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct FrameRecordReader<R> {
+    reader: R,
+}
+
+impl<R: Read> FrameRecordReader<R> {
+    /// Read the records from the given reader, checking the recording header.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0; MAGIC.len() + 1];
+        reader.read_exact(&mut header)?;
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(invalid_data("not a lapin frames recording"));
+        }
+        if header[MAGIC.len()] != VERSION {
+            return Err(invalid_data(format!(
+                "unsupported recording version: {}",
+                header[MAGIC.len()]
+            )));
+        }
+        Ok(Self { reader })
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<FrameRecord>> {
+        let mut direction = [0; 1];
+        if self.reader.read(&mut direction)? == 0 {
+            return Ok(None);
+        }
+        let direction = match direction[0] {
+            0 => FrameDirection::Inbound,
+            1 => FrameDirection::Outbound,
+            direction => return Err(invalid_data(format!("invalid direction: {direction}"))),
+        };
+        let mut header = [0; 18];
+        self.reader.read_exact(&mut header)?;
+        let timestamp = u64::from_be_bytes(header[0..8].try_into().unwrap());
+        let channel_id = ChannelId::from_be_bytes(header[8..10].try_into().unwrap());
+        let truncated = u32::from_be_bytes(header[10..14].try_into().unwrap()) as usize;
+        let size = u32::from_be_bytes(header[14..18].try_into().unwrap()) as usize;
+        let mut bytes = vec![0; size];
+        self.reader.read_exact(&mut bytes)?;
+        let (_, frame) = parse_frame(&bytes[..])
+            .map_err(|err| invalid_data(format!("failed to parse frame: {err:?}")))?;
+        Ok(Some(FrameRecord {
+            direction,
+            timestamp: UNIX_EPOCH + Duration::from_micros(timestamp),
+            channel_id,
+            truncated,
+            frame,
+        }))
+    }
+}
+
+impl FrameRecordReader<BufReader<File>> {
+    /// Read the records from the given file.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> Iterator for FrameRecordReader<R> {
+    type Item = io::Result<FrameRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
@@ -0,0 +1,70 @@
+use crate::{
+    Channel, Error, Result,
+    topology::{BindingDefinition, ConsumerDefinition, ExchangeDefinition, QueueDefinition},
+    types::ChannelId,
+};
+use async_trait::async_trait;
+
+/// A trait used to hook into the automatic recovery process.
+///
+/// Every method has a default implementation which keeps the default behaviour, so you only need
+/// to implement the ones you're interested in.
+///
+/// The filters are called with the definitions we're about to redeclare while recovering a
+/// channel. Return `false` to skip a definition (it is then forgotten, and won't be recovered
+/// anymore), or modify it in place to alter how it gets redeclared.
+#[async_trait]
+pub trait RecoveryListener: Send + Sync + 'static {
+    /// Called when the connection got lost and before we start recovering it
+    fn before_connection_recovery(&self, _error: &Error) {}
+
+    /// Called once the connection and all its channels have been recovered, or when recovery failed
+    fn after_connection_recovery(&self, _result: &Result<()>) {}
+
+    /// Called before reopening a channel and redeclaring its topology
+    fn before_channel_recovery(&self, _channel: &Channel) {}
+
+    /// Called once a channel has been recovered, or when its recovery failed
+    fn after_channel_recovery(&self, _channel: &Channel, _result: &Result<()>) {}
+
+    /// Filter the exchanges we declared before redeclaring them
+    fn filter_exchange(&self, _channel_id: ChannelId, _exchange: &mut ExchangeDefinition) -> bool {
+        true
+    }
+
+    /// Filter the bindings having the `destination` exchange as destination before redeclaring them
+    fn filter_exchange_binding(
+        &self,
+        _channel_id: ChannelId,
+        _destination: &str,
+        _binding: &mut BindingDefinition,
+    ) -> bool {
+        true
+    }
+
+    /// Filter the queues we declared before redeclaring them
+    fn filter_queue(&self, _channel_id: ChannelId, _queue: &mut QueueDefinition) -> bool {
+        true
+    }
+
+    /// Filter the bindings of the `queue` queue before redeclaring them
+    fn filter_queue_binding(
+        &self,
+        _channel_id: ChannelId,
+        _queue: &str,
+        _binding: &mut BindingDefinition,
+    ) -> bool {
+        true
+    }
+
+    /// Filter the consumers before registering them again. A skipped consumer gets canceled.
+    fn filter_consumer(&self, _channel_id: ChannelId, _consumer: &mut ConsumerDefinition) -> bool {
+        true
+    }
+
+    /// Run custom commands on a channel once its topology has been restored, before the channel
+    /// gets reported as recovered. Returning an error makes the channel recovery fail.
+    async fn after_topology_recovery(&self, _channel: &Channel) -> Result<()> {
+        Ok(())
+    }
+}
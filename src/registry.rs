@@ -1,8 +1,9 @@
 use crate::{
     exchange::ExchangeKind,
     options::{ExchangeDeclareOptions, QueueDeclareOptions},
-    topology::{ExchangeDefinition, QueueDefinition},
-    types::{FieldTable, ShortString},
+    queue::QueueName,
+    topology::{ExchangeDefinition, QosDefinition, QueueDefinition},
+    types::{FieldTable, ShortString, ShortUInt},
 };
 use std::{
     collections::HashMap,
@@ -21,6 +22,14 @@ impl Registry {
         self.lock_inner().queues.values().cloned().collect()
     }
 
+    pub(crate) fn qos_topology(&self) -> QosDefinition {
+        self.lock_inner().qos
+    }
+
+    pub(crate) fn register_qos(&self, prefetch_count: ShortUInt, global: bool) {
+        self.lock_inner().qos.register(prefetch_count, global);
+    }
+
     pub(crate) fn register_exchange(
         &self,
         name: ShortString,
@@ -74,20 +83,45 @@ impl Registry {
         name: ShortString,
         options: QueueDeclareOptions,
         arguments: FieldTable,
-    ) {
+        server_named: bool,
+    ) -> QueueName {
         let mut inner = self.lock_inner();
         if let Some(queue) = inner.queues.get_mut(&name) {
-            queue.set_declared(options, arguments);
+            queue.set_declared(options, arguments, server_named);
         } else {
             inner.queues.insert(
                 name.clone(),
-                QueueDefinition::declared(name, options, arguments),
+                QueueDefinition::declared(name.clone(), options, arguments, server_named),
             );
         }
+        inner
+            .queue_names
+            .entry(name.clone())
+            .or_insert_with(|| QueueName::new(name))
+            .clone()
     }
 
     pub(crate) fn deregister_queue(&self, name: &str) {
-        self.lock_inner().queues.remove(name);
+        let mut inner = self.lock_inner();
+        inner.queues.remove(name);
+        inner.queue_names.remove(name);
+    }
+
+    /// Move the definition of a server-named queue to the name the server gave it on recovery
+    pub(crate) fn rename_queue(&self, old_name: &str, new_name: ShortString) {
+        let mut inner = self.lock_inner();
+        if let Some(mut old) = inner.queues.remove(old_name) {
+            if let Some(queue) = inner.queues.get_mut(&new_name) {
+                queue.merge_bindings(old.bindings);
+            } else {
+                old.name = new_name.clone();
+                inner.queues.insert(new_name.clone(), old);
+            }
+        }
+        if let Some(queue_name) = inner.queue_names.remove(old_name) {
+            queue_name.set(new_name.clone());
+            inner.queue_names.insert(new_name, queue_name);
+        }
     }
 
     pub(crate) fn register_queue_binding(
@@ -125,4 +159,6 @@ impl Registry {
 struct Inner {
     exchanges: HashMap<ShortString, ExchangeDefinition>,
     queues: HashMap<ShortString, QueueDefinition>,
+    queue_names: HashMap<ShortString, QueueName>,
+    qos: QosDefinition,
 }
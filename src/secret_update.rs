@@ -1,6 +1,6 @@
 use crate::{
-    ConnectionStatus, Error, ErrorKind, auth::AuthProvider, internal_rpc::InternalRPCHandle,
-    killswitch::KillSwitch,
+    ConnectionStatus, Error, ErrorKind, auth::AuthProvider, events::EventsSender,
+    internal_rpc::InternalRPCHandle, killswitch::KillSwitch,
 };
 use async_rs::{Runtime, traits::*};
 use std::{fmt, sync::Arc, time::Duration};
@@ -10,6 +10,7 @@ pub struct SecretUpdate<RK: RuntimeKit + Clone + Send + 'static> {
     connection_status: ConnectionStatus,
     runtime: Runtime<RK>,
     provider: Arc<dyn AuthProvider>,
+    events_sender: EventsSender,
     killswitch: KillSwitch,
 }
 
@@ -19,6 +20,7 @@ impl<RK: RuntimeKit + Clone + Send + 'static> Clone for SecretUpdate<RK> {
             connection_status: self.connection_status.clone(),
             runtime: self.runtime.clone(),
             provider: self.provider.clone(),
+            events_sender: self.events_sender.clone(),
             killswitch: self.killswitch.clone(),
         }
     }
@@ -29,11 +31,13 @@ impl<RK: RuntimeKit + Clone + Send + 'static> SecretUpdate<RK> {
         connection_status: ConnectionStatus,
         runtime: Runtime<RK>,
         provider: Arc<dyn AuthProvider>,
+        events_sender: EventsSender,
     ) -> Self {
         Self {
             connection_status,
             runtime,
             provider,
+            events_sender,
             killswitch: KillSwitch::default(),
         }
     }
@@ -44,19 +48,23 @@ impl<RK: RuntimeKit + Clone + Send + 'static> SecretUpdate<RK> {
         self.runtime.spawn(async move {
             while let Some(dur) = secret_update.poll_timeout(&killswitch) {
                 secret_update.runtime.sleep(dur).await;
-                match secret_update
+                let res = match secret_update
                     .provider
                     .refresh()
                     .map_err(|e| Error::from(ErrorKind::AuthProviderError(e)))
                 {
-                    Err(err) => error!(%err, "Failed refreshing secret"),
+                    Err(err) => Err(err),
                     Ok(token) => {
-                        if let Err(err) = internal_rpc
+                        internal_rpc
                             .update_secret(token, "Automatic periodical refresh".into())
                             .await
-                        {
-                            error!(%err, "Failed refreshing secret");
-                        }
+                    }
+                };
+                match res {
+                    Ok(()) => secret_update.events_sender.secret_refreshed(),
+                    Err(err) => {
+                        error!(%err, "Failed refreshing secret");
+                        secret_update.events_sender.secret_refresh_failed(err);
                     }
                 }
             }
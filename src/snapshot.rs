@@ -0,0 +1,75 @@
+//! Inspect what the channels of a connection are doing at runtime, to investigate leaked
+//! channels or stalled consumers for example.
+//!
+//! With the `serde` feature, the snapshots can be serialized, to expose them on an admin
+//! endpoint:
+//!
+//! ```rust,no_run
+//! # async fn dump(connection: &lapin::Connection) {
+//! let snapshot = connection.snapshot();
+//! for channel in &snapshot.channels {
+//!     println!(
+//!         "channel {}: {:?}, {} unacked deliveries",
+//!         channel.channel_id, channel.state, channel.unacked_deliveries
+//!     );
+//! }
+//! # }
+//! ```
+
+use crate::{
+    ChannelRecoveryState, ChannelState, ConnectionState,
+    types::{ChannelId, ShortString},
+};
+
+/// The runtime state of a connection, as returned by [`Connection::snapshot`].
+///
+/// [`Connection::snapshot`]: ../struct.Connection.html#method.snapshot
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConnectionSnapshot {
+    /// The state of the connection
+    pub state: ConnectionState,
+    /// The channels of the connection, sorted by id
+    pub channels: Vec<ChannelSnapshot>,
+}
+
+/// The runtime state of a channel, as returned by [`Channel::snapshot`].
+///
+/// [`Channel::snapshot`]: ../struct.Channel.html#method.snapshot
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelSnapshot {
+    /// The id of the channel
+    pub channel_id: ChannelId,
+    /// The state of the channel
+    pub state: ChannelState,
+    /// The progress of the recovery of the channel, if it got recovered at some point
+    pub recovery_state: Option<ChannelRecoveryState>,
+    /// Whether publisher confirms are enabled on this channel
+    pub confirm: bool,
+    /// The published messages waiting for a confirmation from the server
+    pub pending_confirms: usize,
+    /// The consumers registered on this channel
+    pub consumers: Vec<ConsumerSnapshot>,
+    /// The deliveries received on this channel (by consumers or `basic_get`) which were
+    /// neither acked, nacked nor rejected yet
+    pub unacked_deliveries: usize,
+    /// The frames queued to be sent to the server for this channel
+    pub queued_frames: usize,
+    /// The synchronous methods waiting for a reply from the server on this channel
+    pub expected_replies: usize,
+}
+
+/// The runtime state of a consumer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConsumerSnapshot {
+    /// The consumer tag
+    pub tag: ShortString,
+    /// The queue we're consuming
+    pub queue: ShortString,
+    /// Whether the deliveries are automatically acknowledged
+    pub no_ack: bool,
+    /// The deliveries received but not consumed yet
+    pub buffered_deliveries: usize,
+}
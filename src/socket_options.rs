@@ -0,0 +1,306 @@
+use crate::{
+    tcp::{AsyncTcpStream, TLSConfig},
+    uri::{AMQPScheme, AMQPUri},
+};
+use async_rs::{Runtime, traits::*};
+use cfg_if::cfg_if;
+use futures_io::{AsyncRead, AsyncWrite};
+use std::{io, net::SocketAddr, time::Duration};
+use tracing::trace;
+
+pub(crate) trait AsyncSocket: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<S: AsyncRead + AsyncWrite + Send + Unpin> AsyncSocket for S {}
+
+/// Options applied to every TCP socket opened by the default connector, for the initial
+/// connection as well as for reconnections.
+///
+/// Socket level options (nodelay, keepalive and buffer sizes) are only applied on unix
+/// platforms with the `socket-options` feature enabled. Otherwise, the sockets are opened by the
+/// runtime with the system defaults, and a warning is logged when some of these options got set.
+/// The connect timeout is honoured everywhere.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SocketOptions {
+    pub(crate) nodelay: bool,
+    pub(crate) keepalive: Option<Duration>,
+    pub(crate) keepalive_interval: Option<Duration>,
+    pub(crate) keepalive_retries: Option<u32>,
+    pub(crate) send_buffer_size: Option<usize>,
+    pub(crate) recv_buffer_size: Option<usize>,
+    pub(crate) connect_timeout: Option<Duration>,
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        Self {
+            nodelay: true,
+            keepalive: None,
+            keepalive_interval: None,
+            keepalive_retries: None,
+            send_buffer_size: None,
+            recv_buffer_size: None,
+            connect_timeout: None,
+        }
+    }
+}
+
+impl SocketOptions {
+    /// Set `TCP_NODELAY` (enabled by default).
+    #[must_use]
+    pub fn with_nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    /// Enable `SO_KEEPALIVE`, sending probes once the connection has been idle for `time`.
+    #[must_use]
+    pub fn with_keepalive(mut self, time: Duration) -> Self {
+        self.keepalive = Some(time);
+        self
+    }
+
+    /// Interval between two keepalive probes (Linux, Android, BSDs and Apple platforms only).
+    #[must_use]
+    pub fn with_keepalive_interval(mut self, interval: Duration) -> Self {
+        self.keepalive_interval = Some(interval);
+        self
+    }
+
+    /// Number of unanswered keepalive probes before dropping the connection (Linux, Android,
+    /// BSDs and Apple platforms only).
+    #[must_use]
+    pub fn with_keepalive_retries(mut self, retries: u32) -> Self {
+        self.keepalive_retries = Some(retries);
+        self
+    }
+
+    /// Set `SO_SNDBUF`.
+    #[must_use]
+    pub fn with_send_buffer_size(mut self, size: usize) -> Self {
+        self.send_buffer_size = Some(size);
+        self
+    }
+
+    /// Set `SO_RCVBUF`.
+    #[must_use]
+    pub fn with_recv_buffer_size(mut self, size: usize) -> Self {
+        self.recv_buffer_size = Some(size);
+        self
+    }
+
+    /// Abort connection attempts taking longer than `timeout`.
+    ///
+    /// Takes precedence over the `connection_timeout` URI parameter.
+    #[must_use]
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    pub fn nodelay(&self) -> bool {
+        self.nodelay
+    }
+
+    pub fn keepalive(&self) -> Option<Duration> {
+        self.keepalive
+    }
+
+    pub fn keepalive_interval(&self) -> Option<Duration> {
+        self.keepalive_interval
+    }
+
+    pub fn keepalive_retries(&self) -> Option<u32> {
+        self.keepalive_retries
+    }
+
+    pub fn send_buffer_size(&self) -> Option<usize> {
+        self.send_buffer_size
+    }
+
+    pub fn recv_buffer_size(&self) -> Option<usize> {
+        self.recv_buffer_size
+    }
+
+    pub fn connect_timeout(&self) -> Option<Duration> {
+        self.connect_timeout
+    }
+
+    pub(crate) async fn connect<RK: RuntimeKit + Send + Sync>(
+        &self,
+        uri: &AMQPUri,
+        config: TLSConfig<'_, '_, '_>,
+        runtime: &Runtime<RK>,
+    ) -> io::Result<AsyncTcpStream<Box<dyn AsyncSocket>>> {
+        cfg_if! {
+            if #[cfg(feature = "hickory-dns")] {
+                let addrs = async_rs::HickoryToSocketAddrs::new(uri.authority.host.clone(), uri.authority.port);
+            } else {
+                let addrs = runtime.to_socket_addrs((uri.authority.host.clone(), uri.authority.port));
+            }
+        }
+        let timeout = self
+            .connect_timeout
+            .or_else(|| uri.query.connection_timeout.map(Duration::from_millis));
+        let mut error = None;
+        let mut stream = None;
+        for addr in addrs.to_socket_addrs().await? {
+            trace!(%addr, "Connecting.");
+            match self.connect_addr(addr, timeout, runtime).await {
+                Ok(s) => {
+                    stream = Some(s);
+                    break;
+                }
+                Err(err) => error = Some(err),
+            }
+        }
+        let stream = AsyncTcpStream::Plain(stream.ok_or_else(|| {
+            error.unwrap_or_else(|| {
+                io::Error::new(io::ErrorKind::AddrNotAvailable, "couldn't resolve host")
+            })
+        })?);
+        match uri.scheme {
+            AMQPScheme::AMQP => Ok(stream),
+            AMQPScheme::AMQPS => stream.into_tls(&uri.authority.host, config).await,
+        }
+    }
+
+    #[cfg(all(unix, feature = "socket-options"))]
+    async fn connect_addr<RK: RuntimeKit + Send + Sync>(
+        &self,
+        addr: SocketAddr,
+        timeout: Option<Duration>,
+        runtime: &Runtime<RK>,
+    ) -> io::Result<Box<dyn AsyncSocket>> {
+        use socket2::{Domain, Protocol, Socket, Type};
+
+        // The connection is established from the io loop thread, so we can afford a blocking
+        // connect here, which lets us configure the socket before the handshake.
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+        self.apply(&socket)?;
+        match timeout {
+            Some(timeout) => socket.connect_timeout(&addr.into(), timeout)?,
+            None => socket.connect(&addr.into())?,
+        }
+        socket.set_nonblocking(true)?;
+        Ok(Box::new(
+            runtime.register(std::net::TcpStream::from(socket))?,
+        ))
+    }
+
+    #[cfg(not(all(unix, feature = "socket-options")))]
+    async fn connect_addr<RK: RuntimeKit + Send + Sync>(
+        &self,
+        addr: SocketAddr,
+        timeout: Option<Duration>,
+        runtime: &Runtime<RK>,
+    ) -> io::Result<Box<dyn AsyncSocket>> {
+        if self.has_socket_level_options() {
+            tracing::warn!(
+                options = ?self,
+                "Socket level options are only applied on unix platforms with the socket-options feature, ignoring them"
+            );
+        }
+        let connect = std::pin::pin!(runtime.tcp_connect_addr(addr));
+        let Some(timeout) = timeout else {
+            return Ok(Box::new(connect.await?));
+        };
+        let mut sleep = std::pin::pin!(runtime.sleep(timeout));
+        let mut connect = connect;
+        std::future::poll_fn(|cx| {
+            if let std::task::Poll::Ready(res) = connect.as_mut().poll(cx) {
+                return std::task::Poll::Ready(res.map(|s| Box::new(s) as Box<dyn AsyncSocket>));
+            }
+            sleep.as_mut().poll(cx).map(|_| {
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "connection timed out",
+                ))
+            })
+        })
+        .await
+    }
+
+    // nodelay only counts when disabled, as it's enabled by default
+    #[cfg(not(all(unix, feature = "socket-options")))]
+    fn has_socket_level_options(&self) -> bool {
+        !self.nodelay
+            || self.keepalive.is_some()
+            || self.keepalive_interval.is_some()
+            || self.keepalive_retries.is_some()
+            || self.send_buffer_size.is_some()
+            || self.recv_buffer_size.is_some()
+    }
+
+    #[cfg(all(unix, feature = "socket-options"))]
+    fn apply(&self, socket: &socket2::Socket) -> io::Result<()> {
+        socket.set_tcp_nodelay(self.nodelay)?;
+        if let Some(time) = self.keepalive {
+            let keepalive = socket2::TcpKeepalive::new().with_time(time);
+            #[cfg(any(
+                target_os = "android",
+                target_os = "freebsd",
+                target_os = "ios",
+                target_os = "linux",
+                target_os = "macos",
+                target_os = "netbsd",
+            ))]
+            let keepalive = {
+                let mut keepalive = keepalive;
+                if let Some(interval) = self.keepalive_interval {
+                    keepalive = keepalive.with_interval(interval);
+                }
+                if let Some(retries) = self.keepalive_retries {
+                    keepalive = keepalive.with_retries(retries);
+                }
+                keepalive
+            };
+            socket.set_tcp_keepalive(&keepalive)?;
+        }
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix, feature = "socket-options"))]
+mod tests {
+    use super::*;
+    use crate::runtime;
+    use socket2::{Domain, SockRef, Socket, Type};
+    use std::net::TcpListener;
+
+    #[test]
+    fn apply_options() {
+        let runtime = runtime::default_runtime().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let options = SocketOptions::default()
+            .with_keepalive(Duration::from_secs(30))
+            .with_keepalive_interval(Duration::from_secs(5))
+            .with_keepalive_retries(3)
+            .with_recv_buffer_size(64 * 1024)
+            .with_connect_timeout(Duration::from_secs(1));
+        assert_eq!(options.keepalive_interval(), Some(Duration::from_secs(5)));
+        assert_eq!(options.keepalive_retries(), Some(3));
+        let socket = runtime
+            .block_on(options.connect_addr(
+                listener.local_addr().unwrap(),
+                options.connect_timeout(),
+                &runtime,
+            ))
+            .unwrap();
+        drop(socket);
+        let (peer, _) = listener.accept().unwrap();
+        drop(peer);
+
+        let socket = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
+        options.apply(&socket).unwrap();
+        let socket = SockRef::from(&socket);
+        assert!(socket.tcp_nodelay().unwrap());
+        assert!(socket.keepalive().unwrap());
+        assert!(socket.recv_buffer_size().unwrap() >= 64 * 1024);
+    }
+}
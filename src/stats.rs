@@ -0,0 +1,201 @@
+//! Traffic statistics of a connection, always collected and readable through
+//! [`Connection::stats`], without having to register a [`Metrics`] backend.
+//!
+//! ```rust,no_run
+//! # fn check(connection: &lapin::Connection) {
+//! let stats = connection.stats();
+//! println!(
+//!     "{} bytes in, {} bytes out, {} heartbeats received",
+//!     stats.bytes_received,
+//!     stats.bytes_sent,
+//!     stats.heartbeats_received()
+//! );
+//! if let Some(last_read) = stats.last_read {
+//!     println!("last read {:?} ago", last_read.elapsed());
+//! }
+//! # }
+//! ```
+//!
+//! [`Connection::stats`]: ../struct.Connection.html#method.stats
+//! [`Metrics`]: ../metrics/trait.Metrics.html
+
+use crate::{heartbeat::SocketActivity, metrics::FrameType};
+use std::{
+    fmt,
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Instant,
+};
+
+/// The traffic statistics of a connection at some point, as returned by [`Connection::stats`].
+///
+/// The counters are cumulative over the whole life of the connection, including the
+/// reconnections.
+///
+/// With the `serde` feature, the stats can be serialized, except for the instants which only
+/// make sense within the process.
+///
+/// [`Connection::stats`]: ../struct.Connection.html#method.stats
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConnectionStats {
+    /// Bytes read from the socket
+    pub bytes_received: u64,
+    /// Bytes written to the socket
+    pub bytes_sent: u64,
+    /// Frames received from the server
+    pub frames_received: FrameStats,
+    /// Frames sent to the server
+    pub frames_sent: FrameStats,
+    /// The last time we read something from the socket
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub last_read: Option<Instant>,
+    /// The last time we wrote something to the socket
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub last_write: Option<Instant>,
+    /// How many times the connection got successfully recovered
+    pub reconnections: u64,
+    /// Bytes currently waiting in the send buffer to be written to the socket
+    pub send_buffer: usize,
+    /// Bytes currently waiting in the receive buffer to be parsed
+    pub receive_buffer: usize,
+}
+
+impl ConnectionStats {
+    /// Heartbeats sent to the server
+    pub fn heartbeats_sent(&self) -> u64 {
+        self.frames_sent.heartbeat
+    }
+
+    /// Heartbeats received from the server
+    pub fn heartbeats_received(&self) -> u64 {
+        self.frames_received.heartbeat
+    }
+}
+
+/// Frames counts, by frame type.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameStats {
+    /// Protocol headers, sent when opening the connection
+    pub protocol_header: u64,
+    /// Method frames
+    pub method: u64,
+    /// Content header frames
+    pub header: u64,
+    /// Content body frames
+    pub body: u64,
+    /// Heartbeat frames
+    pub heartbeat: u64,
+}
+
+impl FrameStats {
+    /// The number of frames, of any type.
+    pub fn total(&self) -> u64 {
+        self.protocol_header + self.method + self.header + self.body + self.heartbeat
+    }
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct TrafficStats(Arc<Inner>);
+
+#[derive(Default)]
+struct Inner {
+    bytes_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    frames_received: FrameCounters,
+    frames_sent: FrameCounters,
+    socket_activity: OnceLock<SocketActivity>,
+    reconnections: AtomicU64,
+    send_buffer: AtomicUsize,
+    receive_buffer: AtomicUsize,
+}
+
+impl TrafficStats {
+    pub(crate) fn bytes_received(&self, bytes: usize) {
+        self.0
+            .bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn bytes_sent(&self, bytes: usize) {
+        self.0.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn frame_received(&self, frame_type: FrameType) {
+        self.0.frames_received.increment(frame_type);
+    }
+
+    pub(crate) fn frame_sent(&self, frame_type: FrameType) {
+        self.0.frames_sent.increment(frame_type);
+    }
+
+    // The heartbeat already tracks the last socket activity
+    pub(crate) fn set_socket_activity(&self, socket_activity: SocketActivity) {
+        let _ = self.0.socket_activity.set(socket_activity);
+    }
+
+    pub(crate) fn reconnected(&self) {
+        self.0.reconnections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_buffers(&self, send_buffer: usize, receive_buffer: usize) {
+        self.0.send_buffer.store(send_buffer, Ordering::Relaxed);
+        self.0
+            .receive_buffer
+            .store(receive_buffer, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> ConnectionStats {
+        let socket_activity = self.0.socket_activity.get();
+        ConnectionStats {
+            bytes_received: self.0.bytes_received.load(Ordering::Relaxed),
+            bytes_sent: self.0.bytes_sent.load(Ordering::Relaxed),
+            frames_received: self.0.frames_received.snapshot(),
+            frames_sent: self.0.frames_sent.snapshot(),
+            last_read: socket_activity.map(SocketActivity::last_read),
+            last_write: socket_activity.map(SocketActivity::last_write),
+            reconnections: self.0.reconnections.load(Ordering::Relaxed),
+            send_buffer: self.0.send_buffer.load(Ordering::Relaxed),
+            receive_buffer: self.0.receive_buffer.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl fmt::Debug for TrafficStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.snapshot(), f)
+    }
+}
+
+#[derive(Default)]
+struct FrameCounters([AtomicU64; 5]);
+
+impl FrameCounters {
+    fn increment(&self, frame_type: FrameType) {
+        let index = match frame_type {
+            FrameType::ProtocolHeader => 0,
+            FrameType::Method => 1,
+            FrameType::Header => 2,
+            FrameType::Body => 3,
+            FrameType::Heartbeat => 4,
+        };
+        self.0[index].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> FrameStats {
+        let [protocol_header, method, header, body, heartbeat] = self
+            .0
+            .each_ref()
+            .map(|counter| counter.load(Ordering::Relaxed));
+        FrameStats {
+            protocol_header,
+            method,
+            header,
+            body,
+            heartbeat,
+        }
+    }
+}
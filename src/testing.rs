@@ -0,0 +1,370 @@
+//! Test helpers, to exercise code using lapin without a running server.
+//!
+//! ## Fake broker
+//!
+//! The [`FakeBroker`] speaks AMQP 0.9.1 over an in-process stream plugged into
+//! [`Connection::connector`], so the whole client stack (framing, channels, consumers,
+//! publisher confirms, recovery, ...) gets exercised exactly as with a real broker.
+//!
+//! It supports:
+//!
+//! * exchanges (`direct`, `fanout`, `topic` and `headers`) including the predeclared `amq.*` ones,
+//!   exchange to exchange bindings and the default exchange
+//! * queues, with server-named, exclusive and auto-delete queues, binding, purging and deleting
+//! * publishing, with mandatory messages getting returned when unroutable
+//! * consuming (with `basic.qos` prefetch), `basic.get`, acks, nacks, rejects and `basic.recover`
+//! * publisher confirms
+//!
+//! Declarations are checked for equivalence and errors are reported the way RabbitMQ does,
+//! closing the channel or the connection with the same reply codes.
+//!
+//! Messages are kept in memory only and there is no notion of vhosts, users or permissions.
+//! `tx.select`, `tx.commit` and `tx.rollback` are accepted but published messages and acks are
+//! applied immediately.
+//!
+//! ```rust
+//! use lapin::{BasicProperties, options::*, testing::FakeBroker, types::FieldTable};
+//!
+//! # async fn run() -> lapin::Result<()> {
+//! let broker = FakeBroker::new();
+//! let connection = broker.connect(Default::default()).await?;
+//! let channel = connection.create_channel().await?;
+//! channel
+//!     .queue_declare("hello".into(), QueueDeclareOptions::default(), FieldTable::default())
+//!     .await?;
+//! channel
+//!     .basic_publish(
+//!         "".into(),
+//!         "hello".into(),
+//!         BasicPublishOptions::default(),
+//!         b"Hello world!",
+//!         BasicProperties::default(),
+//!     )
+//!     .await?
+//!     .await?;
+//! assert_eq!(broker.message_count("hello"), Some(1));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Fault injection
+//!
+//! The [`FaultInjector`] wraps the streams used by a connection, to disconnect them, add some
+//! latency, stall them or corrupt the frames at will, from the test itself. This is useful to
+//! check how the code behaves with automatic recovery for example.
+//!
+//! ## Mocking the application side
+//!
+//! Application code publishing through a [`Publisher`] or acknowledging deliveries through an
+//! [`Acknowledger`] can be unit tested without any connection, using a [`RecordingPublisher`]
+//! and a [`RecordingAcknowledger`] to check what it did.
+//!
+//! [`Publisher`]: crate::Publisher
+//! [`Acknowledger`]: crate::Acknowledger
+//!
+//! ## Scripted peer
+//!
+//! To test how some code reacts to protocol edge cases (the server closing the connection in the
+//! middle of a publish, nacking confirms, pausing the flow of a channel, cancelling a consumer...),
+//! the [`ScriptedPeer`] plays a [`Script`] of [`AMQPFrame`]s instead, checking what the client
+//! sends and sending back exactly what it was told to.
+//!
+//! ```rust
+//! use lapin::{
+//!     protocol::{AMQPClass, channel},
+//!     testing::{Script, ScriptedPeer},
+//! };
+//!
+//! # async fn run() -> lapin::Result<()> {
+//! let peer = ScriptedPeer::new(
+//!     Script::new()
+//!         .with_handshake()
+//!         .expect_method(1, |method| {
+//!             matches!(method, AMQPClass::Channel(channel::AMQPMethod::Open(_)))
+//!         })
+//!         .send_method(1, AMQPClass::Channel(channel::AMQPMethod::OpenOk(channel::OpenOk {})))
+//!         .send_method(
+//!             1,
+//!             AMQPClass::Channel(channel::AMQPMethod::Flow(channel::Flow { active: false })),
+//!         ),
+//! );
+//! let connection = peer.connect(Default::default()).await?;
+//! let _channel = connection.create_channel().await?;
+//! peer.finished().await;
+//! peer.assert_finished();
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A script can also be built from a recording made with a [`FrameRecorder`] using
+//! [`Script::replay`], to reproduce on the client side a bug seen in production.
+//!
+//! [`FrameRecorder`]: crate::recorder::FrameRecorder
+
+use crate::{Connection, ConnectionProperties, Result, parsing::ParsingContext, uri::AMQPUri};
+use amq_protocol::frame::{gen_frame, parse_frame, parsing::traits::Input};
+use async_rs::{Runtime, traits::*};
+use broker::{ConnectionId, State};
+use futures_io::{AsyncRead, AsyncWrite};
+use std::{
+    collections::VecDeque,
+    fmt, io,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+};
+
+pub use amq_protocol::frame::{AMQPContentHeader, AMQPFrame, ProtocolVersion};
+pub use faults::{FaultInjector, FaultyStream};
+pub use recording::{Acknowledgement, PublishedMessage, RecordingAcknowledger, RecordingPublisher};
+pub use script::{Script, ScriptedPeer, ScriptedStream};
+
+mod broker;
+mod faults;
+mod recording;
+mod script;
+
+/// An in-memory AMQP broker.
+///
+/// Cloning it gives another handle to the same broker, so that several connections can talk to
+/// each other through it.
+#[derive(Clone)]
+pub struct FakeBroker {
+    state: Arc<Mutex<State>>,
+}
+
+impl FakeBroker {
+    /// Create a new broker, with only the predeclared exchanges.
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State::new())),
+        }
+    }
+
+    /// Connect to the broker, using the default runtime.
+    pub async fn connect(&self, options: ConnectionProperties) -> Result<Connection> {
+        self.connect_with_runtime(options, crate::runtime::default_runtime()?)
+            .await
+    }
+
+    /// Connect to the broker, using the given runtime.
+    ///
+    /// Each (re)connection attempt opens a new stream to the broker, so automatic recovery
+    /// works as with a real server.
+    pub async fn connect_with_runtime<RK: RuntimeKit + Clone + Send + 'static>(
+        &self,
+        options: ConnectionProperties,
+        runtime: Runtime<RK>,
+    ) -> Result<Connection> {
+        let broker = self.clone();
+        Connection::connector(
+            AMQPUri::default(),
+            runtime,
+            async move |_, _| Ok(broker.stream()),
+            options,
+        )
+        .await
+    }
+
+    /// Open a new stream to the broker, to be used with [`Connection::connector`].
+    pub fn stream(&self) -> BrokerStream {
+        let pipe = Arc::new(Pipe::default());
+        let connection = self.lock().open_connection(pipe.clone());
+        BrokerStream {
+            broker: self.clone(),
+            connection,
+            pipe,
+            incoming: Vec::new(),
+        }
+    }
+
+    /// Abruptly close all the connections to the broker, as if the server went down.
+    ///
+    /// The queues and exchanges are kept, except for the exclusive queues.
+    pub fn disconnect_all(&self) {
+        self.lock().close_all_connections();
+    }
+
+    /// The number of messages ready for delivery in the given queue, if it exists.
+    pub fn message_count(&self, queue: &str) -> Option<usize> {
+        self.lock().message_count(queue)
+    }
+
+    /// The number of consumers of the given queue, if it exists.
+    pub fn consumer_count(&self, queue: &str) -> Option<usize> {
+        self.lock().consumer_count(queue)
+    }
+
+    /// Whether the given exchange exists.
+    pub fn has_exchange(&self, exchange: &str) -> bool {
+        self.lock().has_exchange(exchange)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for FakeBroker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for FakeBroker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FakeBroker").finish_non_exhaustive()
+    }
+}
+
+/// A client stream connected to a [`FakeBroker`].
+///
+/// The frames written to it are handled synchronously by the broker and its replies are
+/// available for reading right away, so no background task is needed and it works with any
+/// runtime. Dropping it closes the connection on the broker side.
+pub struct BrokerStream {
+    broker: FakeBroker,
+    connection: ConnectionId,
+    pipe: Arc<Pipe>,
+    incoming: Vec<u8>,
+}
+
+impl BrokerStream {
+    fn handle_incoming(&mut self) -> io::Result<()> {
+        let res = parse_frames(&mut self.incoming, |frame| {
+            self.broker.lock().handle_frame(self.connection, frame)
+        });
+        if res.is_err() {
+            self.disconnect();
+        }
+        res
+    }
+
+    fn disconnect(&self) {
+        self.broker.lock().close_connection(self.connection);
+    }
+}
+
+impl AsyncRead for BrokerStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.pipe.poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for BrokerStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if self.pipe.is_closed() {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        self.incoming.extend_from_slice(buf);
+        Poll::Ready(self.handle_incoming().map(|()| buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.disconnect();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for BrokerStream {
+    fn drop(&mut self) {
+        self.disconnect();
+    }
+}
+
+impl fmt::Debug for BrokerStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BrokerStream")
+            .field("connection", &self.connection)
+            .finish_non_exhaustive()
+    }
+}
+
+// The bytes sent to a client, waiting to be read
+#[derive(Default)]
+struct Pipe(Mutex<PipeInner>);
+
+#[derive(Default)]
+struct PipeInner {
+    data: VecDeque<u8>,
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+impl Pipe {
+    fn push_frame(&self, frame: &AMQPFrame) {
+        let bytes = gen_frame(frame)(Vec::new().into())
+            .expect("failed to serialize frame")
+            .write;
+        let mut inner = self.lock();
+        if inner.closed {
+            return;
+        }
+        inner.data.extend(bytes);
+        if let Some(waker) = inner.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn close(&self) {
+        let mut inner = self.lock();
+        inner.closed = true;
+        if let Some(waker) = inner.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.lock().closed
+    }
+
+    fn poll_read(&self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let mut inner = self.lock();
+        if inner.data.is_empty() {
+            if inner.closed {
+                return Poll::Ready(Ok(0));
+            }
+            inner.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let len = buf.len().min(inner.data.len());
+        for (dst, src) in buf.iter_mut().zip(inner.data.drain(..len)) {
+            *dst = src;
+        }
+        Poll::Ready(Ok(len))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PipeInner> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// Handle all the complete frames written by a client so far
+fn parse_frames(incoming: &mut Vec<u8>, mut handle: impl FnMut(AMQPFrame)) -> io::Result<()> {
+    loop {
+        let (consumed, frame) = match parse_frame(ParsingContext::from(&incoming[..])) {
+            Ok((rest, frame)) => (incoming.len() - rest.input_len(), frame),
+            Err(err) if err.is_incomplete() => return Ok(()),
+            Err(err) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("failed to parse frame: {err:?}"),
+                ));
+            }
+        };
+        incoming.drain(..consumed);
+        handle(frame);
+    }
+}
@@ -0,0 +1,1480 @@
+use super::Pipe;
+use crate::{
+    BasicProperties,
+    protocol::{
+        AMQPClass, AMQPError, AMQPErrorKind, AMQPHardError, AMQPSoftError, basic, channel, confirm,
+        connection, exchange, queue, tx,
+    },
+    types::{AMQPValue, ChannelId, DeliveryTag, FieldTable, ShortString},
+};
+use amq_protocol::frame::{AMQPContentHeader, AMQPFrame, ProtocolVersion};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    sync::Arc,
+};
+use tracing::trace;
+
+pub(super) type ConnectionId = u64;
+
+const CHANNEL_MAX: u16 = 2047;
+const FRAME_MAX: u32 = 131_072;
+const VHOST: &str = "/";
+
+#[derive(Default)]
+pub(super) struct State {
+    exchanges: HashMap<ShortString, Exchange>,
+    queues: HashMap<ShortString, Queue>,
+    connections: HashMap<ConnectionId, ConnectionState>,
+    next_connection_id: ConnectionId,
+    next_name_id: u64,
+}
+
+struct Exchange {
+    kind: ShortString,
+    durable: bool,
+    auto_delete: bool,
+    internal: bool,
+    arguments: FieldTable,
+    bindings: Vec<Binding>,
+}
+
+#[derive(PartialEq)]
+struct Binding {
+    destination: Destination,
+    routing_key: ShortString,
+    arguments: FieldTable,
+}
+
+#[derive(Clone, PartialEq)]
+enum Destination {
+    Exchange(ShortString),
+    Queue(ShortString),
+}
+
+struct Queue {
+    durable: bool,
+    exclusive: Option<ConnectionId>,
+    auto_delete: bool,
+    arguments: FieldTable,
+    messages: VecDeque<Message>,
+    consumers: Vec<Consumer>,
+    next_consumer: usize,
+}
+
+#[derive(Clone)]
+struct Consumer {
+    tag: ShortString,
+    connection: ConnectionId,
+    channel: ChannelId,
+    no_ack: bool,
+}
+
+#[derive(Clone)]
+struct Message {
+    exchange: ShortString,
+    routing_key: ShortString,
+    properties: BasicProperties,
+    body: Vec<u8>,
+    redelivered: bool,
+}
+
+struct ConnectionState {
+    pipe: Arc<Pipe>,
+    frame_max: u32,
+    heartbeat: bool,
+    channels: HashMap<ChannelId, ChannelState>,
+    closing: bool,
+}
+
+#[derive(Default)]
+struct ChannelState {
+    closing: bool,
+    confirm: bool,
+    published: DeliveryTag,
+    delivered: DeliveryTag,
+    prefetch_count: u16,
+    unacked: BTreeMap<DeliveryTag, Unacked>,
+    publish: Option<Publish>,
+}
+
+struct Unacked {
+    queue: ShortString,
+    message: Message,
+}
+
+struct Publish {
+    exchange: ShortString,
+    routing_key: ShortString,
+    mandatory: bool,
+    content: Option<(u64, BasicProperties)>,
+    body: Vec<u8>,
+}
+
+impl State {
+    pub(super) fn new() -> Self {
+        let mut state = Self::default();
+        state.declare_exchange("", "direct");
+        for kind in ["direct", "fanout", "topic", "headers"] {
+            state.declare_exchange(&format!("amq.{kind}"), kind);
+        }
+        state.declare_exchange("amq.match", "headers");
+        state
+    }
+
+    fn declare_exchange(&mut self, name: &str, kind: &str) {
+        self.exchanges.insert(
+            name.into(),
+            Exchange {
+                kind: kind.into(),
+                durable: true,
+                auto_delete: false,
+                internal: false,
+                arguments: FieldTable::default(),
+                bindings: Vec::new(),
+            },
+        );
+    }
+
+    pub(super) fn open_connection(&mut self, pipe: Arc<Pipe>) -> ConnectionId {
+        self.next_connection_id += 1;
+        self.connections.insert(
+            self.next_connection_id,
+            ConnectionState {
+                pipe,
+                frame_max: FRAME_MAX,
+                heartbeat: false,
+                channels: HashMap::new(),
+                closing: false,
+            },
+        );
+        self.next_connection_id
+    }
+
+    pub(super) fn close_connection(&mut self, connection: ConnectionId) {
+        let Some(conn) = self.connections.remove(&connection) else {
+            return;
+        };
+        conn.pipe.close();
+        let mut touched = BTreeSet::new();
+        for channel in conn.channels.into_values() {
+            self.requeue(channel.unacked, &mut touched);
+        }
+        self.remove_consumers(|consumer| consumer.connection == connection);
+        self.queues
+            .retain(|_, queue| queue.exclusive != Some(connection));
+        self.forget_missing_queues();
+        self.dispatch(touched);
+    }
+
+    pub(super) fn close_all_connections(&mut self) {
+        let connections = self.connections.keys().copied().collect::<Vec<_>>();
+        for connection in connections {
+            self.close_connection(connection);
+        }
+    }
+
+    pub(super) fn message_count(&self, queue: &str) -> Option<usize> {
+        self.queues.get(queue).map(|queue| queue.messages.len())
+    }
+
+    pub(super) fn consumer_count(&self, queue: &str) -> Option<usize> {
+        self.queues.get(queue).map(|queue| queue.consumers.len())
+    }
+
+    pub(super) fn has_exchange(&self, exchange: &str) -> bool {
+        self.exchanges.contains_key(exchange)
+    }
+
+    pub(super) fn handle_frame(&mut self, connection: ConnectionId, frame: AMQPFrame) {
+        trace!(%connection, ?frame, "fake broker received frame");
+        match frame {
+            AMQPFrame::ProtocolHeader(version) => {
+                if version != ProtocolVersion::amqp_0_9_1() {
+                    self.send(
+                        connection,
+                        AMQPFrame::ProtocolHeader(ProtocolVersion::amqp_0_9_1()),
+                    );
+                    self.close_connection(connection);
+                    return;
+                }
+                let mut capabilities = FieldTable::default();
+                for capability in [
+                    "publisher_confirms",
+                    "exchange_exchange_bindings",
+                    "basic.nack",
+                    "consumer_cancel_notify",
+                    "per_consumer_qos",
+                ] {
+                    capabilities.insert(capability.into(), AMQPValue::Boolean(true));
+                }
+                let mut server_properties = FieldTable::default();
+                server_properties.insert(
+                    "product".into(),
+                    AMQPValue::LongString("lapin fake broker".into()),
+                );
+                server_properties
+                    .insert("capabilities".into(), AMQPValue::FieldTable(capabilities));
+                self.send_method(
+                    connection,
+                    0,
+                    AMQPClass::Connection(connection::AMQPMethod::Start(connection::Start {
+                        version_major: 0,
+                        version_minor: 9,
+                        server_properties,
+                        mechanisms: "PLAIN AMQPLAIN".into(),
+                        locales: "en_US".into(),
+                    })),
+                );
+            }
+            AMQPFrame::Method(channel_id, method) => {
+                if self.ignore(connection, channel_id, &method) {
+                    return;
+                }
+                if channel_id == 0 {
+                    self.handle_connection_method(connection, method);
+                } else {
+                    self.handle_channel_method(connection, channel_id, method);
+                }
+            }
+            AMQPFrame::Header(channel_id, header) => {
+                self.handle_content_header(connection, channel_id, header)
+            }
+            AMQPFrame::Body(channel_id, payload) => {
+                self.handle_body(connection, channel_id, payload)
+            }
+            AMQPFrame::Heartbeat => {
+                // We have no timer, answer the client's heartbeats to keep it happy
+                if self
+                    .connections
+                    .get(&connection)
+                    .is_some_and(|conn| conn.heartbeat)
+                {
+                    self.send(connection, AMQPFrame::Heartbeat);
+                }
+            }
+            AMQPFrame::InvalidHeartbeat(_) => {}
+        }
+    }
+
+    // Drop what we receive on a closing connection or channel, except for the close handshake
+    fn ignore(&self, connection: ConnectionId, channel_id: ChannelId, method: &AMQPClass) -> bool {
+        let Some(conn) = self.connections.get(&connection) else {
+            return true;
+        };
+        if conn.closing {
+            return !matches!(
+                method,
+                AMQPClass::Connection(
+                    connection::AMQPMethod::Close(_) | connection::AMQPMethod::CloseOk(_)
+                )
+            );
+        }
+        conn.channels.get(&channel_id).is_some_and(|channel| {
+            channel.closing
+                && !matches!(
+                    method,
+                    AMQPClass::Channel(
+                        channel::AMQPMethod::Close(_) | channel::AMQPMethod::CloseOk(_)
+                    )
+                )
+        })
+    }
+
+    fn handle_connection_method(&mut self, connection: ConnectionId, method: AMQPClass) {
+        let reply = match method {
+            AMQPClass::Connection(connection::AMQPMethod::StartOk(_))
+            | AMQPClass::Connection(connection::AMQPMethod::SecureOk(_)) => {
+                connection::AMQPMethod::Tune(connection::Tune {
+                    channel_max: CHANNEL_MAX,
+                    frame_max: FRAME_MAX,
+                    heartbeat: 0,
+                })
+            }
+            AMQPClass::Connection(connection::AMQPMethod::TuneOk(tune_ok)) => {
+                if let Some(conn) = self.connections.get_mut(&connection) {
+                    if tune_ok.frame_max != 0 {
+                        conn.frame_max = tune_ok.frame_max;
+                    }
+                    conn.heartbeat = tune_ok.heartbeat != 0;
+                }
+                return;
+            }
+            AMQPClass::Connection(connection::AMQPMethod::Open(_)) => {
+                connection::AMQPMethod::OpenOk(connection::OpenOk {})
+            }
+            AMQPClass::Connection(connection::AMQPMethod::UpdateSecret(_)) => {
+                connection::AMQPMethod::UpdateSecretOk(connection::UpdateSecretOk {})
+            }
+            AMQPClass::Connection(connection::AMQPMethod::Close(_)) => {
+                self.send_method(
+                    connection,
+                    0,
+                    AMQPClass::Connection(connection::AMQPMethod::CloseOk(connection::CloseOk {})),
+                );
+                self.close_connection(connection);
+                return;
+            }
+            AMQPClass::Connection(connection::AMQPMethod::CloseOk(_)) => {
+                self.close_connection(connection);
+                return;
+            }
+            method => {
+                return self.connection_error(
+                    connection,
+                    AMQPHardError::COMMANDINVALID,
+                    format!("unexpected method on channel 0: {method:?}"),
+                    &method,
+                );
+            }
+        };
+        self.send_method(connection, 0, AMQPClass::Connection(reply));
+    }
+
+    fn handle_channel_method(
+        &mut self,
+        connection: ConnectionId,
+        channel_id: ChannelId,
+        method: AMQPClass,
+    ) {
+        let opened = self.channel(connection, channel_id).is_some();
+        match (&method, opened) {
+            (AMQPClass::Channel(channel::AMQPMethod::Open(_)), false) => {
+                if let Some(conn) = self.connections.get_mut(&connection) {
+                    conn.channels.insert(channel_id, ChannelState::default());
+                }
+                return self.reply(
+                    connection,
+                    channel_id,
+                    AMQPClass::Channel(channel::AMQPMethod::OpenOk(channel::OpenOk {})),
+                );
+            }
+            (AMQPClass::Channel(channel::AMQPMethod::Open(_)), true) => {
+                return self.connection_error(
+                    connection,
+                    AMQPHardError::CHANNELERROR,
+                    format!("second 'channel.open' seen on channel {channel_id}"),
+                    &method,
+                );
+            }
+            (_, false) => {
+                return self.connection_error(
+                    connection,
+                    AMQPHardError::CHANNELERROR,
+                    format!("expected 'channel.open' on channel {channel_id}"),
+                    &method,
+                );
+            }
+            _ => {}
+        }
+
+        let res = match method.clone() {
+            AMQPClass::Channel(method) => self.handle_channel(connection, channel_id, method),
+            AMQPClass::Exchange(method) => self.handle_exchange(connection, channel_id, method),
+            AMQPClass::Queue(method) => self.handle_queue(connection, channel_id, method),
+            AMQPClass::Basic(method) => self.handle_basic(connection, channel_id, method),
+            AMQPClass::Confirm(confirm::AMQPMethod::Select(select)) => {
+                if let Some(channel) = self.channel(connection, channel_id) {
+                    channel.confirm = true;
+                }
+                self.reply_unless(
+                    select.nowait,
+                    connection,
+                    channel_id,
+                    AMQPClass::Confirm(confirm::AMQPMethod::SelectOk(confirm::SelectOk {})),
+                );
+                Ok(())
+            }
+            AMQPClass::Tx(method) => {
+                let reply = match method {
+                    tx::AMQPMethod::Select(_) => tx::AMQPMethod::SelectOk(tx::SelectOk {}),
+                    tx::AMQPMethod::Commit(_) => tx::AMQPMethod::CommitOk(tx::CommitOk {}),
+                    tx::AMQPMethod::Rollback(_) => tx::AMQPMethod::RollbackOk(tx::RollbackOk {}),
+                    method => {
+                        return self.not_implemented(connection, &AMQPClass::Tx(method));
+                    }
+                };
+                self.reply(connection, channel_id, AMQPClass::Tx(reply));
+                Ok(())
+            }
+            _ => return self.not_implemented(connection, &method),
+        };
+        if let Err((kind, message)) = res {
+            self.channel_error(connection, channel_id, kind, message, &method);
+        }
+    }
+
+    fn handle_channel(
+        &mut self,
+        connection: ConnectionId,
+        channel_id: ChannelId,
+        method: channel::AMQPMethod,
+    ) -> ChannelResult {
+        match method {
+            channel::AMQPMethod::Flow(flow) => self.reply(
+                connection,
+                channel_id,
+                AMQPClass::Channel(channel::AMQPMethod::FlowOk(channel::FlowOk {
+                    active: flow.active,
+                })),
+            ),
+            channel::AMQPMethod::Close(_) => {
+                self.close_channel(connection, channel_id);
+                self.reply(
+                    connection,
+                    channel_id,
+                    AMQPClass::Channel(channel::AMQPMethod::CloseOk(channel::CloseOk {})),
+                );
+            }
+            channel::AMQPMethod::CloseOk(_) => self.close_channel(connection, channel_id),
+            method => {
+                return Err((
+                    AMQPHardError::COMMANDINVALID.into(),
+                    format!("unexpected method: {method:?}"),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_exchange(
+        &mut self,
+        connection: ConnectionId,
+        channel_id: ChannelId,
+        method: exchange::AMQPMethod,
+    ) -> ChannelResult {
+        let (nowait, reply) = match method {
+            exchange::AMQPMethod::Declare(declare) => {
+                match self.exchanges.get(&declare.exchange) {
+                    Some(existing) if !declare.passive => {
+                        let mismatch = if existing.kind != declare.kind {
+                            Some("type")
+                        } else if existing.durable != declare.durable {
+                            Some("durable")
+                        } else if existing.auto_delete != declare.auto_delete {
+                            Some("auto_delete")
+                        } else if existing.internal != declare.internal {
+                            Some("internal")
+                        } else if existing.arguments != declare.arguments {
+                            Some("arguments")
+                        } else {
+                            None
+                        };
+                        if let Some(arg) = mismatch {
+                            return Err(precondition_failed(format!(
+                                "inequivalent arg '{arg}' for {}",
+                                describe_exchange(&declare.exchange)
+                            )));
+                        }
+                    }
+                    Some(_) => {}
+                    None if declare.passive => {
+                        return Err(not_found(describe_exchange(&declare.exchange)));
+                    }
+                    None => {
+                        if declare.exchange.as_str().starts_with("amq.") {
+                            return Err((
+                                AMQPSoftError::ACCESSREFUSED.into(),
+                                format!(
+                                    "exchange name '{}' contains reserved prefix 'amq.*'",
+                                    declare.exchange
+                                ),
+                            ));
+                        }
+                        self.exchanges.insert(
+                            declare.exchange,
+                            Exchange {
+                                kind: declare.kind,
+                                durable: declare.durable,
+                                auto_delete: declare.auto_delete,
+                                internal: declare.internal,
+                                arguments: declare.arguments,
+                                bindings: Vec::new(),
+                            },
+                        );
+                    }
+                }
+                (
+                    declare.nowait,
+                    exchange::AMQPMethod::DeclareOk(exchange::DeclareOk {}),
+                )
+            }
+            exchange::AMQPMethod::Delete(delete) => {
+                if delete.if_unused
+                    && self.exchanges.values().any(|exchange| {
+                        exchange.bindings.iter().any(|binding| {
+                            binding.destination == Destination::Exchange(delete.exchange.clone())
+                        })
+                    })
+                {
+                    return Err(precondition_failed(format!(
+                        "{} in use",
+                        describe_exchange(&delete.exchange)
+                    )));
+                }
+                self.exchanges.remove(&delete.exchange);
+                self.unbind_all(&Destination::Exchange(delete.exchange));
+                (
+                    delete.nowait,
+                    exchange::AMQPMethod::DeleteOk(exchange::DeleteOk {}),
+                )
+            }
+            exchange::AMQPMethod::Bind(bind) => {
+                self.ensure_exchange(&bind.destination)?;
+                self.bind(
+                    &bind.source,
+                    Binding {
+                        destination: Destination::Exchange(bind.destination),
+                        routing_key: bind.routing_key,
+                        arguments: bind.arguments,
+                    },
+                )?;
+                (
+                    bind.nowait,
+                    exchange::AMQPMethod::BindOk(exchange::BindOk {}),
+                )
+            }
+            exchange::AMQPMethod::Unbind(unbind) => {
+                self.unbind(
+                    &unbind.source,
+                    Binding {
+                        destination: Destination::Exchange(unbind.destination),
+                        routing_key: unbind.routing_key,
+                        arguments: unbind.arguments,
+                    },
+                );
+                (
+                    unbind.nowait,
+                    exchange::AMQPMethod::UnbindOk(exchange::UnbindOk {}),
+                )
+            }
+            method => {
+                return Err((
+                    AMQPHardError::COMMANDINVALID.into(),
+                    format!("unexpected method: {method:?}"),
+                ));
+            }
+        };
+        self.reply_unless(nowait, connection, channel_id, AMQPClass::Exchange(reply));
+        Ok(())
+    }
+
+    fn handle_queue(
+        &mut self,
+        connection: ConnectionId,
+        channel_id: ChannelId,
+        method: queue::AMQPMethod,
+    ) -> ChannelResult {
+        let (nowait, reply) = match method {
+            queue::AMQPMethod::Declare(declare) => {
+                let name = if declare.queue.as_str().is_empty() {
+                    self.generate_name("amq.gen-")
+                } else {
+                    declare.queue
+                };
+                match self.queues.get(&name) {
+                    Some(existing)
+                        if existing.exclusive.is_some_and(|owner| owner != connection) =>
+                    {
+                        return Err((
+                            AMQPSoftError::RESOURCELOCKED.into(),
+                            format!(
+                                "cannot obtain exclusive access to locked {}",
+                                describe_queue(&name)
+                            ),
+                        ));
+                    }
+                    Some(existing) if !declare.passive => {
+                        let mismatch = if existing.durable != declare.durable {
+                            Some("durable")
+                        } else if existing.exclusive.is_some() != declare.exclusive {
+                            Some("exclusive")
+                        } else if existing.auto_delete != declare.auto_delete {
+                            Some("auto_delete")
+                        } else if existing.arguments != declare.arguments {
+                            Some("arguments")
+                        } else {
+                            None
+                        };
+                        if let Some(arg) = mismatch {
+                            return Err(precondition_failed(format!(
+                                "inequivalent arg '{arg}' for {}",
+                                describe_queue(&name)
+                            )));
+                        }
+                    }
+                    Some(_) => {}
+                    None if declare.passive => return Err(not_found(describe_queue(&name))),
+                    None => {
+                        self.queues.insert(
+                            name.clone(),
+                            Queue {
+                                durable: declare.durable,
+                                exclusive: declare.exclusive.then_some(connection),
+                                auto_delete: declare.auto_delete,
+                                arguments: declare.arguments,
+                                messages: VecDeque::new(),
+                                consumers: Vec::new(),
+                                next_consumer: 0,
+                            },
+                        );
+                    }
+                }
+                let queue = &self.queues[&name];
+                let reply = queue::AMQPMethod::DeclareOk(queue::DeclareOk {
+                    message_count: queue.messages.len() as u32,
+                    consumer_count: queue.consumers.len() as u32,
+                    queue: name,
+                });
+                (declare.nowait, reply)
+            }
+            queue::AMQPMethod::Bind(bind) => {
+                self.ensure_queue(&bind.queue)?;
+                self.bind(
+                    &bind.exchange,
+                    Binding {
+                        destination: Destination::Queue(bind.queue),
+                        routing_key: bind.routing_key,
+                        arguments: bind.arguments,
+                    },
+                )?;
+                (bind.nowait, queue::AMQPMethod::BindOk(queue::BindOk {}))
+            }
+            queue::AMQPMethod::Unbind(unbind) => {
+                self.unbind(
+                    &unbind.exchange,
+                    Binding {
+                        destination: Destination::Queue(unbind.queue),
+                        routing_key: unbind.routing_key,
+                        arguments: unbind.arguments,
+                    },
+                );
+                (false, queue::AMQPMethod::UnbindOk(queue::UnbindOk {}))
+            }
+            queue::AMQPMethod::Purge(purge) => {
+                self.ensure_queue(&purge.queue)?;
+                let queue = self.queues.get_mut(&purge.queue).expect("checked above");
+                let message_count = queue.messages.len() as u32;
+                queue.messages.clear();
+                (
+                    purge.nowait,
+                    queue::AMQPMethod::PurgeOk(queue::PurgeOk { message_count }),
+                )
+            }
+            queue::AMQPMethod::Delete(delete) => {
+                let message_count = match self.queues.get(&delete.queue) {
+                    Some(queue) if delete.if_unused && !queue.consumers.is_empty() => {
+                        return Err(precondition_failed(format!(
+                            "{} in use",
+                            describe_queue(&delete.queue)
+                        )));
+                    }
+                    Some(queue) if delete.if_empty && !queue.messages.is_empty() => {
+                        return Err(precondition_failed(format!(
+                            "{} not empty",
+                            describe_queue(&delete.queue)
+                        )));
+                    }
+                    Some(queue) => queue.messages.len() as u32,
+                    None => 0,
+                };
+                self.delete_queue(&delete.queue);
+                (
+                    delete.nowait,
+                    queue::AMQPMethod::DeleteOk(queue::DeleteOk { message_count }),
+                )
+            }
+            method => {
+                return Err((
+                    AMQPHardError::COMMANDINVALID.into(),
+                    format!("unexpected method: {method:?}"),
+                ));
+            }
+        };
+        self.reply_unless(nowait, connection, channel_id, AMQPClass::Queue(reply));
+        Ok(())
+    }
+
+    fn handle_basic(
+        &mut self,
+        connection: ConnectionId,
+        channel_id: ChannelId,
+        method: basic::AMQPMethod,
+    ) -> ChannelResult {
+        match method {
+            basic::AMQPMethod::Qos(qos) => {
+                if let Some(channel) = self.channel(connection, channel_id) {
+                    channel.prefetch_count = qos.prefetch_count;
+                }
+                self.reply(
+                    connection,
+                    channel_id,
+                    AMQPClass::Basic(basic::AMQPMethod::QosOk(basic::QosOk {})),
+                );
+            }
+            basic::AMQPMethod::Consume(consume) => {
+                self.ensure_queue(&consume.queue)?;
+                let tag = if consume.consumer_tag.as_str().is_empty() {
+                    self.generate_name("amq.ctag-")
+                } else {
+                    consume.consumer_tag
+                };
+                let queue = self.queues.get_mut(&consume.queue).expect("checked above");
+                if queue.consumers.iter().any(|consumer| consumer.tag == tag) {
+                    return Err((
+                        AMQPHardError::NOTALLOWED.into(),
+                        format!("attempt to reuse consumer tag '{tag}'"),
+                    ));
+                }
+                queue.consumers.push(Consumer {
+                    tag: tag.clone(),
+                    connection,
+                    channel: channel_id,
+                    no_ack: consume.no_ack,
+                });
+                self.reply_unless(
+                    consume.nowait,
+                    connection,
+                    channel_id,
+                    AMQPClass::Basic(basic::AMQPMethod::ConsumeOk(basic::ConsumeOk {
+                        consumer_tag: tag,
+                    })),
+                );
+                self.dispatch([consume.queue].into());
+            }
+            basic::AMQPMethod::Cancel(cancel) => {
+                let tag = cancel.consumer_tag.clone();
+                self.remove_consumers(|consumer| {
+                    consumer.connection == connection
+                        && consumer.channel == channel_id
+                        && consumer.tag == tag
+                });
+                self.reply_unless(
+                    cancel.nowait,
+                    connection,
+                    channel_id,
+                    AMQPClass::Basic(basic::AMQPMethod::CancelOk(basic::CancelOk {
+                        consumer_tag: cancel.consumer_tag,
+                    })),
+                );
+            }
+            basic::AMQPMethod::Publish(publish) => {
+                self.ensure_exchange(&publish.exchange)?;
+                if let Some(channel) = self.channel(connection, channel_id) {
+                    channel.publish = Some(Publish {
+                        exchange: publish.exchange,
+                        routing_key: publish.routing_key,
+                        mandatory: publish.mandatory,
+                        content: None,
+                        body: Vec::new(),
+                    });
+                }
+            }
+            basic::AMQPMethod::Get(get) => {
+                self.ensure_queue(&get.queue)?;
+                let queue = self.queues.get_mut(&get.queue).expect("checked above");
+                let Some(message) = queue.messages.pop_front() else {
+                    self.reply(
+                        connection,
+                        channel_id,
+                        AMQPClass::Basic(basic::AMQPMethod::GetEmpty(basic::GetEmpty {})),
+                    );
+                    return Ok(());
+                };
+                let message_count = queue.messages.len() as u32;
+                let delivery_tag =
+                    self.track_delivery(connection, channel_id, &get.queue, &message, get.no_ack);
+                self.send_content(
+                    connection,
+                    channel_id,
+                    AMQPClass::Basic(basic::AMQPMethod::GetOk(basic::GetOk {
+                        delivery_tag,
+                        redelivered: message.redelivered,
+                        exchange: message.exchange.clone(),
+                        routing_key: message.routing_key.clone(),
+                        message_count,
+                    })),
+                    &message,
+                );
+            }
+            basic::AMQPMethod::Ack(ack) => {
+                self.settle(connection, channel_id, ack.delivery_tag, ack.multiple, None)?;
+            }
+            basic::AMQPMethod::Nack(nack) => {
+                self.settle(
+                    connection,
+                    channel_id,
+                    nack.delivery_tag,
+                    nack.multiple,
+                    Some(nack.requeue),
+                )?;
+            }
+            basic::AMQPMethod::Reject(reject) => {
+                self.settle(
+                    connection,
+                    channel_id,
+                    reject.delivery_tag,
+                    false,
+                    Some(reject.requeue),
+                )?;
+            }
+            basic::AMQPMethod::Recover(_) | basic::AMQPMethod::RecoverAsync(_) => {
+                let reply = matches!(method, basic::AMQPMethod::Recover(_));
+                let unacked = self
+                    .channel(connection, channel_id)
+                    .map(|channel| std::mem::take(&mut channel.unacked))
+                    .unwrap_or_default();
+                let mut touched = BTreeSet::new();
+                self.requeue(unacked, &mut touched);
+                if reply {
+                    self.reply(
+                        connection,
+                        channel_id,
+                        AMQPClass::Basic(basic::AMQPMethod::RecoverOk(basic::RecoverOk {})),
+                    );
+                }
+                self.dispatch(touched);
+            }
+            method => {
+                return Err((
+                    AMQPHardError::COMMANDINVALID.into(),
+                    format!("unexpected method: {method:?}"),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_content_header(
+        &mut self,
+        connection: ConnectionId,
+        channel_id: ChannelId,
+        header: AMQPContentHeader,
+    ) {
+        let Some(channel) = self.channel(connection, channel_id) else {
+            return;
+        };
+        if channel.closing {
+            return;
+        }
+        match channel.publish.as_mut() {
+            Some(publish) if publish.content.is_none() => {
+                publish.content = Some((header.body_size, header.properties));
+                self.maybe_route(connection, channel_id);
+            }
+            _ => self.connection_error_frame(
+                connection,
+                "unexpected content header".into(),
+                header.class_id,
+            ),
+        }
+    }
+
+    fn handle_body(&mut self, connection: ConnectionId, channel_id: ChannelId, payload: Vec<u8>) {
+        let Some(channel) = self.channel(connection, channel_id) else {
+            return;
+        };
+        if channel.closing {
+            return;
+        }
+        match channel.publish.as_mut() {
+            Some(publish) if publish.content.is_some() => {
+                publish.body.extend(payload);
+                self.maybe_route(connection, channel_id);
+            }
+            _ => self.connection_error_frame(connection, "unexpected content body".into(), 0),
+        }
+    }
+
+    // Route the message being published once we received its whole body
+    fn maybe_route(&mut self, connection: ConnectionId, channel_id: ChannelId) {
+        let Some(channel) = self.channel(connection, channel_id) else {
+            return;
+        };
+        let complete = channel.publish.as_ref().is_some_and(|publish| {
+            publish
+                .content
+                .as_ref()
+                .is_some_and(|(size, _)| publish.body.len() as u64 >= *size)
+        });
+        if !complete {
+            return;
+        }
+        let publish = channel.publish.take().expect("checked above");
+        let confirm = channel.confirm.then(|| {
+            channel.published += 1;
+            channel.published
+        });
+        let (_, properties) = publish.content.expect("checked above");
+        let message = Message {
+            exchange: publish.exchange,
+            routing_key: publish.routing_key,
+            properties,
+            body: publish.body,
+            redelivered: false,
+        };
+
+        let queues = self.route(&message);
+        if queues.is_empty() && publish.mandatory {
+            self.send_content(
+                connection,
+                channel_id,
+                AMQPClass::Basic(basic::AMQPMethod::Return(basic::Return {
+                    reply_code: AMQPSoftError::NOROUTE.get_id(),
+                    reply_text: "NO_ROUTE".into(),
+                    exchange: message.exchange.clone(),
+                    routing_key: message.routing_key.clone(),
+                })),
+                &message,
+            );
+        }
+        for queue in &queues {
+            if let Some(queue) = self.queues.get_mut(queue) {
+                queue.messages.push_back(message.clone());
+            }
+        }
+        if let Some(delivery_tag) = confirm {
+            self.send_method(
+                connection,
+                channel_id,
+                AMQPClass::Basic(basic::AMQPMethod::Ack(basic::Ack {
+                    delivery_tag,
+                    multiple: false,
+                })),
+            );
+        }
+        self.dispatch(queues);
+    }
+
+    fn route(&self, message: &Message) -> BTreeSet<ShortString> {
+        let mut queues = BTreeSet::new();
+        if message.exchange.as_str().is_empty() {
+            if self.queues.contains_key(&message.routing_key) {
+                queues.insert(message.routing_key.clone());
+            }
+            return queues;
+        }
+        let mut visited = BTreeSet::new();
+        let mut exchanges = vec![message.exchange.clone()];
+        while let Some(name) = exchanges.pop() {
+            if !visited.insert(name.clone()) {
+                continue;
+            }
+            let Some(exchange) = self.exchanges.get(&name) else {
+                continue;
+            };
+            let headers = message.properties.headers().as_ref();
+            for binding in exchange.bindings.iter().filter(|binding| {
+                binding_matches(
+                    exchange.kind.as_str(),
+                    binding,
+                    message.routing_key.as_str(),
+                    headers,
+                )
+            }) {
+                match &binding.destination {
+                    Destination::Queue(queue) => {
+                        queues.insert(queue.clone());
+                    }
+                    Destination::Exchange(exchange) => exchanges.push(exchange.clone()),
+                }
+            }
+        }
+        queues
+    }
+
+    // Deliver the pending messages of these queues to their consumers
+    fn dispatch(&mut self, queues: BTreeSet<ShortString>) {
+        for name in queues {
+            while let Some((consumer, message)) = self.next_delivery(name.as_str()) {
+                let delivery_tag = self.track_delivery(
+                    consumer.connection,
+                    consumer.channel,
+                    &name,
+                    &message,
+                    consumer.no_ack,
+                );
+                self.send_content(
+                    consumer.connection,
+                    consumer.channel,
+                    AMQPClass::Basic(basic::AMQPMethod::Deliver(basic::Deliver {
+                        consumer_tag: consumer.tag.clone(),
+                        delivery_tag,
+                        redelivered: message.redelivered,
+                        exchange: message.exchange.clone(),
+                        routing_key: message.routing_key.clone(),
+                    })),
+                    &message,
+                );
+            }
+        }
+    }
+
+    fn dispatch_all(&mut self) {
+        let queues = self.queues.keys().cloned().collect();
+        self.dispatch(queues);
+    }
+
+    // Pick the next consumer with some prefetch room, in a round robin fashion
+    fn next_delivery(&mut self, name: &str) -> Option<(Consumer, Message)> {
+        let Self {
+            queues,
+            connections,
+            ..
+        } = self;
+        let queue = queues.get_mut(name)?;
+        if queue.messages.is_empty() {
+            return None;
+        }
+        let count = queue.consumers.len();
+        let index = (0..count)
+            .map(|offset| (queue.next_consumer + offset) % count)
+            .find(|index| {
+                let consumer = &queue.consumers[*index];
+                consumer.no_ack
+                    || connections
+                        .get(&consumer.connection)
+                        .and_then(|conn| conn.channels.get(&consumer.channel))
+                        .is_some_and(|channel| {
+                            !channel.closing
+                                && (channel.prefetch_count == 0
+                                    || channel.unacked.len() < channel.prefetch_count as usize)
+                        })
+            })?;
+        queue.next_consumer = index + 1;
+        let message = queue.messages.pop_front()?;
+        Some((queue.consumers[index].clone(), message))
+    }
+
+    fn track_delivery(
+        &mut self,
+        connection: ConnectionId,
+        channel_id: ChannelId,
+        queue: &ShortString,
+        message: &Message,
+        no_ack: bool,
+    ) -> DeliveryTag {
+        let Some(channel) = self.channel(connection, channel_id) else {
+            return 0;
+        };
+        channel.delivered += 1;
+        if !no_ack {
+            channel.unacked.insert(
+                channel.delivered,
+                Unacked {
+                    queue: queue.clone(),
+                    message: message.clone(),
+                },
+            );
+        }
+        channel.delivered
+    }
+
+    // Handle an ack (requeue is None), a nack or a reject
+    fn settle(
+        &mut self,
+        connection: ConnectionId,
+        channel_id: ChannelId,
+        delivery_tag: DeliveryTag,
+        multiple: bool,
+        requeue: Option<bool>,
+    ) -> ChannelResult {
+        let Some(channel) = self.channel(connection, channel_id) else {
+            return Ok(());
+        };
+        let settled = if multiple {
+            let remaining = if delivery_tag == 0 {
+                BTreeMap::new()
+            } else {
+                channel.unacked.split_off(&(delivery_tag + 1))
+            };
+            std::mem::replace(&mut channel.unacked, remaining)
+        } else {
+            match channel.unacked.remove(&delivery_tag) {
+                Some(unacked) => [(delivery_tag, unacked)].into(),
+                None => {
+                    return Err(precondition_failed(format!(
+                        "unknown delivery tag {delivery_tag}"
+                    )));
+                }
+            }
+        };
+        if requeue == Some(true) {
+            let mut touched = BTreeSet::new();
+            self.requeue(settled, &mut touched);
+        }
+        // Some prefetch room may have been freed
+        self.dispatch_all();
+        Ok(())
+    }
+
+    fn requeue(
+        &mut self,
+        unacked: BTreeMap<DeliveryTag, Unacked>,
+        touched: &mut BTreeSet<ShortString>,
+    ) {
+        for unacked in unacked.into_values().rev() {
+            if let Some(queue) = self.queues.get_mut(&unacked.queue) {
+                queue.messages.push_front(Message {
+                    redelivered: true,
+                    ..unacked.message
+                });
+                touched.insert(unacked.queue);
+            }
+        }
+    }
+
+    fn close_channel(&mut self, connection: ConnectionId, channel_id: ChannelId) {
+        let Some(channel) = self
+            .connections
+            .get_mut(&connection)
+            .and_then(|conn| conn.channels.remove(&channel_id))
+        else {
+            return;
+        };
+        let mut touched = BTreeSet::new();
+        self.requeue(channel.unacked, &mut touched);
+        self.remove_consumers(|consumer| {
+            consumer.connection == connection && consumer.channel == channel_id
+        });
+        self.dispatch(touched);
+    }
+
+    fn remove_consumers(&mut self, filter: impl Fn(&Consumer) -> bool) {
+        let mut auto_deleted = Vec::new();
+        for (name, queue) in self.queues.iter_mut() {
+            let count = queue.consumers.len();
+            queue.consumers.retain(|consumer| !filter(consumer));
+            if queue.auto_delete && count > 0 && queue.consumers.is_empty() {
+                auto_deleted.push(name.clone());
+            }
+        }
+        for name in auto_deleted {
+            self.delete_queue(&name);
+        }
+    }
+
+    fn delete_queue(&mut self, name: &ShortString) {
+        let Some(queue) = self.queues.remove(name) else {
+            return;
+        };
+        // Let the consumers know their queue is gone
+        for consumer in queue.consumers {
+            self.send_method(
+                consumer.connection,
+                consumer.channel,
+                AMQPClass::Basic(basic::AMQPMethod::Cancel(basic::Cancel {
+                    consumer_tag: consumer.tag,
+                    nowait: true,
+                })),
+            );
+        }
+        self.unbind_all(&Destination::Queue(name.clone()));
+    }
+
+    // Forget the bindings to the exclusive queues which got deleted with their connection
+    fn forget_missing_queues(&mut self) {
+        let Self {
+            exchanges, queues, ..
+        } = self;
+        for exchange in exchanges.values_mut() {
+            exchange
+                .bindings
+                .retain(|binding| match &binding.destination {
+                    Destination::Queue(queue) => queues.contains_key(queue),
+                    Destination::Exchange(_) => true,
+                });
+        }
+    }
+
+    fn bind(&mut self, source: &ShortString, binding: Binding) -> ChannelResult {
+        let Some(exchange) = self.exchanges.get_mut(source) else {
+            return Err(not_found(describe_exchange(source)));
+        };
+        if !exchange.bindings.contains(&binding) {
+            exchange.bindings.push(binding);
+        }
+        Ok(())
+    }
+
+    fn unbind(&mut self, source: &ShortString, binding: Binding) {
+        if let Some(exchange) = self.exchanges.get_mut(source) {
+            exchange.bindings.retain(|existing| *existing != binding);
+        }
+    }
+
+    fn unbind_all(&mut self, destination: &Destination) {
+        for exchange in self.exchanges.values_mut() {
+            exchange
+                .bindings
+                .retain(|binding| binding.destination != *destination);
+        }
+    }
+
+    fn ensure_exchange(&self, name: &ShortString) -> ChannelResult {
+        if self.exchanges.contains_key(name) {
+            Ok(())
+        } else {
+            Err(not_found(describe_exchange(name)))
+        }
+    }
+
+    fn ensure_queue(&self, name: &ShortString) -> ChannelResult {
+        if self.queues.contains_key(name) {
+            Ok(())
+        } else {
+            Err(not_found(describe_queue(name)))
+        }
+    }
+
+    fn generate_name(&mut self, prefix: &str) -> ShortString {
+        self.next_name_id += 1;
+        format!("{prefix}{:016x}", self.next_name_id).into()
+    }
+
+    fn channel(
+        &mut self,
+        connection: ConnectionId,
+        channel_id: ChannelId,
+    ) -> Option<&mut ChannelState> {
+        self.connections
+            .get_mut(&connection)?
+            .channels
+            .get_mut(&channel_id)
+    }
+
+    fn channel_error(
+        &mut self,
+        connection: ConnectionId,
+        channel_id: ChannelId,
+        kind: AMQPErrorKind,
+        message: String,
+        method: &AMQPClass,
+    ) {
+        if let AMQPErrorKind::Hard(kind) = kind {
+            return self.connection_error(connection, kind, message, method);
+        }
+        let error = AMQPError::new(kind, message.into());
+        trace!(%connection, channel=%channel_id, %error, "fake broker closing channel");
+        if let Some(channel) = self.channel(connection, channel_id) {
+            channel.closing = true;
+            let unacked = std::mem::take(&mut channel.unacked);
+            let mut touched = BTreeSet::new();
+            self.requeue(unacked, &mut touched);
+            self.remove_consumers(|consumer| {
+                consumer.connection == connection && consumer.channel == channel_id
+            });
+            self.dispatch(touched);
+        }
+        self.send_method(
+            connection,
+            channel_id,
+            AMQPClass::Channel(channel::AMQPMethod::Close(channel::Close {
+                reply_code: error.get_id(),
+                reply_text: error_text(&error),
+                class_id: method.get_amqp_class_id(),
+                method_id: method.get_amqp_method_id(),
+            })),
+        );
+    }
+
+    fn connection_error(
+        &mut self,
+        connection: ConnectionId,
+        kind: AMQPHardError,
+        message: String,
+        method: &AMQPClass,
+    ) {
+        self.close_with_error(
+            connection,
+            AMQPError::new(kind.into(), message.into()),
+            method.get_amqp_class_id(),
+            method.get_amqp_method_id(),
+        );
+    }
+
+    fn connection_error_frame(&mut self, connection: ConnectionId, message: String, class_id: u16) {
+        self.close_with_error(
+            connection,
+            AMQPError::new(AMQPHardError::UNEXPECTEDFRAME.into(), message.into()),
+            class_id,
+            0,
+        );
+    }
+
+    fn close_with_error(
+        &mut self,
+        connection: ConnectionId,
+        error: AMQPError,
+        class_id: u16,
+        method_id: u16,
+    ) {
+        trace!(%connection, %error, "fake broker closing connection");
+        self.send_method(
+            connection,
+            0,
+            AMQPClass::Connection(connection::AMQPMethod::Close(connection::Close {
+                reply_code: error.get_id(),
+                reply_text: error_text(&error),
+                class_id,
+                method_id,
+            })),
+        );
+        if let Some(conn) = self.connections.get_mut(&connection) {
+            conn.closing = true;
+        }
+    }
+
+    fn not_implemented(&mut self, connection: ConnectionId, method: &AMQPClass) {
+        self.connection_error(
+            connection,
+            AMQPHardError::NOTIMPLEMENTED,
+            format!("{method:?} is not supported by the fake broker"),
+            method,
+        );
+    }
+
+    fn reply(&self, connection: ConnectionId, channel_id: ChannelId, method: AMQPClass) {
+        self.send_method(connection, channel_id, method);
+    }
+
+    fn reply_unless(
+        &self,
+        nowait: bool,
+        connection: ConnectionId,
+        channel_id: ChannelId,
+        method: AMQPClass,
+    ) {
+        if !nowait {
+            self.reply(connection, channel_id, method);
+        }
+    }
+
+    fn send_method(&self, connection: ConnectionId, channel_id: ChannelId, method: AMQPClass) {
+        self.send(connection, AMQPFrame::Method(channel_id, method));
+    }
+
+    fn send_content(
+        &self,
+        connection: ConnectionId,
+        channel_id: ChannelId,
+        method: AMQPClass,
+        message: &Message,
+    ) {
+        let Some(conn) = self.connections.get(&connection) else {
+            return;
+        };
+        self.send(connection, AMQPFrame::Method(channel_id, method));
+        self.send(
+            connection,
+            AMQPFrame::Header(
+                channel_id,
+                AMQPContentHeader {
+                    class_id: 60,
+                    body_size: message.body.len() as u64,
+                    properties: message.properties.clone(),
+                },
+            ),
+        );
+        // 8 bytes of framing: frame type, channel id, payload size and frame end
+        let chunk_size = (conn.frame_max as usize).saturating_sub(8).max(1);
+        for chunk in message.body.chunks(chunk_size) {
+            self.send(connection, AMQPFrame::Body(channel_id, chunk.to_vec()));
+        }
+    }
+
+    fn send(&self, connection: ConnectionId, frame: AMQPFrame) {
+        let Some(conn) = self.connections.get(&connection) else {
+            return;
+        };
+        trace!(%connection, ?frame, "fake broker sending frame");
+        conn.pipe.push_frame(&frame);
+    }
+}
+
+type ChannelResult = Result<(), (AMQPErrorKind, String)>;
+
+fn not_found(what: String) -> (AMQPErrorKind, String) {
+    (AMQPSoftError::NOTFOUND.into(), format!("no {what}"))
+}
+
+fn precondition_failed(message: String) -> (AMQPErrorKind, String) {
+    (AMQPSoftError::PRECONDITIONFAILED.into(), message)
+}
+
+fn describe_exchange(name: &ShortString) -> String {
+    format!("exchange '{name}' in vhost '{VHOST}'")
+}
+
+fn describe_queue(name: &ShortString) -> String {
+    format!("queue '{name}' in vhost '{VHOST}'")
+}
+
+// Mimic RabbitMQ's "NOT_FOUND - no queue 'foo' in vhost '/'"
+fn error_text(error: &AMQPError) -> ShortString {
+    let kind = match error.kind() {
+        AMQPErrorKind::Soft(kind) => kind.to_string(),
+        AMQPErrorKind::Hard(kind) => kind.to_string(),
+    }
+    .replace('-', "_");
+    format!("{kind} - {}", error.get_message()).into()
+}
+
+fn binding_matches(
+    kind: &str,
+    binding: &Binding,
+    routing_key: &str,
+    headers: Option<&FieldTable>,
+) -> bool {
+    match kind {
+        "fanout" => true,
+        "topic" => topic_matches(binding.routing_key.as_str(), routing_key),
+        "headers" => headers_match(&binding.arguments, headers),
+        _ => binding.routing_key.as_str() == routing_key,
+    }
+}
+
+fn topic_matches(pattern: &str, routing_key: &str) -> bool {
+    fn words_match(pattern: &[&str], key: &[&str]) -> bool {
+        match pattern.split_first() {
+            None => key.is_empty(),
+            Some((&"#", rest)) => (0..=key.len()).any(|skip| words_match(rest, &key[skip..])),
+            Some((&word, rest)) => key.split_first().is_some_and(|(&first, key)| {
+                (word == "*" || word == first) && words_match(rest, key)
+            }),
+        }
+    }
+
+    words_match(
+        &pattern.split('.').collect::<Vec<_>>(),
+        &routing_key.split('.').collect::<Vec<_>>(),
+    )
+}
+
+fn headers_match(arguments: &FieldTable, headers: Option<&FieldTable>) -> bool {
+    let any = arguments
+        .inner()
+        .get("x-match")
+        .is_some_and(|value| value_as_str(value).is_some_and(|mode| mode.starts_with("any")));
+    let mut expected = arguments
+        .inner()
+        .iter()
+        .filter(|(key, _)| !key.as_str().starts_with("x-"));
+    let found = |(key, value): (&ShortString, &AMQPValue)| {
+        headers
+            .and_then(|headers| headers.inner().get(key))
+            .is_some_and(|header| *value == AMQPValue::Void || header == value)
+    };
+    if any {
+        expected.any(found)
+    } else {
+        expected.all(found)
+    }
+}
+
+fn value_as_str(value: &AMQPValue) -> Option<&str> {
+    match value {
+        AMQPValue::ShortString(value) => Some(value.as_str()),
+        AMQPValue::LongString(value) => std::str::from_utf8(value.as_bytes()).ok(),
+        _ => None,
+    }
+}
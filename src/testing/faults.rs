@@ -0,0 +1,354 @@
+use async_rs::{Runtime, traits::*};
+use futures_io::{AsyncRead, AsyncWrite};
+use std::{
+    fmt,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+type Sleep = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+type Sleeper = Arc<dyn Fn(Duration) -> Sleep + Send + Sync>;
+
+// type (1 byte) + channel id (2 bytes) + payload size (4 bytes)
+const FRAME_HEADER_SIZE: usize = 7;
+
+/// A handle to inject faults in the streams returned by a [`Connection::connector`], to test
+/// how the code reacts to network failures.
+///
+/// The faults apply to the streams currently wrapped by this injector. Disconnections are one
+/// shot: once a stream got disconnected, the next ones (when the connection gets recovered for
+/// example) work normally, unless new faults are injected.
+///
+/// ```rust
+/// use lapin::{
+///     Connection, ConnectionProperties,
+///     testing::{FakeBroker, FaultInjector},
+///     uri::AMQPUri,
+/// };
+///
+/// # async fn run() -> lapin::Result<()> {
+/// let runtime = lapin::runtime::default_runtime()?;
+/// let broker = FakeBroker::new();
+/// let faults = FaultInjector::new(runtime.clone());
+/// let connection = {
+///     let faults = faults.clone();
+///     Connection::connector(
+///         AMQPUri::default(),
+///         runtime,
+///         async move |_, _| Ok(faults.wrap(broker.stream())),
+///         ConnectionProperties::default().enable_auto_recover(),
+///     )
+///     .await?
+/// };
+/// // The connection will be dropped after receiving the next 2 frames, then recovered
+/// faults.disconnect_after_frames(2);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Connection::connector`]: crate::Connection::connector
+#[derive(Clone)]
+pub struct FaultInjector {
+    inner: Arc<Mutex<Inner>>,
+    sleep: Sleeper,
+}
+
+#[derive(Default)]
+struct Inner {
+    // Streams created before the current generation are disconnected
+    generation: u64,
+    disconnect_after_bytes: Option<usize>,
+    disconnect_after_frames: Option<usize>,
+    latency: Option<Duration>,
+    stalled: bool,
+    corrupt_next_frame: bool,
+    wakers: Vec<Waker>,
+}
+
+impl FaultInjector {
+    /// Create a new fault injector, using the given runtime for the latency timers.
+    pub fn new<RK: RuntimeKit + Send + 'static>(runtime: Runtime<RK>) -> Self {
+        let sleeper = Mutex::new(runtime);
+        let sleep = Arc::new(move |dur| {
+            let sleep = sleeper.lock().unwrap_or_else(|e| e.into_inner()).sleep(dur);
+            Box::pin(async move {
+                sleep.await;
+            }) as Sleep
+        });
+        Self {
+            inner: Arc::default(),
+            sleep,
+        }
+    }
+
+    /// Wrap a stream to inject faults in it.
+    pub fn wrap<S: AsyncRead + AsyncWrite + Unpin>(&self, stream: S) -> FaultyStream<S> {
+        FaultyStream {
+            stream,
+            faults: self.clone(),
+            generation: self.lock().generation,
+            frame: FrameTracker::default(),
+            delay: None,
+            disconnected: false,
+        }
+    }
+
+    /// Disconnect the streams right away.
+    pub fn disconnect(&self) {
+        let mut inner = self.lock();
+        inner.generation += 1;
+        inner.wake();
+    }
+
+    /// Disconnect the stream after it received this number of bytes from the server.
+    pub fn disconnect_after_bytes(&self, bytes: usize) {
+        let mut inner = self.lock();
+        inner.disconnect_after_bytes = Some(bytes);
+        inner.wake();
+    }
+
+    /// Disconnect the stream after it received this number of frames from the server.
+    pub fn disconnect_after_frames(&self, frames: usize) {
+        let mut inner = self.lock();
+        inner.disconnect_after_frames = Some(frames);
+        inner.wake();
+    }
+
+    /// Delay each read from the server by the given duration, or stop delaying them with `None`.
+    pub fn set_latency(&self, latency: Option<Duration>) {
+        self.lock().latency = latency;
+    }
+
+    /// Stop reading from the server, as if the network was stalled, to make the heartbeats time
+    /// out for example.
+    pub fn stall_reads(&self) {
+        self.lock().stalled = true;
+    }
+
+    /// Resume reading from the server after [`FaultInjector::stall_reads`].
+    pub fn resume_reads(&self) {
+        let mut inner = self.lock();
+        inner.stalled = false;
+        inner.wake();
+    }
+
+    /// Corrupt the next frame received from the server, to make its parsing fail.
+    pub fn corrupt_next_frame(&self) {
+        self.lock().corrupt_next_frame = true;
+    }
+
+    /// Stop injecting any fault.
+    pub fn reset(&self) {
+        let mut inner = self.lock();
+        let generation = inner.generation;
+        let wakers = std::mem::take(&mut inner.wakers);
+        *inner = Inner {
+            generation,
+            wakers,
+            ..Inner::default()
+        };
+        inner.wake();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl fmt::Debug for FaultInjector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.lock();
+        f.debug_struct("FaultInjector")
+            .field("disconnect_after_bytes", &inner.disconnect_after_bytes)
+            .field("disconnect_after_frames", &inner.disconnect_after_frames)
+            .field("latency", &inner.latency)
+            .field("stalled", &inner.stalled)
+            .field("corrupt_next_frame", &inner.corrupt_next_frame)
+            .finish()
+    }
+}
+
+impl Inner {
+    fn register(&mut self, waker: &Waker) {
+        if !self.wakers.iter().any(|known| known.will_wake(waker)) {
+            self.wakers.push(waker.clone());
+        }
+    }
+
+    fn wake(&mut self) {
+        for waker in self.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// A stream wrapped by a [`FaultInjector`].
+pub struct FaultyStream<S> {
+    stream: S,
+    faults: FaultInjector,
+    generation: u64,
+    frame: FrameTracker,
+    delay: Option<Sleep>,
+    disconnected: bool,
+}
+
+impl<S> FaultyStream<S> {
+    fn check_connected(&mut self, faults: &Inner) -> io::Result<()> {
+        if faults.generation != self.generation {
+            self.disconnected = true;
+        }
+        if self.disconnected {
+            Err(io::ErrorKind::ConnectionReset.into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for FaultyStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        let faults = this.faults.clone();
+        let mut inner = faults.lock();
+        this.check_connected(&inner)?;
+        if inner.stalled {
+            inner.register(cx.waker());
+            return Poll::Pending;
+        }
+        if let Some(latency) = inner.latency {
+            let delay = this.delay.get_or_insert_with(|| (faults.sleep)(latency));
+            if delay.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            // A finished delay cannot be polled again, the next read gets a new one
+            this.delay = None;
+        }
+        if inner.disconnect_after_frames == Some(0) {
+            inner.disconnect_after_frames = None;
+            this.disconnected = true;
+            return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()));
+        }
+        let mut len = buf.len();
+        if let Some(bytes) = inner.disconnect_after_bytes {
+            if bytes == 0 {
+                inner.disconnect_after_bytes = None;
+                this.disconnected = true;
+                return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()));
+            }
+            len = len.min(bytes);
+        }
+        // Don't hold the lock while reading, the server could be waiting on it
+        drop(inner);
+        let read = match Pin::new(&mut this.stream).poll_read(cx, &mut buf[..len]) {
+            Poll::Ready(Ok(read)) => read,
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+            Poll::Pending => {
+                // Make sure disconnect() can wake us up
+                faults.lock().register(cx.waker());
+                return Poll::Pending;
+            }
+        };
+
+        let mut inner = faults.lock();
+        let mut read = read;
+        for (index, byte) in buf.iter_mut().enumerate().take(read) {
+            if !this.frame.advance(*byte) {
+                continue;
+            }
+            if inner.corrupt_next_frame {
+                inner.corrupt_next_frame = false;
+                // Replace the frame end marker
+                *byte = !*byte;
+            }
+            if let Some(frames) = inner.disconnect_after_frames.as_mut() {
+                *frames = frames.saturating_sub(1);
+                if *frames == 0 {
+                    inner.disconnect_after_frames = None;
+                    inner.disconnect_after_bytes = None;
+                    this.disconnected = true;
+                    read = index + 1;
+                    break;
+                }
+            }
+        }
+        if let Some(bytes) = inner.disconnect_after_bytes.as_mut() {
+            *bytes = bytes.saturating_sub(read);
+        }
+        Poll::Ready(Ok(read))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for FaultyStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let faults = self.faults.clone();
+        self.check_connected(&faults.lock())?;
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // Like for a TCP socket, there is nothing to flush once disconnected
+        if self.disconnected {
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_close(cx)
+    }
+}
+
+impl<S> fmt::Debug for FaultyStream<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FaultyStream")
+            .field("disconnected", &self.disconnected)
+            .finish_non_exhaustive()
+    }
+}
+
+// Follow the frames boundaries in the bytes received from the server
+#[derive(Default)]
+struct FrameTracker {
+    header: [u8; FRAME_HEADER_SIZE],
+    header_len: usize,
+    remaining: usize,
+}
+
+impl FrameTracker {
+    // Returns whether this byte is the end of a frame
+    fn advance(&mut self, byte: u8) -> bool {
+        if self.header_len < FRAME_HEADER_SIZE {
+            self.header[self.header_len] = byte;
+            self.header_len += 1;
+            if self.header_len == FRAME_HEADER_SIZE {
+                let size = u32::from_be_bytes([
+                    self.header[3],
+                    self.header[4],
+                    self.header[5],
+                    self.header[6],
+                ]);
+                // The payload and the frame end marker
+                self.remaining = size as usize + 1;
+            }
+            return false;
+        }
+        self.remaining -= 1;
+        if self.remaining == 0 {
+            self.header_len = 0;
+            return true;
+        }
+        false
+    }
+}
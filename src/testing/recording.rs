@@ -0,0 +1,240 @@
+use crate::{
+    Acknowledger, BasicProperties, Publisher, Result,
+    options::{BasicAckOptions, BasicNackOptions, BasicPublishOptions, BasicRejectOptions},
+    publisher_confirm::{Confirmation, PublisherConfirm},
+    types::{DeliveryTag, ShortString},
+};
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// A message published through a [`RecordingPublisher`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct PublishedMessage {
+    /// The exchange the message was published to
+    pub exchange: ShortString,
+    /// The routing key of the message
+    pub routing_key: ShortString,
+    /// The options used to publish the message
+    pub options: BasicPublishOptions,
+    /// The payload of the message
+    pub payload: Vec<u8>,
+    /// The properties and headers of the message
+    pub properties: BasicProperties,
+}
+
+/// A [`Publisher`] keeping the published messages in memory, to check what the code under test
+/// published without a broker.
+///
+/// Cloning it gives another handle to the same record.
+///
+/// ```rust
+/// use lapin::{
+///     BasicProperties, Publisher, options::BasicPublishOptions, testing::RecordingPublisher,
+/// };
+///
+/// async fn notify(publisher: &dyn Publisher, user: &str) -> lapin::Result<()> {
+///     publisher
+///         .basic_publish(
+///             "events".into(),
+///             "user.created".into(),
+///             BasicPublishOptions::default(),
+///             user.as_bytes(),
+///             BasicProperties::default(),
+///         )
+///         .await?
+///         .await?;
+///     Ok(())
+/// }
+///
+/// # async fn run() -> lapin::Result<()> {
+/// let publisher = RecordingPublisher::new();
+/// notify(&publisher, "alice").await?;
+/// let published = publisher.published();
+/// assert_eq!(published[0].exchange.as_str(), "events");
+/// assert_eq!(published[0].payload, b"alice");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RecordingPublisher {
+    inner: Arc<Mutex<PublisherInner>>,
+}
+
+#[derive(Debug, Default)]
+struct PublisherInner {
+    published: Vec<PublishedMessage>,
+    confirms: bool,
+    nack_next: bool,
+}
+
+impl RecordingPublisher {
+    /// Create a publisher answering [`Confirmation::NotRequested`], as a channel without
+    /// publisher confirms would.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer [`Confirmation::Ack`] instead, as a channel with publisher confirms would.
+    #[must_use]
+    pub fn with_confirms(self) -> Self {
+        self.lock().confirms = true;
+        self
+    }
+
+    /// Answer [`Confirmation::Nack`] to the next message, which still gets recorded.
+    pub fn nack_next(&self) {
+        self.lock().nack_next = true;
+    }
+
+    /// The messages published so far, in order.
+    pub fn published(&self) -> Vec<PublishedMessage> {
+        self.lock().published.clone()
+    }
+
+    /// Forget about the messages published so far.
+    pub fn clear(&self) {
+        self.lock().published.clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PublisherInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait]
+impl Publisher for RecordingPublisher {
+    async fn basic_publish(
+        &self,
+        exchange: ShortString,
+        routing_key: ShortString,
+        options: BasicPublishOptions,
+        payload: &[u8],
+        properties: BasicProperties,
+    ) -> Result<PublisherConfirm> {
+        let mut inner = self.lock();
+        inner.published.push(PublishedMessage {
+            exchange,
+            routing_key,
+            options,
+            payload: payload.to_vec(),
+            properties,
+        });
+        let confirmation = if std::mem::take(&mut inner.nack_next) {
+            Confirmation::Nack(None)
+        } else if inner.confirms {
+            Confirmation::Ack(None)
+        } else {
+            Confirmation::NotRequested
+        };
+        Ok(PublisherConfirm::ready(confirmation))
+    }
+}
+
+/// An acknowledgement made through a [`RecordingAcknowledger`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Acknowledgement {
+    /// The delivery was acked with `basic.ack`.
+    Ack(DeliveryTag, BasicAckOptions),
+    /// The delivery was nacked with `basic.nack`.
+    Nack(DeliveryTag, BasicNackOptions),
+    /// The delivery was rejected with `basic.reject`.
+    Reject(DeliveryTag, BasicRejectOptions),
+}
+
+impl Acknowledgement {
+    /// The delivery tag of the acknowledged delivery.
+    pub fn delivery_tag(&self) -> DeliveryTag {
+        match self {
+            Self::Ack(delivery_tag, _)
+            | Self::Nack(delivery_tag, _)
+            | Self::Reject(delivery_tag, _) => *delivery_tag,
+        }
+    }
+}
+
+/// An [`Acknowledger`] keeping the acknowledgements in memory, to check how the code under test
+/// handled the deliveries without a broker.
+///
+/// Like an [`Acker`], each delivery can only be acknowledged once: the next attempts return
+/// `Ok(false)` and don't get recorded.
+///
+/// ```rust
+/// use lapin::{
+///     Acknowledger,
+///     options::BasicAckOptions,
+///     testing::{Acknowledgement, RecordingAcknowledger},
+/// };
+///
+/// # async fn run() -> lapin::Result<()> {
+/// let acknowledgements = RecordingAcknowledger::new();
+/// acknowledgements
+///     .delivery(3)
+///     .ack(BasicAckOptions::default())
+///     .await?;
+/// assert_eq!(
+///     acknowledgements.acknowledgements(),
+///     vec![Acknowledgement::Ack(3, BasicAckOptions::default())],
+/// );
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Acker`]: crate::Acker
+#[derive(Clone, Debug, Default)]
+pub struct RecordingAcknowledger {
+    delivery_tag: DeliveryTag,
+    acknowledgements: Arc<Mutex<Vec<Acknowledgement>>>,
+}
+
+impl RecordingAcknowledger {
+    /// Create an acknowledger for the delivery tag 0.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get an acknowledger for another delivery, sharing the same record.
+    pub fn delivery(&self, delivery_tag: DeliveryTag) -> Self {
+        Self {
+            delivery_tag,
+            acknowledgements: self.acknowledgements.clone(),
+        }
+    }
+
+    /// The acknowledgements made so far, in order.
+    pub fn acknowledgements(&self) -> Vec<Acknowledgement> {
+        self.lock().clone()
+    }
+
+    fn record(&self, acknowledgement: Acknowledgement) -> bool {
+        let mut acknowledgements = self.lock();
+        if acknowledgements
+            .iter()
+            .any(|known| known.delivery_tag() == self.delivery_tag)
+        {
+            return false;
+        }
+        acknowledgements.push(acknowledgement);
+        true
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Acknowledgement>> {
+        self.acknowledgements
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait]
+impl Acknowledger for RecordingAcknowledger {
+    async fn ack(&self, options: BasicAckOptions) -> Result<bool> {
+        Ok(self.record(Acknowledgement::Ack(self.delivery_tag, options)))
+    }
+
+    async fn nack(&self, options: BasicNackOptions) -> Result<bool> {
+        Ok(self.record(Acknowledgement::Nack(self.delivery_tag, options)))
+    }
+
+    async fn reject(&self, options: BasicRejectOptions) -> Result<bool> {
+        Ok(self.record(Acknowledgement::Reject(self.delivery_tag, options)))
+    }
+}
@@ -0,0 +1,550 @@
+use super::{Pipe, parse_frames};
+use crate::{
+    BasicProperties, Connection, ConnectionProperties, Result,
+    protocol::{AMQPClass, connection},
+    recorder::{FrameDirection, FrameRecord},
+    types::{AMQPValue, ChannelId, FieldTable},
+    uri::AMQPUri,
+};
+use amq_protocol::frame::{AMQPContentHeader, AMQPFrame, ProtocolVersion};
+use async_rs::{Runtime, traits::*};
+use futures_io::{AsyncRead, AsyncWrite};
+use std::{
+    collections::VecDeque,
+    fmt, io,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+};
+use tracing::trace;
+
+type Matcher = Box<dyn Fn(&AMQPFrame) -> bool + Send>;
+
+/// The sequence of frames a [`ScriptedPeer`] expects from the client and sends back.
+///
+/// The steps are played in order: each expected frame must be the next one the client sends,
+/// while the frames to send are written as soon as all the steps before them are done.
+/// Heartbeats sent by the client are ignored unless explicitly expected.
+#[derive(Default)]
+pub struct Script {
+    steps: VecDeque<Step>,
+}
+
+enum Step {
+    Expect {
+        description: String,
+        matcher: Matcher,
+    },
+    ExpectContent {
+        channel_id: ChannelId,
+        remaining: Option<u64>,
+    },
+    Send(Box<AMQPFrame>),
+    Disconnect,
+}
+
+impl Script {
+    /// Create an empty script.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Play the connection handshake, as RabbitMQ would, with no heartbeat.
+    #[must_use]
+    pub fn with_handshake(self) -> Self {
+        let mut capabilities = FieldTable::default();
+        for capability in [
+            "publisher_confirms",
+            "exchange_exchange_bindings",
+            "basic.nack",
+            "consumer_cancel_notify",
+            "connection.blocked",
+            "authentication_failure_close",
+        ] {
+            capabilities.insert(capability.into(), AMQPValue::Boolean(true));
+        }
+        let mut server_properties = FieldTable::default();
+        server_properties.insert("capabilities".into(), AMQPValue::FieldTable(capabilities));
+        self.expect(AMQPFrame::ProtocolHeader(ProtocolVersion::amqp_0_9_1()))
+            .send_method(
+                0,
+                AMQPClass::Connection(connection::AMQPMethod::Start(connection::Start {
+                    version_major: 0,
+                    version_minor: 9,
+                    server_properties,
+                    mechanisms: "PLAIN AMQPLAIN".into(),
+                    locales: "en_US".into(),
+                })),
+            )
+            .expect_method(0, |method| {
+                matches!(
+                    method,
+                    AMQPClass::Connection(connection::AMQPMethod::StartOk(_))
+                )
+            })
+            .send_method(
+                0,
+                AMQPClass::Connection(connection::AMQPMethod::Tune(connection::Tune {
+                    channel_max: 2047,
+                    frame_max: 131_072,
+                    heartbeat: 0,
+                })),
+            )
+            .expect_method(0, |method| {
+                matches!(
+                    method,
+                    AMQPClass::Connection(connection::AMQPMethod::TuneOk(_))
+                )
+            })
+            .expect_method(0, |method| {
+                matches!(
+                    method,
+                    AMQPClass::Connection(connection::AMQPMethod::Open(_))
+                )
+            })
+            .send_method(
+                0,
+                AMQPClass::Connection(connection::AMQPMethod::OpenOk(connection::OpenOk {})),
+            )
+    }
+
+    /// Build a script replaying a recording made with a [`FrameRecorder`], to reproduce
+    /// deterministically what the client went through.
+    ///
+    /// The frames the client sent are expected again, only checking their type, channel and
+    /// method as their arguments can legitimately differ between runs (generated consumer tags
+    /// for example), while the frames the server sent are sent back in the same order.
+    /// Heartbeats are skipped and truncated payloads are padded with zeros. If the client
+    /// reconnected during the recording, the connection gets dropped at the same point.
+    ///
+    /// [`FrameRecorder`]: crate::recorder::FrameRecorder
+    pub fn replay(records: impl IntoIterator<Item = FrameRecord>) -> Self {
+        let mut script = Self::new();
+        for FrameRecord {
+            direction,
+            truncated,
+            frame,
+            ..
+        } in records
+        {
+            script = match (direction, frame) {
+                (_, AMQPFrame::Heartbeat | AMQPFrame::InvalidHeartbeat(_)) => script,
+                (FrameDirection::Outbound, frame) => {
+                    let script = if matches!(frame, AMQPFrame::ProtocolHeader(_))
+                        && !script.steps.is_empty()
+                    {
+                        script.disconnect()
+                    } else {
+                        script
+                    };
+                    script.expect_matching(format!("{frame:?}"), move |received| {
+                        same_kind(received, &frame)
+                    })
+                }
+                (FrameDirection::Inbound, AMQPFrame::Body(channel_id, mut payload)) => {
+                    payload.resize(payload.len() + truncated, 0);
+                    script.send(AMQPFrame::Body(channel_id, payload))
+                }
+                (FrameDirection::Inbound, frame) => script.send(frame),
+            };
+        }
+        script
+    }
+
+    /// Expect the client to send exactly this frame.
+    #[must_use]
+    pub fn expect(self, frame: AMQPFrame) -> Self {
+        let description = format!("{frame:?}");
+        self.expect_matching(description, move |received| *received == frame)
+    }
+
+    /// Expect the client to send a frame accepted by the given matcher.
+    ///
+    /// The description is used to report what was expected when the script fails.
+    #[must_use]
+    pub fn expect_matching<M: Fn(&AMQPFrame) -> bool + Send + 'static>(
+        self,
+        description: impl Into<String>,
+        matcher: M,
+    ) -> Self {
+        self.with_step(Step::Expect {
+            description: description.into(),
+            matcher: Box::new(matcher),
+        })
+    }
+
+    /// Expect the client to send a method on the given channel, accepted by the given matcher.
+    #[must_use]
+    pub fn expect_method<M: Fn(&AMQPClass) -> bool + Send + 'static>(
+        self,
+        channel_id: ChannelId,
+        matcher: M,
+    ) -> Self {
+        self.expect_matching(format!("a method on channel {channel_id}"), move |frame| {
+            matches!(frame, AMQPFrame::Method(id, method) if *id == channel_id && matcher(method))
+        })
+    }
+
+    /// Expect the client to send a content header and all its body frames on the given channel,
+    /// following a `basic.publish` for example.
+    #[must_use]
+    pub fn expect_content(self, channel_id: ChannelId) -> Self {
+        self.with_step(Step::ExpectContent {
+            channel_id,
+            remaining: None,
+        })
+    }
+
+    /// Send this frame to the client.
+    #[must_use]
+    pub fn send(self, frame: AMQPFrame) -> Self {
+        self.with_step(Step::Send(Box::new(frame)))
+    }
+
+    /// Send this method to the client on the given channel.
+    #[must_use]
+    pub fn send_method(self, channel_id: ChannelId, method: AMQPClass) -> Self {
+        self.send(AMQPFrame::Method(channel_id, method))
+    }
+
+    /// Send this method to the client followed by a message, such as a `basic.deliver`.
+    #[must_use]
+    pub fn send_content(
+        self,
+        channel_id: ChannelId,
+        method: AMQPClass,
+        properties: BasicProperties,
+        body: Vec<u8>,
+    ) -> Self {
+        let class_id = method.get_amqp_class_id();
+        let header = AMQPFrame::Header(
+            channel_id,
+            AMQPContentHeader {
+                class_id,
+                body_size: body.len() as u64,
+                properties,
+            },
+        );
+        let script = self.send_method(channel_id, method).send(header);
+        if body.is_empty() {
+            script
+        } else {
+            script.send(AMQPFrame::Body(channel_id, body))
+        }
+    }
+
+    /// Abruptly close the connection, as if the network went down.
+    ///
+    /// If the client reconnects, the rest of the script is played on the new connection.
+    #[must_use]
+    pub fn disconnect(self) -> Self {
+        self.with_step(Step::Disconnect)
+    }
+
+    fn with_step(mut self, step: Step) -> Self {
+        self.steps.push_back(step);
+        self
+    }
+}
+
+// Compare the structure of the frames, but not their arguments
+fn same_kind(received: &AMQPFrame, recorded: &AMQPFrame) -> bool {
+    match (received, recorded) {
+        (AMQPFrame::Method(id, method), AMQPFrame::Method(recorded_id, recorded_method)) => {
+            id == recorded_id
+                && method.get_amqp_class_id() == recorded_method.get_amqp_class_id()
+                && method.get_amqp_method_id() == recorded_method.get_amqp_method_id()
+        }
+        (AMQPFrame::Header(id, header), AMQPFrame::Header(recorded_id, recorded_header)) => {
+            id == recorded_id && header.class_id == recorded_header.class_id
+        }
+        (AMQPFrame::Body(id, _), AMQPFrame::Body(recorded_id, _)) => id == recorded_id,
+        (received, recorded) => received == recorded,
+    }
+}
+
+impl fmt::Debug for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Script")
+            .field("steps", &self.steps.len())
+            .finish()
+    }
+}
+
+/// A mock AMQP server playing a [`Script`].
+///
+/// It goes through the [`Connection::connector`] hook and doesn't need any background task,
+/// so it can be used with any runtime. Cloning it gives another handle to the same peer.
+///
+/// When the client sends an unexpected frame, the peer records the failure and closes the
+/// connection. Use [`ScriptedPeer::assert_finished`] at the end of the test to check that the
+/// whole script was played.
+#[derive(Clone)]
+pub struct ScriptedPeer {
+    inner: Arc<Mutex<Inner>>,
+}
+
+struct Inner {
+    steps: VecDeque<Step>,
+    pipe: Option<Arc<Pipe>>,
+    failure: Option<String>,
+    waiters: Vec<Waker>,
+}
+
+impl ScriptedPeer {
+    /// Create a peer playing the given script.
+    pub fn new(script: Script) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                steps: script.steps,
+                pipe: None,
+                failure: None,
+                waiters: Vec::new(),
+            })),
+        }
+    }
+
+    /// Connect to the peer, using the default runtime.
+    pub async fn connect(&self, options: ConnectionProperties) -> Result<Connection> {
+        self.connect_with_runtime(options, crate::runtime::default_runtime()?)
+            .await
+    }
+
+    /// Connect to the peer, using the given runtime.
+    pub async fn connect_with_runtime<RK: RuntimeKit + Clone + Send + 'static>(
+        &self,
+        options: ConnectionProperties,
+        runtime: Runtime<RK>,
+    ) -> Result<Connection> {
+        let peer = self.clone();
+        Connection::connector(
+            AMQPUri::default(),
+            runtime,
+            async move |_, _| Ok(peer.stream()),
+            options,
+        )
+        .await
+    }
+
+    /// Open a new stream to the peer, to be used with [`Connection::connector`].
+    ///
+    /// Any previous stream gets disconnected.
+    pub fn stream(&self) -> ScriptedStream {
+        let pipe = Arc::new(Pipe::default());
+        let mut inner = self.lock();
+        if let Some(previous) = inner.pipe.replace(pipe.clone()) {
+            previous.close();
+        }
+        inner.play();
+        ScriptedStream {
+            peer: self.clone(),
+            pipe,
+            incoming: Vec::new(),
+        }
+    }
+
+    /// Whether all the steps of the script were played.
+    pub fn is_finished(&self) -> bool {
+        self.lock().steps.is_empty()
+    }
+
+    /// The error describing why the script failed, if the client sent an unexpected frame.
+    pub fn failure(&self) -> Option<String> {
+        self.lock().failure.clone()
+    }
+
+    /// Wait until all the steps of the script were played or until it failed.
+    pub async fn finished(&self) {
+        std::future::poll_fn(|cx| {
+            let mut inner = self.lock();
+            if inner.steps.is_empty() || inner.failure.is_some() {
+                Poll::Ready(())
+            } else {
+                inner.waiters.push(cx.waker().clone());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Panic if the script failed or if some of its steps weren't played.
+    pub fn assert_finished(&self) {
+        let inner = self.lock();
+        if let Some(failure) = inner.failure.as_ref() {
+            panic!("script failed: {failure}");
+        }
+        if let Some(step) = inner.steps.front() {
+            panic!(
+                "script not finished, {} steps left, next one: {}",
+                inner.steps.len(),
+                step.describe()
+            );
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl fmt::Debug for ScriptedPeer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.lock();
+        f.debug_struct("ScriptedPeer")
+            .field("steps", &inner.steps.len())
+            .field("failure", &inner.failure)
+            .finish()
+    }
+}
+
+impl Inner {
+    fn receive(&mut self, pipe: &Arc<Pipe>, frame: AMQPFrame) {
+        if !self
+            .pipe
+            .as_ref()
+            .is_some_and(|current| Arc::ptr_eq(current, pipe))
+            || self.failure.is_some()
+        {
+            return;
+        }
+        trace!(?frame, "scripted peer received frame");
+        let matched = match self.steps.front_mut() {
+            Some(Step::Expect { matcher, .. }) if matcher(&frame) => {
+                self.steps.pop_front();
+                true
+            }
+            Some(Step::ExpectContent {
+                channel_id,
+                remaining,
+            }) => match (&frame, remaining.as_mut()) {
+                (AMQPFrame::Header(id, header), None) if id == channel_id => {
+                    if header.body_size == 0 {
+                        self.steps.pop_front();
+                    } else {
+                        *remaining = Some(header.body_size);
+                    }
+                    true
+                }
+                (AMQPFrame::Body(id, payload), Some(remaining)) if id == channel_id => {
+                    *remaining = remaining.saturating_sub(payload.len() as u64);
+                    if *remaining == 0 {
+                        self.steps.pop_front();
+                    }
+                    true
+                }
+                _ => false,
+            },
+            _ => false,
+        };
+        if matched {
+            self.play();
+        } else if frame != AMQPFrame::Heartbeat {
+            let expected = self
+                .steps
+                .front()
+                .map_or_else(|| "the end of the script".to_string(), Step::describe);
+            self.fail(format!("expected {expected}, received {frame:?}"));
+        }
+    }
+
+    // Send the frames until the next expected one
+    fn play(&mut self) {
+        while let Some(step) = self.steps.front() {
+            match step {
+                Step::Send(frame) => {
+                    trace!(?frame, "scripted peer sending frame");
+                    if let Some(pipe) = self.pipe.as_ref() {
+                        pipe.push_frame(frame);
+                    }
+                }
+                Step::Disconnect => {
+                    if let Some(pipe) = self.pipe.take() {
+                        pipe.close();
+                    }
+                }
+                Step::Expect { .. } | Step::ExpectContent { .. } => return,
+            }
+            self.steps.pop_front();
+        }
+        self.wake_waiters();
+    }
+
+    fn fail(&mut self, failure: String) {
+        trace!(%failure, "scripted peer failed");
+        self.failure = Some(failure);
+        if let Some(pipe) = self.pipe.take() {
+            pipe.close();
+        }
+        self.wake_waiters();
+    }
+
+    fn wake_waiters(&mut self) {
+        for waker in self.waiters.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+impl Step {
+    fn describe(&self) -> String {
+        match self {
+            Step::Expect { description, .. } => description.clone(),
+            Step::ExpectContent { channel_id, .. } => format!("content on channel {channel_id}"),
+            Step::Send(frame) => format!("sending {frame:?}"),
+            Step::Disconnect => "disconnecting".to_string(),
+        }
+    }
+}
+
+/// A client stream connected to a [`ScriptedPeer`].
+pub struct ScriptedStream {
+    peer: ScriptedPeer,
+    pipe: Arc<Pipe>,
+    incoming: Vec<u8>,
+}
+
+impl AsyncRead for ScriptedStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.pipe.poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for ScriptedStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if self.pipe.is_closed() {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        let this = &mut *self;
+        this.incoming.extend_from_slice(buf);
+        let res = parse_frames(&mut this.incoming, |frame| {
+            this.peer.lock().receive(&this.pipe, frame)
+        });
+        if let Err(err) = res.as_ref() {
+            this.peer.lock().fail(err.to_string());
+        }
+        Poll::Ready(res.map(|()| buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.pipe.close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl fmt::Debug for ScriptedStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScriptedStream").finish_non_exhaustive()
+    }
+}
@@ -1,32 +1,347 @@
 use crate::{
     consumer::Consumer,
     exchange::ExchangeKind,
-    options::{ExchangeDeclareOptions, QueueDeclareOptions},
-    types::{FieldTable, ShortString},
+    options::{BasicConsumeOptions, ExchangeDeclareOptions, QueueDeclareOptions},
+    types::{ChannelId, FieldTable, ShortString, ShortUInt},
 };
 
+/// A set of exchanges and queues, with their bindings, to declare at once using
+/// [`Channel::apply_topology`].
+///
+/// It can either be built in code:
+///
+/// ```rust
+/// use lapin::{
+///     ExchangeKind,
+///     options::{ExchangeDeclareOptions, QueueDeclareOptions},
+///     topology::Topology,
+///     types::FieldTable,
+/// };
+///
+/// let topology = Topology::new()
+///     .with_exchange(
+///         "events".into(),
+///         ExchangeKind::Topic,
+///         ExchangeDeclareOptions::default(),
+///         FieldTable::default(),
+///     )
+///     .with_queue(
+///         "audit".into(),
+///         QueueDeclareOptions::default(),
+///         FieldTable::default(),
+///     )
+///     .with_queue_binding(
+///         "audit".into(),
+///         "events".into(),
+///         "#".into(),
+///         FieldTable::default(),
+///     );
+/// ```
+///
+/// or, with the `serde` feature, be loaded from JSON (or any other format supported by serde).
+/// Omitted fields get their default value, and exchanges and queues are considered declared
+/// unless `is_declared` is `false`:
+///
+/// ```json
+/// {
+///   "exchanges": [{ "name": "events", "kind": "topic", "options": { "durable": true } }],
+///   "queues": [
+///     {
+///       "name": "audit",
+///       "bindings": [{ "source": "events", "routing_key": "#" }]
+///     }
+///   ]
+/// }
+/// ```
+///
+/// [`Channel::apply_topology`]: ../struct.Channel.html#method.apply_topology
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Topology {
+    /// The exchanges, with the bindings having them as destination
+    pub exchanges: Vec<ExchangeDefinition>,
+    /// The queues, with their bindings
+    pub queues: Vec<QueueDefinition>,
+}
+
+impl Topology {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_exchange(
+        mut self,
+        name: ShortString,
+        kind: ExchangeKind,
+        options: ExchangeDeclareOptions,
+        arguments: FieldTable,
+    ) -> Self {
+        match self
+            .exchanges
+            .iter_mut()
+            .find(|exchange| exchange.name == name)
+        {
+            Some(exchange) => exchange.set_declared(kind, options, arguments),
+            None => self
+                .exchanges
+                .push(ExchangeDefinition::declared(name, kind, options, arguments)),
+        }
+        self
+    }
+
+    /// Use an empty name to let the server generate one. Bindings can then only be added to
+    /// such queues through [`QueueDefinition::bindings`].
+    ///
+    /// [`QueueDefinition::bindings`]: ./struct.QueueDefinition.html#structfield.bindings
+    #[must_use]
+    pub fn with_queue(
+        mut self,
+        name: ShortString,
+        options: QueueDeclareOptions,
+        arguments: FieldTable,
+    ) -> Self {
+        let server_named = name.as_str().is_empty();
+        match self
+            .queues
+            .iter_mut()
+            .find(|queue| !server_named && queue.name == name)
+        {
+            Some(queue) => queue.set_declared(options, arguments, false),
+            None => self.queues.push(QueueDefinition::declared(
+                name,
+                options,
+                arguments,
+                server_named,
+            )),
+        }
+        self
+    }
+
+    #[must_use]
+    pub fn with_exchange_binding(
+        mut self,
+        destination: ShortString,
+        source: ShortString,
+        routing_key: ShortString,
+        arguments: FieldTable,
+    ) -> Self {
+        match self
+            .exchanges
+            .iter_mut()
+            .find(|exchange| exchange.name == destination)
+        {
+            Some(exchange) => exchange.register_binding(source, routing_key, arguments),
+            None => {
+                let mut exchange = ExchangeDefinition::undeclared(destination);
+                exchange.register_binding(source, routing_key, arguments);
+                self.exchanges.push(exchange);
+            }
+        }
+        self
+    }
+
+    #[must_use]
+    pub fn with_queue_binding(
+        mut self,
+        queue: ShortString,
+        exchange: ShortString,
+        routing_key: ShortString,
+        arguments: FieldTable,
+    ) -> Self {
+        match self.queues.iter_mut().find(|q| q.name == queue) {
+            Some(queue) => queue.register_binding(exchange, routing_key, arguments),
+            None => {
+                let mut queue = QueueDefinition::undeclared(queue);
+                queue.register_binding(exchange, routing_key, arguments);
+                self.queues.push(queue);
+            }
+        }
+        self
+    }
+}
+
+/// What [`Channel::apply_topology`] did
+///
+/// [`Channel::apply_topology`]: ../struct.Channel.html#method.apply_topology
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TopologyReport {
+    /// The exchanges which didn't exist and got created
+    pub created_exchanges: Vec<ShortString>,
+    /// The exchanges which already existed
+    pub existing_exchanges: Vec<ShortString>,
+    /// The queues which didn't exist and got created, with the name generated by the server for
+    /// server-named ones
+    pub created_queues: Vec<ShortString>,
+    /// The queues which already existed
+    pub existing_queues: Vec<ShortString>,
+}
+
+/// What [`Channel::verify_topology`] found
+///
+/// [`Channel::verify_topology`]: ../struct.Channel.html#method.verify_topology
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TopologyVerification {
+    /// The exchanges which don't exist
+    pub missing_exchanges: Vec<ShortString>,
+    /// The exchanges which exist but aren't equivalent to the expected ones
+    pub mismatched_exchanges: Vec<TopologyMismatch>,
+    /// The queues which don't exist
+    pub missing_queues: Vec<ShortString>,
+    /// The queues which exist but aren't equivalent to the expected ones, or cannot be accessed
+    pub mismatched_queues: Vec<TopologyMismatch>,
+}
+
+impl TopologyVerification {
+    /// Whether everything exists as expected
+    pub fn is_valid(&self) -> bool {
+        self.missing_exchanges.is_empty()
+            && self.mismatched_exchanges.is_empty()
+            && self.missing_queues.is_empty()
+            && self.mismatched_queues.is_empty()
+    }
+}
+
+/// An exchange or a queue which isn't as expected
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TopologyMismatch {
+    /// The name of the exchange or queue
+    pub name: ShortString,
+    /// Why the server rejected the expected definition
+    pub reason: ShortString,
+}
+
+/// A snapshot of everything recorded on a connection to recover it, see [`Connection::topology`].
+///
+/// [`Connection::topology`]: ../struct.Connection.html#method.topology
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct ConnectionTopology {
+    /// The exchanges recorded at the connection level, if enabled with
+    /// [`ConnectionProperties::enable_connection_topology`]
+    ///
+    /// [`ConnectionProperties::enable_connection_topology`]: ../struct.ConnectionProperties.html#method.enable_connection_topology
+    pub exchanges: Vec<ExchangeDefinition>,
+    /// The queues recorded at the connection level, if enabled with
+    /// [`ConnectionProperties::enable_connection_topology`]
+    ///
+    /// [`ConnectionProperties::enable_connection_topology`]: ../struct.ConnectionProperties.html#method.enable_connection_topology
+    pub queues: Vec<QueueDefinition>,
+    /// The topology of each channel, ordered by channel id
+    pub channels: Vec<ChannelTopology>,
+}
+
+/// A snapshot of everything recorded on a channel to recover it, see [`Channel::topology`].
+///
+/// [`Channel::topology`]: ../struct.Channel.html#method.topology
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct ChannelTopology {
+    /// The id of the channel
+    pub channel_id: ChannelId,
+    /// The exchanges declared on this channel, with the bindings having them as destination
+    pub exchanges: Vec<ExchangeDefinition>,
+    /// The queues declared on this channel, with their bindings
+    pub queues: Vec<QueueDefinition>,
+    /// The consumers registered on this channel
+    pub consumers: Vec<ConsumerDefinition>,
+    /// The prefetch settings of this channel
+    pub qos: QosDefinition,
+}
+
 #[derive(Clone, Debug, Default)]
 pub(crate) struct ChannelDefinition {
     pub(crate) exchanges: Vec<ExchangeDefinition>,
     pub(crate) queues: Vec<QueueDefinition>,
     pub(crate) consumers: Vec<Consumer>,
+    pub(crate) qos: QosDefinition,
 }
 
-#[derive(Clone, Debug, Default)]
-pub(crate) struct BindingDefinition {
-    pub(crate) source: ShortString,
-    pub(crate) routing_key: ShortString,
-    pub(crate) arguments: FieldTable,
+impl ChannelDefinition {
+    pub(crate) fn export(self, channel_id: ChannelId) -> ChannelTopology {
+        ChannelTopology {
+            channel_id,
+            exchanges: self.exchanges,
+            queues: self.queues,
+            consumers: self.consumers.iter().map(ConsumerDefinition::new).collect(),
+            qos: self.qos,
+        }
+    }
 }
 
+/// The prefetch settings of a channel, as set with `basic_qos`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct QosDefinition {
+    /// The prefetch count shared by all the consumers of the channel
+    pub global_prefetch_count: Option<ShortUInt>,
+    /// The prefetch count applying to each consumer of the channel
+    pub consumer_prefetch_count: Option<ShortUInt>,
+}
+
+impl QosDefinition {
+    pub(crate) fn register(&mut self, prefetch_count: ShortUInt, global: bool) {
+        if global {
+            self.global_prefetch_count = Some(prefetch_count);
+        } else {
+            self.consumer_prefetch_count = Some(prefetch_count);
+        }
+    }
+}
+
+/// A binding of a queue or an exchange (the destination) to a source exchange
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct BindingDefinition {
+    /// The exchange the destination is bound to
+    pub source: ShortString,
+    /// The routing key of the binding
+    pub routing_key: ShortString,
+    /// The arguments of the binding
+    pub arguments: FieldTable,
+}
+
+/// An exchange, as it got declared on a channel, with the bindings having it as destination
 #[derive(Clone, Debug, Default)]
-pub(crate) struct ExchangeDefinition {
-    pub(crate) name: ShortString,
-    pub(crate) kind: Option<ExchangeKind>,
-    pub(crate) options: Option<ExchangeDeclareOptions>,
-    pub(crate) arguments: Option<FieldTable>,
-    pub(crate) bindings: Vec<BindingDefinition>,
-    pub(crate) is_declared: bool,
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct ExchangeDefinition {
+    /// The name of the exchange
+    pub name: ShortString,
+    /// The kind of the exchange, if we declared it
+    pub kind: Option<ExchangeKind>,
+    /// The options used to declare the exchange, if we declared it
+    pub options: Option<ExchangeDeclareOptions>,
+    /// The arguments used to declare the exchange, if we declared it
+    pub arguments: Option<FieldTable>,
+    /// The bindings having this exchange as destination
+    pub bindings: Vec<BindingDefinition>,
+    /// Whether we declared the exchange or only used it as a binding destination
+    #[cfg_attr(feature = "serde", serde(default = "declared_by_default"))]
+    pub is_declared: bool,
 }
 
 impl ExchangeDefinition {
@@ -75,11 +390,15 @@ impl ExchangeDefinition {
         routing_key: ShortString,
         arguments: FieldTable,
     ) {
-        self.bindings.push(BindingDefinition {
+        let binding = BindingDefinition {
             source,
             routing_key,
             arguments,
-        });
+        };
+        // Bindings get registered again when they're replayed during recovery
+        if !self.bindings.contains(&binding) {
+            self.bindings.push(binding);
+        }
     }
 
     pub(crate) fn deregister_binding(
@@ -96,13 +415,27 @@ impl ExchangeDefinition {
     }
 }
 
+/// A queue, as it got declared on a channel, with its bindings
 #[derive(Clone, Debug, Default)]
-pub(crate) struct QueueDefinition {
-    pub(crate) name: ShortString,
-    pub(crate) options: Option<QueueDeclareOptions>,
-    pub(crate) arguments: Option<FieldTable>,
-    pub(crate) bindings: Vec<BindingDefinition>,
-    pub(crate) is_declared: bool,
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct QueueDefinition {
+    /// The name of the queue
+    pub name: ShortString,
+    /// The options used to declare the queue, if we declared it
+    pub options: Option<QueueDeclareOptions>,
+    /// The arguments used to declare the queue, if we declared it
+    pub arguments: Option<FieldTable>,
+    /// The bindings of this queue
+    pub bindings: Vec<BindingDefinition>,
+    /// Whether we declared the queue or only used it as a binding destination
+    #[cfg_attr(feature = "serde", serde(default = "declared_by_default"))]
+    pub is_declared: bool,
+    /// Whether the name of the queue was generated by the server
+    pub server_named: bool,
 }
 
 impl QueueDefinition {
@@ -110,6 +443,7 @@ impl QueueDefinition {
         name: ShortString,
         options: QueueDeclareOptions,
         arguments: FieldTable,
+        server_named: bool,
     ) -> Self {
         Self {
             name,
@@ -117,6 +451,7 @@ impl QueueDefinition {
             arguments: Some(arguments),
             bindings: Vec::new(),
             is_declared: true,
+            server_named,
         }
     }
 
@@ -127,13 +462,27 @@ impl QueueDefinition {
             arguments: None,
             bindings: Vec::new(),
             is_declared: false,
+            server_named: false,
         }
     }
 
-    pub(crate) fn set_declared(&mut self, options: QueueDeclareOptions, arguments: FieldTable) {
+    pub(crate) fn set_declared(
+        &mut self,
+        options: QueueDeclareOptions,
+        arguments: FieldTable,
+        server_named: bool,
+    ) {
         self.options = Some(options);
         self.arguments = Some(arguments);
         self.is_declared = true;
+        // Redeclaring a server-named queue by its generated name doesn't change its nature
+        self.server_named |= server_named;
+    }
+
+    pub(crate) fn merge_bindings(&mut self, bindings: Vec<BindingDefinition>) {
+        for binding in bindings {
+            self.register_binding(binding.source, binding.routing_key, binding.arguments);
+        }
     }
 
     pub(crate) fn register_binding(
@@ -142,11 +491,15 @@ impl QueueDefinition {
         routing_key: ShortString,
         arguments: FieldTable,
     ) {
-        self.bindings.push(BindingDefinition {
+        let binding = BindingDefinition {
             source,
             routing_key,
             arguments,
-        });
+        };
+        // Bindings get registered again when they're replayed during recovery
+        if !self.bindings.contains(&binding) {
+            self.bindings.push(binding);
+        }
     }
 
     pub(crate) fn deregister_binding(
@@ -162,3 +515,37 @@ impl QueueDefinition {
         });
     }
 }
+
+/// A consumer, as it got registered on a channel
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConsumerDefinition {
+    tag: ShortString,
+    /// The queue we're consuming
+    pub queue: ShortString,
+    /// The options used to register the consumer
+    pub options: BasicConsumeOptions,
+    /// The arguments used to register the consumer
+    pub arguments: FieldTable,
+}
+
+impl ConsumerDefinition {
+    pub(crate) fn new(consumer: &Consumer) -> Self {
+        Self {
+            tag: consumer.tag(),
+            queue: consumer.queue(),
+            options: consumer.options(),
+            arguments: consumer.arguments(),
+        }
+    }
+
+    /// The tag of the consumer, which cannot be changed as it identifies the consumer
+    pub fn tag(&self) -> &ShortString {
+        &self.tag
+    }
+}
+
+#[cfg(feature = "serde")]
+fn declared_by_default() -> bool {
+    true
+}
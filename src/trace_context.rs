@@ -0,0 +1,229 @@
+//! Propagate the [W3C trace context] through the messages headers.
+//!
+//! With the `trace-context` feature, [`Channel::basic_publish`] injects the OpenTelemetry
+//! context of the current `tracing` span in the `traceparent` and `tracestate` headers of the
+//! message (unless they are already set), using [`tracing-opentelemetry`].
+//!
+//! On the consumer side, each [`Delivery`] gets a span which is a child of the context extracted
+//! from these headers. It is created by the first call to [`Delivery::span`], so that it only
+//! covers the processing of the message. Instrument your handler with it; the delivery releases
+//! its own handle once it gets acked, nacked or rejected:
+//!
+//! ```rust
+//! use lapin::{message::Delivery, options::BasicAckOptions};
+//! use tracing::Instrument;
+//!
+//! async fn handle(delivery: Delivery) -> lapin::Result<()> {
+//!     let span = delivery.span();
+//!     async move {
+//!         // ... process the message
+//!         delivery.ack(BasicAckOptions::default()).await?;
+//!         Ok(())
+//!     }
+//!     .instrument(span)
+//!     .await
+//! }
+//! ```
+//!
+//! [`inject`] and [`extract`] can also be used directly to propagate other contexts.
+//!
+//! [W3C trace context]: https://www.w3.org/TR/trace-context/
+//! [`tracing-opentelemetry`]: https://docs.rs/tracing-opentelemetry
+//! [`Channel::basic_publish`]: ../struct.Channel.html#method.basic_publish
+//! [`Delivery`]: ../message/struct.Delivery.html
+//! [`Delivery::span`]: ../struct.Acker.html#method.span
+
+use crate::{
+    BasicProperties,
+    types::{AMQPValue, FieldTable, ShortString},
+};
+use opentelemetry::{
+    Context,
+    trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState},
+};
+use std::{
+    mem,
+    str::FromStr,
+    sync::{Arc, Mutex, MutexGuard},
+};
+use tracing::{Span, field::Empty, info_span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// The header carrying the trace id, the parent span id and the trace flags
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+/// The header carrying the vendor specific trace state
+pub const TRACESTATE_HEADER: &str = "tracestate";
+
+const SUPPORTED_VERSION: u8 = 0;
+
+/// Inject the span context of `context` in the headers of `properties`.
+///
+/// Nothing is injected if the context doesn't hold a valid span context, or if the headers
+/// already contain a `traceparent`.
+pub fn inject(context: &Context, properties: BasicProperties) -> BasicProperties {
+    let span = context.span();
+    let span_context = span.span_context();
+    if !span_context.is_valid() {
+        return properties;
+    }
+    let mut headers = properties.headers().clone().unwrap_or_default();
+    if headers.contains_key(TRACEPARENT_HEADER) {
+        return properties;
+    }
+    headers.insert(
+        TRACEPARENT_HEADER.into(),
+        AMQPValue::LongString(
+            format!(
+                "{:02x}-{}-{}-{:02x}",
+                SUPPORTED_VERSION,
+                span_context.trace_id(),
+                span_context.span_id(),
+                span_context.trace_flags() & TraceFlags::SAMPLED
+            )
+            .into(),
+        ),
+    );
+    let trace_state = span_context.trace_state().header();
+    if !trace_state.is_empty() {
+        headers.insert(
+            TRACESTATE_HEADER.into(),
+            AMQPValue::LongString(trace_state.into()),
+        );
+    }
+    properties.with_headers(headers)
+}
+
+/// Extract the remote span context from the headers of `properties`.
+///
+/// Returns an empty context if there is no valid `traceparent` header.
+pub fn extract(properties: &BasicProperties) -> Context {
+    properties
+        .headers()
+        .as_ref()
+        .and_then(extract_span_context)
+        .map(|span_context| Context::new().with_remote_span_context(span_context))
+        .unwrap_or_default()
+}
+
+fn extract_span_context(headers: &FieldTable) -> Option<SpanContext> {
+    let traceparent = header(headers, TRACEPARENT_HEADER)?;
+    let mut parts = traceparent.trim().split('-');
+    let version = parse_hex_u8(parts.next()?)?;
+    let trace_id = parts.next().filter(|id| is_lower_hex(id, 32))?;
+    let span_id = parts.next().filter(|id| is_lower_hex(id, 16))?;
+    let flags = parse_hex_u8(parts.next()?)?;
+    // Version 255 is invalid, and version 0 has exactly 4 fields. Future versions may add fields.
+    if version == 0xff || (version == SUPPORTED_VERSION && parts.next().is_some()) {
+        return None;
+    }
+    let trace_id = TraceId::from_hex(trace_id).ok()?;
+    let span_id = SpanId::from_hex(span_id).ok()?;
+    let trace_state = header(headers, TRACESTATE_HEADER)
+        .and_then(|trace_state| TraceState::from_str(&trace_state).ok())
+        .unwrap_or_default();
+    let span_context = SpanContext::new(
+        trace_id,
+        span_id,
+        TraceFlags::new(flags) & TraceFlags::SAMPLED,
+        true,
+        trace_state,
+    );
+    span_context.is_valid().then_some(span_context)
+}
+
+fn header(headers: &FieldTable, name: &str) -> Option<String> {
+    match headers.inner().get(&ShortString::from(name))? {
+        AMQPValue::LongString(value) => Some(String::from_utf8_lossy(value.as_bytes()).into()),
+        AMQPValue::ShortString(value) => Some(value.as_str().into()),
+        _ => None,
+    }
+}
+
+fn is_lower_hex(value: &str, len: usize) -> bool {
+    value.len() == len
+        && value
+            .bytes()
+            .all(|byte| byte.is_ascii_digit() || (b'a'..=b'f').contains(&byte))
+}
+
+fn parse_hex_u8(value: &str) -> Option<u8> {
+    is_lower_hex(value, 2)
+        .then(|| u8::from_str_radix(value, 16).ok())
+        .flatten()
+}
+
+// The span of a delivery, shared by the clones of its Acker. It only gets created on the first
+// call to span(), so that it doesn't cover the time spent waiting in the consumer buffer.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DeliverySpan(Arc<Mutex<DeliverySpanState>>);
+
+#[derive(Debug, Default)]
+enum DeliverySpanState {
+    #[default]
+    Empty,
+    Received {
+        exchange: ShortString,
+        routing_key: ShortString,
+        parent: Context,
+    },
+    Started(Span),
+}
+
+impl DeliverySpan {
+    pub(crate) fn receive(
+        &self,
+        exchange: ShortString,
+        routing_key: ShortString,
+        properties: &BasicProperties,
+    ) {
+        *self.lock() = DeliverySpanState::Received {
+            exchange,
+            routing_key,
+            parent: extract(properties),
+        };
+    }
+
+    pub(crate) fn span(&self) -> Span {
+        let mut state = self.lock();
+        if matches!(*state, DeliverySpanState::Received { .. })
+            && let DeliverySpanState::Received {
+                exchange,
+                routing_key,
+                parent,
+            } = mem::take(&mut *state)
+        {
+            let span = info_span!(
+                parent: None,
+                "lapin.delivery",
+                otel.kind = "consumer",
+                messaging.system = "rabbitmq",
+                messaging.operation = "process",
+                messaging.destination.name = exchange.as_str(),
+                messaging.rabbitmq.destination.routing_key = routing_key.as_str(),
+                messaging.rabbitmq.acknowledgement = Empty,
+            );
+            // Fails if the span is disabled or no OpenTelemetry layer is registered, nothing to do
+            // then
+            let _ = span.set_parent(parent);
+            *state = DeliverySpanState::Started(span);
+        }
+        match &*state {
+            DeliverySpanState::Started(span) => span.clone(),
+            _ => Span::none(),
+        }
+    }
+
+    pub(crate) fn finish(&self, acknowledgement: &str) {
+        if let DeliverySpanState::Started(span) = mem::take(&mut *self.lock()) {
+            span.record("messaging.rabbitmq.acknowledgement", acknowledgement);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, DeliverySpanState> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+pub(crate) fn inject_current(properties: BasicProperties) -> BasicProperties {
+    inject(&Span::current().context(), properties)
+}
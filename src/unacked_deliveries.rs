@@ -0,0 +1,50 @@
+use crate::types::DeliveryTag;
+use std::{
+    collections::BTreeSet,
+    fmt,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+// The delivery tags received on a channel which still need to be acked, nacked or rejected
+#[derive(Clone, Default)]
+pub(crate) struct UnackedDeliveries(Arc<Mutex<BTreeSet<DeliveryTag>>>);
+
+impl UnackedDeliveries {
+    pub(crate) fn register(&self, delivery_tag: DeliveryTag) {
+        self.lock().insert(delivery_tag);
+    }
+
+    pub(crate) fn acknowledge(&self, multiple: bool, delivery_tag: DeliveryTag) {
+        let mut inner = self.lock();
+        if !multiple {
+            inner.remove(&delivery_tag);
+        } else if delivery_tag == 0 {
+            inner.clear();
+        } else {
+            *inner = inner.split_off(&(delivery_tag + 1));
+        }
+    }
+
+    // The server forgets about the delivery tags on basic.recover or when the channel goes away
+    pub(crate) fn clear(&self) {
+        self.lock().clear();
+    }
+
+    pub(crate) fn count(&self) -> usize {
+        self.lock().len()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeSet<DeliveryTag>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl fmt::Debug for UnackedDeliveries {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_tuple("UnackedDeliveries");
+        if let Ok(inner) = self.0.try_lock() {
+            debug.field(&inner.len());
+        }
+        debug.finish()
+    }
+}
@@ -8,6 +8,7 @@ pub mod options {
   {{#unless @argument_is_value ~}}
   {{#unless argument.ignore_flags ~}}
   #[derive(Copy, Clone, Debug, Default, PartialEq)]
+  #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
   pub struct {{camel class.name}}{{camel method.name}}Options {
     {{#each argument.flags as |flag| ~}}
     pub {{snake flag.name}}: Boolean,
@@ -141,9 +142,11 @@ impl Channel {
       self.receive_{{snake class.name false}}_{{snake method.name false}}_ok(nowait_reply)?;
     }
     {{/if ~}}
-    {{/if ~}}
+    self.wait_for_reply("{{class.name}}.{{method.name}}", promise).await
+    {{else}}
     promise.await
     {{/if ~}}
+    {{/if ~}}
   }
   {{/if ~}}
 
@@ -0,0 +1,89 @@
+// Helpers shared by the tests running against the fake broker
+#![allow(dead_code)]
+
+use async_rs::Runtime;
+use futures_lite::stream::StreamExt;
+use lapin::{
+    BasicProperties, Channel, Confirmation, Connection, ConnectionProperties, Event,
+    options::BasicPublishOptions, testing::FakeBroker, uri::AMQPUri,
+};
+use std::{
+    io,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+// Connect to the broker through a custom connector, which lets us pick the uri
+pub async fn connect(
+    broker: &FakeBroker,
+    uri: AMQPUri,
+    options: ConnectionProperties,
+) -> Connection {
+    let broker = broker.clone();
+    Connection::connector(
+        uri,
+        Runtime::tokio_current(),
+        async move |_, _| Ok(broker.stream()),
+        options,
+    )
+    .await
+    .unwrap()
+}
+
+// Connect to the broker, refusing to reconnect while `refuse` is set
+pub async fn connect_refusable(
+    broker: &FakeBroker,
+    refuse: &Arc<AtomicBool>,
+    options: ConnectionProperties,
+) -> Connection {
+    let broker = broker.clone();
+    let refuse = refuse.clone();
+    Connection::connector(
+        AMQPUri::default(),
+        Runtime::tokio_current(),
+        async move |_, _| {
+            if refuse.load(Ordering::SeqCst) {
+                return Err(io::Error::from(io::ErrorKind::ConnectionRefused).into());
+            }
+            Ok(broker.stream())
+        },
+        options,
+    )
+    .await
+    .unwrap()
+}
+
+// Publish a mandatory message and wait for its confirmation
+pub async fn publish(
+    channel: &Channel,
+    exchange: &str,
+    routing_key: &str,
+    properties: BasicProperties,
+) -> Confirmation {
+    channel
+        .basic_publish(
+            exchange.into(),
+            routing_key.into(),
+            BasicPublishOptions {
+                mandatory: true,
+                ..BasicPublishOptions::default()
+            },
+            b"payload",
+            properties,
+        )
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+}
+
+// Subscribe right away so that we don't miss the event
+pub fn wait_for_event(
+    connection: &Connection,
+    matcher: impl Fn(&Event) -> bool,
+) -> impl Future<Output = ()> {
+    let mut events = connection.events_listener();
+    async move { while !events.next().await.is_some_and(|event| matcher(&event)) {} }
+}
@@ -1,6 +1,6 @@
 use lapin::{
-    BasicProperties, Confirmation, Connection, ConnectionProperties, ConsumerDelegate,
-    message::DeliveryResult, options::*, types::FieldTable,
+    BasicProperties, Confirmation, ConnectionProperties, ConsumerDelegate, message::DeliveryResult,
+    options::*, testing::FakeBroker, types::FieldTable,
 };
 use std::{
     future::Future,
@@ -47,9 +47,9 @@ async fn tokio_main() {
 
     let _ = tracing_subscriber::fmt::try_init();
 
-    let addr = std::env::var("AMQP_ADDR").unwrap_or_else(|_| "amqp://127.0.0.1:5672/%2f".into());
-
-    let conn = Connection::connect(&addr, ConnectionProperties::default())
+    let broker = FakeBroker::new();
+    let conn = broker
+        .connect(ConnectionProperties::default())
         .await
         .expect("connection error");
 
@@ -0,0 +1,720 @@
+mod common;
+
+use common::publish;
+use futures_lite::stream::StreamExt;
+use lapin::{
+    BasicProperties, Channel, Confirmation, Connection, ConnectionProperties, ErrorKind, Event,
+    ExchangeKind, RecoveryListener,
+    metrics::AtomicMetrics,
+    options::{
+        BasicAckOptions, BasicConsumeOptions, BasicGetOptions, BasicNackOptions, BasicQosOptions,
+        ConfirmSelectOptions, ExchangeDeclareOptions, QueueBindOptions, QueueDeclareOptions,
+        QueueDeleteOptions,
+    },
+    protocol::{AMQPErrorKind, AMQPSoftError},
+    testing::FakeBroker,
+    topology::{ConsumerDefinition, QueueDefinition, Topology},
+    types::{AMQPValue, ChannelId, FieldTable},
+};
+use std::{
+    io,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+async fn declare_bound_queue(
+    channel: &Channel,
+    queue: &str,
+    exchange: &str,
+    routing_key: &str,
+    arguments: FieldTable,
+) {
+    channel
+        .queue_declare(
+            queue.into(),
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+    channel
+        .queue_bind(
+            queue.into(),
+            exchange.into(),
+            routing_key.into(),
+            QueueBindOptions::default(),
+            arguments,
+        )
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn publish_consume_and_get() {
+    let broker = FakeBroker::new();
+    let connection = broker.connect(Default::default()).await.unwrap();
+    let publisher = connection.create_channel().await.unwrap();
+    let consumer_channel = connection.create_channel().await.unwrap();
+    publisher
+        .confirm_select(ConfirmSelectOptions::default())
+        .await
+        .unwrap();
+    let queue = publisher
+        .queue_declare(
+            "".into(),
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+    assert!(queue.name().as_str().starts_with("amq.gen-"));
+
+    let confirmation = publish(
+        &publisher,
+        "",
+        queue.name().as_str(),
+        BasicProperties::default(),
+    )
+    .await;
+    assert_eq!(confirmation, Confirmation::Ack(None));
+    assert_eq!(broker.message_count(queue.name().as_str()), Some(1));
+
+    let message = publisher
+        .basic_get(queue.name().clone(), BasicGetOptions::default())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(message.delivery.data, b"payload");
+    message
+        .delivery
+        .nack(BasicNackOptions {
+            requeue: true,
+            ..BasicNackOptions::default()
+        })
+        .await
+        .unwrap();
+
+    consumer_channel
+        .basic_qos(1, BasicQosOptions::default())
+        .await
+        .unwrap();
+    let mut consumer = consumer_channel
+        .basic_consume(
+            queue.name().clone(),
+            "".into(),
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(broker.consumer_count(queue.name().as_str()), Some(1));
+    publish(
+        &publisher,
+        "",
+        queue.name().as_str(),
+        BasicProperties::default(),
+    )
+    .await;
+
+    let delivery = consumer.next().await.unwrap().unwrap();
+    assert!(delivery.redelivered);
+    // The prefetch keeps the second message in the queue until the first one gets acked
+    assert_eq!(broker.message_count(queue.name().as_str()), Some(1));
+    delivery.ack(BasicAckOptions::default()).await.unwrap();
+    let delivery = consumer.next().await.unwrap().unwrap();
+    assert!(!delivery.redelivered);
+    delivery.ack(BasicAckOptions::default()).await.unwrap();
+    assert_eq!(broker.message_count(queue.name().as_str()), Some(0));
+}
+
+#[tokio::test]
+async fn routing() {
+    let broker = FakeBroker::new();
+    let connection = broker.connect(Default::default()).await.unwrap();
+    let channel = connection.create_channel().await.unwrap();
+    channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .await
+        .unwrap();
+
+    declare_bound_queue(
+        &channel,
+        "direct",
+        "amq.direct",
+        "key",
+        FieldTable::default(),
+    )
+    .await;
+    declare_bound_queue(&channel, "fanout", "amq.fanout", "", FieldTable::default()).await;
+    declare_bound_queue(
+        &channel,
+        "topic",
+        "amq.topic",
+        "a.*.#",
+        FieldTable::default(),
+    )
+    .await;
+    let mut arguments = FieldTable::default();
+    arguments.insert("x-match".into(), AMQPValue::LongString("any".into()));
+    arguments.insert("format".into(), AMQPValue::LongString("pdf".into()));
+    arguments.insert("type".into(), AMQPValue::LongString("report".into()));
+    declare_bound_queue(&channel, "headers", "amq.headers", "", arguments).await;
+
+    publish(&channel, "amq.direct", "key", BasicProperties::default()).await;
+    publish(
+        &channel,
+        "amq.fanout",
+        "whatever",
+        BasicProperties::default(),
+    )
+    .await;
+    publish(&channel, "amq.topic", "a.b", BasicProperties::default()).await;
+    publish(&channel, "amq.topic", "a.b.c.d", BasicProperties::default()).await;
+    let mut headers = FieldTable::default();
+    headers.insert("format".into(), AMQPValue::LongString("pdf".into()));
+    publish(
+        &channel,
+        "amq.headers",
+        "",
+        BasicProperties::default().with_headers(headers),
+    )
+    .await;
+
+    assert_eq!(broker.message_count("direct"), Some(1));
+    assert_eq!(broker.message_count("fanout"), Some(1));
+    assert_eq!(broker.message_count("topic"), Some(2));
+    assert_eq!(broker.message_count("headers"), Some(1));
+
+    let confirmation = publish(&channel, "amq.topic", "b.a", BasicProperties::default()).await;
+    let returned = confirmation.take_message().unwrap();
+    assert_eq!(returned.reply_code, AMQPSoftError::NOROUTE.get_id());
+    assert_eq!(returned.delivery.data, b"payload");
+}
+
+#[tokio::test]
+async fn errors() {
+    let broker = FakeBroker::new();
+    let connection = broker.connect(Default::default()).await.unwrap();
+    let channel = connection.create_channel().await.unwrap();
+    channel
+        .exchange_declare(
+            "events".into(),
+            ExchangeKind::Topic,
+            ExchangeDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+    assert!(broker.has_exchange("events"));
+
+    let error = channel
+        .exchange_declare(
+            "events".into(),
+            ExchangeKind::Fanout,
+            ExchangeDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap_err();
+    match error.kind() {
+        ErrorKind::ProtocolError(error) => assert_eq!(
+            error.kind(),
+            &AMQPErrorKind::Soft(AMQPSoftError::PRECONDITIONFAILED)
+        ),
+        error => panic!("unexpected error: {error:?}"),
+    }
+    assert!(!channel.status().connected());
+
+    let channel = connection.create_channel().await.unwrap();
+    let error = channel
+        .queue_declare(
+            "missing".into(),
+            QueueDeclareOptions {
+                passive: true,
+                ..QueueDeclareOptions::default()
+            },
+            FieldTable::default(),
+        )
+        .await
+        .unwrap_err();
+    match error.kind() {
+        ErrorKind::ProtocolError(error) => assert_eq!(
+            error.get_message().as_str(),
+            "NOT_FOUND - no queue 'missing' in vhost '/'"
+        ),
+        error => panic!("unexpected error: {error:?}"),
+    }
+    assert!(connection.status().connected());
+}
+
+#[tokio::test]
+async fn recovery() {
+    let broker = FakeBroker::new();
+    let connection = broker
+        .connect(ConnectionProperties::default().enable_auto_recover())
+        .await
+        .unwrap();
+    let mut events = connection.events_listener();
+    let channel = connection.create_channel().await.unwrap();
+    channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .await
+        .unwrap();
+    declare_bound_queue(
+        &channel,
+        "durable",
+        "amq.direct",
+        "key",
+        FieldTable::default(),
+    )
+    .await;
+    let mut consumer = channel
+        .basic_consume(
+            "durable".into(),
+            "consumer".into(),
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+
+    broker.disconnect_all();
+    assert_eq!(broker.consumer_count("durable"), Some(0));
+    while !matches!(
+        events.next().await,
+        Some(Event::RecoverySucceeded { channel_id }) if channel_id == channel.id()
+    ) {}
+    assert_eq!(broker.consumer_count("durable"), Some(1));
+
+    let confirmation = publish(&channel, "amq.direct", "key", BasicProperties::default()).await;
+    assert_eq!(confirmation, Confirmation::Ack(None));
+    let delivery = consumer.next().await.unwrap().unwrap();
+    assert_eq!(delivery.data, b"payload");
+}
+
+struct SkipConsumer;
+
+impl RecoveryListener for SkipConsumer {
+    fn filter_consumer(&self, _channel_id: ChannelId, consumer: &mut ConsumerDefinition) -> bool {
+        consumer.tag().as_str() != "skipped"
+    }
+}
+
+#[tokio::test]
+async fn recovery_skips_consumer() {
+    let broker = FakeBroker::new();
+    let connection = broker
+        .connect(
+            ConnectionProperties::default()
+                .enable_auto_recover()
+                .with_recovery_listener(SkipConsumer),
+        )
+        .await
+        .unwrap();
+    let mut events = connection.events_listener();
+    let channel = connection.create_channel().await.unwrap();
+    declare_bound_queue(
+        &channel,
+        "durable",
+        "amq.direct",
+        "key",
+        FieldTable::default(),
+    )
+    .await;
+    let mut consumers = Vec::new();
+    for tag in ["kept", "skipped"] {
+        consumers.push(
+            channel
+                .basic_consume(
+                    "durable".into(),
+                    tag.into(),
+                    BasicConsumeOptions::default(),
+                    FieldTable::default(),
+                )
+                .await
+                .unwrap(),
+        );
+    }
+
+    broker.disconnect_all();
+    while !matches!(
+        events.next().await,
+        Some(Event::RecoverySucceeded { channel_id }) if channel_id == channel.id()
+    ) {}
+    assert_eq!(broker.consumer_count("durable"), Some(1));
+    // The skipped consumer is gone for good
+    let topology = channel.topology();
+    assert_eq!(topology.consumers.len(), 1);
+    assert_eq!(topology.consumers[0].tag().as_str(), "kept");
+    let snapshot = channel.snapshot();
+    assert_eq!(snapshot.consumers.len(), 1);
+    assert_eq!(snapshot.consumers[0].tag.as_str(), "kept");
+    assert!(consumers[1].next().await.is_none());
+}
+
+#[tokio::test]
+async fn recovery_replays_connection_topology() {
+    let broker = FakeBroker::new();
+    let connection = broker
+        .connect(
+            ConnectionProperties::default()
+                .enable_auto_recover()
+                .enable_connection_topology(),
+        )
+        .await
+        .unwrap();
+    let mut events = connection.events_listener();
+    let channel = connection.create_channel().await.unwrap();
+    channel
+        .queue_declare(
+            "replayed".into(),
+            QueueDeclareOptions {
+                exclusive: true,
+                ..QueueDeclareOptions::default()
+            },
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+    channel
+        .queue_bind(
+            "replayed".into(),
+            "amq.direct".into(),
+            "key".into(),
+            QueueBindOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+    channel
+        .queue_declare(
+            "conflict".into(),
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+    // The declarations outlive the channel in the connection-wide registry
+    channel.close(200, "OK".into()).await.unwrap();
+
+    // Someone else redeclares the queue with other options, so replaying it will fail
+    let other = broker.connect(Default::default()).await.unwrap();
+    let other_channel = other.create_channel().await.unwrap();
+    other_channel
+        .queue_delete("conflict".into(), QueueDeleteOptions::default())
+        .await
+        .unwrap();
+    other_channel
+        .queue_declare(
+            "conflict".into(),
+            QueueDeclareOptions {
+                durable: true,
+                ..QueueDeclareOptions::default()
+            },
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+
+    broker.disconnect_all();
+    assert_eq!(broker.message_count("replayed"), None);
+    while !matches!(
+        events.next().await,
+        Some(Event::TopologyRecoveryFailed { .. })
+    ) {}
+    while broker.message_count("replayed").is_none() {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    let channel = connection.create_channel().await.unwrap();
+    channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .await
+        .unwrap();
+    let confirmation = publish(&channel, "amq.direct", "key", BasicProperties::default()).await;
+    assert_eq!(confirmation, Confirmation::Ack(None));
+    assert_eq!(broker.message_count("replayed"), Some(1));
+}
+
+// Lose the connection again while the connection-wide topology gets restored
+struct DisconnectDuringRestore {
+    broker: FakeBroker,
+    disconnected: AtomicBool,
+}
+
+impl RecoveryListener for DisconnectDuringRestore {
+    fn filter_queue(&self, _channel_id: ChannelId, _queue: &mut QueueDefinition) -> bool {
+        if !self.disconnected.swap(true, Ordering::SeqCst) {
+            self.broker.disconnect_all();
+        }
+        true
+    }
+}
+
+#[tokio::test]
+async fn recovery_channel_stays_hidden() {
+    let broker = FakeBroker::new();
+    let metrics = Arc::new(AtomicMetrics::default());
+    let connection = broker
+        .connect(
+            ConnectionProperties::default()
+                .enable_auto_recover()
+                .enable_connection_topology()
+                .with_metrics(metrics.clone())
+                .with_recovery_listener(DisconnectDuringRestore {
+                    broker: broker.clone(),
+                    disconnected: AtomicBool::new(false),
+                }),
+        )
+        .await
+        .unwrap();
+    let mut events = connection.events_listener();
+    let channel = connection.create_channel().await.unwrap();
+    channel
+        .queue_declare(
+            "queue".into(),
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+
+    broker.disconnect_all();
+    while !matches!(
+        events.next().await,
+        Some(Event::RecoverySucceeded { channel_id }) if channel_id == channel.id()
+    ) {}
+    // Neither the channel which restored the topology nor the one lost while doing it remain
+    let snapshot = connection.snapshot();
+    assert_eq!(snapshot.channels.len(), 1);
+    assert_eq!(snapshot.channels[0].channel_id, channel.id());
+    assert_eq!(connection.topology().channels.len(), 1);
+    assert_eq!(metrics.snapshot().channels, 1);
+    assert_eq!(metrics.snapshot().recoveries, 1);
+}
+
+#[tokio::test]
+async fn verify_topology() {
+    let broker = FakeBroker::new();
+    let owner = broker.connect(Default::default()).await.unwrap();
+    let owner_channel = owner.create_channel().await.unwrap();
+    owner_channel
+        .queue_declare(
+            "audit".into(),
+            QueueDeclareOptions {
+                durable: true,
+                ..QueueDeclareOptions::default()
+            },
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+    owner_channel
+        .queue_declare(
+            "locked".into(),
+            QueueDeclareOptions {
+                exclusive: true,
+                ..QueueDeclareOptions::default()
+            },
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+
+    let connection = broker.connect(Default::default()).await.unwrap();
+    let channel = connection.create_channel().await.unwrap();
+    let topology = Topology::new()
+        .with_exchange(
+            "missing".into(),
+            ExchangeKind::Topic,
+            ExchangeDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .with_queue(
+            "audit".into(),
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .with_queue(
+            "locked".into(),
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        );
+
+    let report = channel.verify_topology(&topology).await.unwrap();
+    assert!(!report.is_valid());
+    assert_eq!(report.missing_exchanges, vec!["missing".into()]);
+    assert!(report.mismatched_exchanges.is_empty());
+    assert!(report.missing_queues.is_empty());
+    assert_eq!(report.mismatched_queues.len(), 1);
+    assert_eq!(report.mismatched_queues[0].name.as_str(), "locked");
+    assert!(!broker.has_exchange("missing"));
+
+    let report = channel.verify_topology_strict(&topology).await.unwrap();
+    assert_eq!(report.missing_exchanges, vec!["missing".into()]);
+    let mismatched = report
+        .mismatched_queues
+        .iter()
+        .map(|mismatch| mismatch.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(mismatched, ["audit", "locked"]);
+    assert!(
+        report.mismatched_queues[0]
+            .reason
+            .as_str()
+            .contains("durable")
+    );
+    assert!(!broker.has_exchange("missing"));
+    assert!(channel.status().connected());
+}
+
+// Refuse to reconnect while `refuse` is set, and give up after one attempt
+async fn connect_refusable(
+    broker: &FakeBroker,
+    refuse: &Arc<AtomicBool>,
+    options: ConnectionProperties,
+) -> Connection {
+    common::connect_refusable(
+        broker,
+        refuse,
+        options
+            .configure_backoff(|backoff| {
+                backoff
+                    .with_min_delay(Duration::from_millis(1))
+                    .with_max_times(1)
+            })
+            .enable_auto_recover(),
+    )
+    .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn recovery_exhausted() {
+    let broker = FakeBroker::new();
+    let refuse = Arc::new(AtomicBool::new(false));
+    let connection = connect_refusable(&broker, &refuse, ConnectionProperties::default()).await;
+    let channel = connection.create_channel().await.unwrap();
+
+    refuse.store(true, Ordering::SeqCst);
+    broker.disconnect_all();
+    let error = channel
+        .queue_declare(
+            "queue".into(),
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap_err();
+    // We give up once the backoff ran out
+    assert!(channel.wait_for_recovery(error).await.is_err());
+    assert!(channel.status().errored());
+    assert!(connection.status().errored());
+    assert!(connection.status().recovery_status().exhausted());
+    let res = tokio::task::spawn_blocking(move || connection.run())
+        .await
+        .unwrap();
+    assert!(matches!(
+        res.unwrap_err().kind(),
+        ErrorKind::IOError(err) if err.kind() == io::ErrorKind::ConnectionRefused
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn manual_recovery() {
+    let broker = FakeBroker::new();
+    let refuse = Arc::new(AtomicBool::new(false));
+    let connection = connect_refusable(
+        &broker,
+        &refuse,
+        ConnectionProperties::default().enable_manual_recovery(),
+    )
+    .await;
+    let channel = connection.create_channel().await.unwrap();
+
+    refuse.store(true, Ordering::SeqCst);
+    broker.disconnect_all();
+    while !connection.status().recovery_status().exhausted() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    // We wait to be asked to try again
+    assert!(connection.status().reconnecting());
+
+    refuse.store(false, Ordering::SeqCst);
+    connection.recover().await.unwrap();
+    assert!(connection.status().connected());
+    channel
+        .queue_declare(
+            "queue".into(),
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn recovery_with_unsent_frames() {
+    let broker = FakeBroker::new();
+    let connection = broker
+        .connect(ConnectionProperties::default().enable_auto_recover())
+        .await
+        .unwrap();
+    let mut events = connection.events_listener();
+    let channel = connection.create_channel().await.unwrap();
+
+    // The stream breaks while queue.declare gets written
+    broker.disconnect_all();
+    let declared = channel
+        .queue_declare(
+            "queue".into(),
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .await;
+    assert!(declared.is_err());
+    // The frame which couldn't be written doesn't hold back the reconnection
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !matches!(
+            events.next().await,
+            Some(Event::RecoverySucceeded { channel_id }) if channel_id == channel.id()
+        ) {}
+    })
+    .await
+    .unwrap();
+    assert!(connection.status().connected());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn wait_for_recovery_of_unsent_frame() {
+    let broker = FakeBroker::new();
+    let connection = broker
+        .connect(ConnectionProperties::default().enable_auto_recover())
+        .await
+        .unwrap();
+    let channel = connection.create_channel().await.unwrap();
+
+    // The stream breaks while queue.declare gets written, which fails with the raw IO error
+    broker.disconnect_all();
+    let error = channel
+        .queue_declare(
+            "queue".into(),
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(error.kind(), ErrorKind::IOError(_)));
+    channel.wait_for_recovery(error).await.unwrap();
+    assert!(channel.status().connected());
+    channel
+        .queue_declare(
+            "queue".into(),
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+}
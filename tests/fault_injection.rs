@@ -0,0 +1,146 @@
+mod common;
+
+use async_rs::Runtime;
+use common::wait_for_event;
+use lapin::{
+    Connection, ConnectionProperties, ErrorKind, Event,
+    options::QueueDeclareOptions,
+    testing::{FakeBroker, FaultInjector},
+    types::FieldTable,
+    uri::AMQPUri,
+};
+use std::time::{Duration, Instant};
+
+async fn connect(uri: AMQPUri, options: ConnectionProperties) -> (FaultInjector, Connection) {
+    let runtime = Runtime::tokio_current();
+    let broker = FakeBroker::new();
+    let faults = FaultInjector::new(runtime.clone());
+    let connection = {
+        let faults = faults.clone();
+        Connection::connector(
+            uri,
+            runtime,
+            async move |_, _| Ok(faults.wrap(broker.stream())),
+            options,
+        )
+        .await
+        .unwrap()
+    };
+    (faults, connection)
+}
+
+#[tokio::test]
+async fn disconnect_and_recover() {
+    let (faults, connection) = connect(
+        AMQPUri::default(),
+        ConnectionProperties::default().enable_auto_recover(),
+    )
+    .await;
+    let channel = connection.create_channel().await.unwrap();
+    let recovered = wait_for_event(
+        &connection,
+        |event| matches!(event, Event::RecoverySucceeded { channel_id } if *channel_id == channel.id()),
+    );
+
+    // Drop the connection when receiving queue.declare-ok
+    faults.disconnect_after_frames(1);
+    let declared = channel
+        .queue_declare(
+            "queue".into(),
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .await;
+    if let Err(error) = declared {
+        channel.wait_for_recovery(error).await.unwrap();
+    }
+    recovered.await;
+    assert!(connection.status().connected());
+
+    faults.disconnect();
+    let error = channel
+        .queue_declare(
+            "queue".into(),
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap_err();
+    channel.wait_for_recovery(error).await.unwrap();
+    assert!(channel.status().connected());
+}
+
+#[tokio::test]
+async fn corrupted_frame() {
+    let (faults, connection) = connect(AMQPUri::default(), ConnectionProperties::default()).await;
+    let channel = connection.create_channel().await.unwrap();
+    faults.corrupt_next_frame();
+    let error = channel
+        .queue_declare(
+            "queue".into(),
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(error.kind(), ErrorKind::ParsingError(_)),
+        "unexpected error: {error:?}"
+    );
+    assert!(connection.status().errored());
+}
+
+#[tokio::test]
+async fn stalled_reads() {
+    let (faults, connection) = connect(
+        "amqp://localhost/%2f?heartbeat=1".parse().unwrap(),
+        ConnectionProperties::default(),
+    )
+    .await;
+    let missed = wait_for_event(&connection, |event| {
+        matches!(event, Event::HeartbeatMissed { .. })
+    });
+    faults.stall_reads();
+    missed.await;
+    assert!(connection.status().errored());
+}
+
+#[tokio::test]
+async fn latency() {
+    let (faults, connection) = connect(AMQPUri::default(), ConnectionProperties::default()).await;
+    let channel = connection.create_channel().await.unwrap();
+    faults.set_latency(Some(Duration::from_millis(100)));
+    let start = Instant::now();
+    channel
+        .queue_declare(
+            "queue".into(),
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(100));
+}
+
+#[tokio::test]
+async fn latency_while_idle() {
+    let (faults, connection) = connect(AMQPUri::default(), ConnectionProperties::default()).await;
+    let channel = connection.create_channel().await.unwrap();
+    faults.set_latency(Some(Duration::from_millis(10)));
+    // Stay idle longer than the latency between the two RPCs
+    for queue in ["first", "second"] {
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            channel.queue_declare(
+                queue.into(),
+                QueueDeclareOptions::default(),
+                FieldTable::default(),
+            ),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(connection.status().connected());
+}
@@ -0,0 +1,110 @@
+use lapin::{
+    BasicProperties, Connection, ConnectionProperties,
+    options::{BasicGetOptions, BasicPublishOptions, QueueDeclareOptions},
+    recorder::{FrameDirection, FrameRecord, FrameRecordReader, FrameRecorder, PayloadPolicy},
+    testing::{AMQPFrame, FakeBroker, ProtocolVersion, Script, ScriptedPeer},
+    types::FieldTable,
+};
+use std::{
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+async fn record(payload_policy: PayloadPolicy) -> Vec<FrameRecord> {
+    let buffer = SharedBuffer::default();
+    let recorder = FrameRecorder::new(buffer.clone())
+        .unwrap()
+        .with_payload_policy(payload_policy);
+    let connection = FakeBroker::new()
+        .connect(ConnectionProperties::default().with_frame_recorder(recorder.clone()))
+        .await
+        .unwrap();
+    assert_eq!(publish_and_get(&connection).await, b"payload");
+    recorder.flush().unwrap();
+    let bytes = buffer.0.lock().unwrap().clone();
+    FrameRecordReader::new(&bytes[..])
+        .unwrap()
+        .collect::<io::Result<_>>()
+        .unwrap()
+}
+
+async fn publish_and_get(connection: &Connection) -> Vec<u8> {
+    let channel = connection.create_channel().await.unwrap();
+    channel
+        .queue_declare(
+            "queue".into(),
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+    channel
+        .basic_publish(
+            "".into(),
+            "queue".into(),
+            BasicPublishOptions::default(),
+            b"payload",
+            BasicProperties::default(),
+        )
+        .await
+        .unwrap();
+    let message = channel
+        .basic_get("queue".into(), BasicGetOptions::default())
+        .await
+        .unwrap()
+        .unwrap();
+    message.delivery.data
+}
+
+fn inbound_payload(records: &[FrameRecord]) -> &FrameRecord {
+    records
+        .iter()
+        .find(|record| {
+            record.direction == FrameDirection::Inbound
+                && matches!(record.frame, AMQPFrame::Body(..))
+        })
+        .unwrap()
+}
+
+#[tokio::test]
+async fn record_and_replay() {
+    let records = record(PayloadPolicy::Truncate(3)).await;
+    assert_eq!(records[0].direction, FrameDirection::Outbound);
+    assert_eq!(
+        records[0].frame,
+        AMQPFrame::ProtocolHeader(ProtocolVersion::amqp_0_9_1())
+    );
+    let payload = inbound_payload(&records);
+    assert_eq!(payload.channel_id, 1);
+    assert_eq!(payload.truncated, 4);
+    assert_eq!(payload.frame, AMQPFrame::Body(1, b"pay".to_vec()));
+
+    let peer = ScriptedPeer::new(Script::replay(records));
+    let connection = peer.connect(Default::default()).await.unwrap();
+    assert_eq!(publish_and_get(&connection).await, b"pay\0\0\0\0");
+    peer.finished().await;
+    peer.assert_finished();
+}
+
+#[tokio::test]
+async fn redacted_payloads() {
+    let records = record(PayloadPolicy::Redact).await;
+    let payload = inbound_payload(&records);
+    assert_eq!(payload.truncated, 0);
+    assert_eq!(payload.frame, AMQPFrame::Body(1, vec![0; 7]));
+    assert!(records.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+}
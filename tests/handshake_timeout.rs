@@ -0,0 +1,23 @@
+use lapin::{Connection, ConnectionProperties, ErrorKind};
+use std::{net::TcpListener, time::Duration};
+
+#[tokio::test]
+async fn handshake_timeout() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    // A server accepting connections but never answering the protocol header
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let _streams = listener.incoming().collect::<Vec<_>>();
+    });
+
+    let options =
+        ConnectionProperties::default().with_handshake_timeout(Duration::from_millis(100));
+    let res = Connection::connect(&format!("amqp://guest:guest@{addr}"), options).await;
+    let err = res.unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::Timeout("connection handshake")
+    ));
+}
@@ -0,0 +1,181 @@
+mod common;
+
+use async_rs::Runtime;
+use common::{connect_refusable, wait_for_event};
+use lapin::{
+    ConnectionProperties, ConnectionState, ErrorKind, Event,
+    health::Health,
+    metrics::AtomicMetrics,
+    protocol::{AMQPClass, channel, connection},
+    testing::{FakeBroker, Script, ScriptedPeer},
+};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+#[tokio::test]
+async fn healthy() {
+    let broker = FakeBroker::new();
+    let connection = broker
+        .connect(ConnectionProperties::default())
+        .await
+        .unwrap();
+    assert_eq!(connection.health(Duration::from_secs(60)), Health::Healthy);
+    assert_eq!(
+        connection.health_check(Duration::from_secs(1)).await,
+        Health::Healthy
+    );
+    // The internal channel gets reused
+    assert_eq!(
+        connection.health_check(Duration::from_secs(1)).await,
+        Health::Healthy
+    );
+    // The internal channel doesn't show up to the user
+    assert!(connection.snapshot().channels.is_empty());
+    assert!(connection.topology().channels.is_empty());
+
+    // We didn't hear from the server for too long
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(matches!(
+        connection.health(Duration::from_millis(10)),
+        Health::Unresponsive {
+            since_last_inbound: Some(_),
+            error: None,
+        }
+    ));
+
+    connection.close(200, "OK".into()).await.unwrap();
+    let health = connection.health(Duration::from_secs(60));
+    assert_eq!(
+        health,
+        Health::Down {
+            state: ConnectionState::Closed
+        }
+    );
+    assert!(!health.is_live());
+    assert_eq!(
+        connection.health_check(Duration::from_secs(1)).await,
+        health
+    );
+}
+
+#[tokio::test]
+async fn internal_channel() {
+    let broker = FakeBroker::new();
+    let metrics = Arc::new(AtomicMetrics::default());
+    let connection = broker
+        .connect(
+            ConnectionProperties::default()
+                .enable_auto_recover()
+                .with_metrics(metrics.clone()),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        connection.health_check(Duration::from_secs(1)).await,
+        Health::Healthy
+    );
+    assert_eq!(metrics.snapshot().channels, 0);
+
+    // It gets reopened after the recovery instead of being recovered
+    let recovered = wait_for_event(&connection, |event| {
+        matches!(event, Event::RecoverySucceeded { channel_id: 0 })
+    });
+    broker.disconnect_all();
+    recovered.await;
+    assert_eq!(
+        connection.health_check(Duration::from_secs(1)).await,
+        Health::Healthy
+    );
+    assert!(connection.snapshot().channels.is_empty());
+    assert_eq!(metrics.snapshot().channels, 0);
+    assert_eq!(metrics.snapshot().recoveries, 1);
+}
+
+#[tokio::test]
+async fn blocked() {
+    let peer = ScriptedPeer::new(Script::new().with_handshake().send_method(
+        0,
+        AMQPClass::Connection(connection::AMQPMethod::Blocked(connection::Blocked {
+            reason: "low on memory".into(),
+        })),
+    ));
+    let connection = peer
+        .connect_with_runtime(ConnectionProperties::default(), Runtime::tokio_current())
+        .await
+        .unwrap();
+    let mut health = connection.health(Duration::from_secs(60));
+    for _ in 0..100 {
+        if health == Health::Blocked {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        health = connection.health(Duration::from_secs(60));
+    }
+    assert_eq!(health, Health::Blocked);
+    assert!(health.is_live());
+    assert!(!health.is_ready());
+    // Nothing can be sent to the server while it blocks us
+    assert_eq!(
+        connection.health_check(Duration::from_secs(1)).await,
+        Health::Blocked
+    );
+    peer.assert_finished();
+}
+
+#[tokio::test]
+async fn unresponsive() {
+    // The server never answers channel.open
+    let peer = ScriptedPeer::new(Script::new().with_handshake().expect_method(1, |method| {
+        matches!(method, AMQPClass::Channel(channel::AMQPMethod::Open(_)))
+    }));
+    let connection = peer
+        .connect_with_runtime(ConnectionProperties::default(), Runtime::tokio_current())
+        .await
+        .unwrap();
+    let health = connection.health_check(Duration::from_millis(50)).await;
+    let Health::Unresponsive {
+        since_last_inbound,
+        error: Some(error),
+    } = &health
+    else {
+        panic!("unexpected health: {health:?}");
+    };
+    assert!(since_last_inbound.is_some());
+    assert!(matches!(error.kind(), ErrorKind::Timeout("health check")));
+    assert!(health.is_live());
+    assert!(!health.is_ready());
+}
+
+#[tokio::test]
+async fn recovering() {
+    let broker = FakeBroker::new();
+    let down = Arc::new(AtomicBool::new(false));
+    let connection = connect_refusable(
+        &broker,
+        &down,
+        ConnectionProperties::default().enable_auto_recover(),
+    )
+    .await;
+
+    down.store(true, Ordering::SeqCst);
+    broker.disconnect_all();
+    let mut health = connection.health(Duration::from_secs(60));
+    for _ in 0..100 {
+        if matches!(health, Health::Recovering { attempts } if attempts > 0) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        health = connection.health_check(Duration::from_secs(1)).await;
+    }
+    assert!(
+        matches!(health, Health::Recovering { attempts } if attempts > 0),
+        "unexpected health: {health:?}"
+    );
+    assert!(health.is_live());
+    assert!(!health.is_ready());
+}
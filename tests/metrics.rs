@@ -0,0 +1,152 @@
+mod common;
+
+use common::publish;
+use futures_lite::stream::StreamExt;
+use lapin::{
+    BasicProperties, Connection, ConnectionProperties, Event,
+    metrics::{AtomicMetrics, Metrics},
+    options::{
+        BasicAckOptions, BasicConsumeOptions, BasicGetOptions, BasicRejectOptions,
+        ConfirmSelectOptions, QueueDeclareOptions,
+    },
+    testing::FakeBroker,
+    types::FieldTable,
+};
+use std::sync::Arc;
+
+async fn connect(metrics: impl Metrics) -> (FakeBroker, Connection) {
+    let broker = FakeBroker::new();
+    let connection = broker
+        .connect(
+            ConnectionProperties::default()
+                .enable_auto_recover()
+                .with_metrics(metrics),
+        )
+        .await
+        .unwrap();
+    (broker, connection)
+}
+
+async fn exercise(broker: &FakeBroker, connection: &Connection) {
+    let channel = connection.create_channel().await.unwrap();
+    channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .await
+        .unwrap();
+    channel
+        .queue_declare(
+            "queue".into(),
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+    publish(&channel, "", "queue", BasicProperties::default()).await;
+    publish(&channel, "", "queue", BasicProperties::default()).await;
+    publish(&channel, "", "missing", BasicProperties::default()).await;
+
+    let message = channel
+        .basic_get("queue".into(), BasicGetOptions::default())
+        .await
+        .unwrap()
+        .unwrap();
+    message
+        .delivery
+        .reject(BasicRejectOptions::default())
+        .await
+        .unwrap();
+    let mut consumer = channel
+        .basic_consume(
+            "queue".into(),
+            "consumer".into(),
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+    let delivery = consumer.next().await.unwrap().unwrap();
+    delivery.ack(BasicAckOptions::default()).await.unwrap();
+
+    let mut events = connection.events_listener();
+    broker.disconnect_all();
+    while !matches!(
+        events.next().await,
+        Some(Event::RecoverySucceeded { channel_id }) if channel_id == channel.id()
+    ) {}
+    channel.close(200, "OK".into()).await.unwrap();
+}
+
+#[tokio::test]
+async fn atomic_counters() {
+    let metrics = Arc::new(AtomicMetrics::default());
+    let (broker, connection) = connect(metrics.clone()).await;
+    exercise(&broker, &connection).await;
+
+    let snapshot = metrics.snapshot();
+    assert!(snapshot.bytes_read > 0);
+    assert!(snapshot.bytes_written > 0);
+    assert!(snapshot.frames_received > 0);
+    assert!(snapshot.frames_sent > 0);
+    assert_eq!(snapshot.published, 3);
+    assert_eq!(snapshot.confirmed, 3);
+    assert_eq!(snapshot.publish_nacked, 0);
+    assert!(snapshot.average_confirm_latency().is_some());
+    assert_eq!(snapshot.delivered, 2);
+    assert_eq!(snapshot.acked, 1);
+    assert_eq!(snapshot.nacked, 0);
+    assert_eq!(snapshot.rejected, 1);
+    assert_eq!(snapshot.returned, 1);
+    assert_eq!(snapshot.recoveries, 1);
+    assert_eq!(snapshot.channels, 0);
+}
+
+#[tokio::test]
+async fn channels_gauge_on_close() {
+    let metrics = Arc::new(AtomicMetrics::default());
+    let (_broker, connection) = connect(metrics.clone()).await;
+    let _first = connection.create_channel().await.unwrap();
+    let _second = connection.create_channel().await.unwrap();
+    assert_eq!(metrics.snapshot().channels, 2);
+
+    connection.close(200, "OK".into()).await.unwrap();
+    assert_eq!(metrics.snapshot().channels, 0);
+}
+
+#[cfg(feature = "prometheus")]
+#[tokio::test]
+async fn prometheus() {
+    use lapin::metrics::PrometheusMetrics;
+
+    let registry = prometheus::Registry::new();
+    let (broker, connection) = connect(PrometheusMetrics::new(&registry).unwrap()).await;
+    exercise(&broker, &connection).await;
+
+    let families = registry.gather();
+    let value = |name: &str| {
+        families
+            .iter()
+            .find(|family| family.name() == name)
+            .map(|family| {
+                family
+                    .get_metric()
+                    .iter()
+                    .map(|metric| metric.get_counter().get_value())
+                    .sum::<f64>()
+            })
+            .unwrap()
+    };
+    assert_eq!(value("lapin_published_total"), 3.0);
+    assert_eq!(value("lapin_publish_confirms_total"), 3.0);
+    assert_eq!(value("lapin_acknowledgements_total"), 2.0);
+    assert_eq!(value("lapin_recoveries_total"), 1.0);
+
+    connection.create_channel().await.unwrap();
+    connection.close(200, "OK".into()).await.unwrap();
+    let channels = registry
+        .gather()
+        .into_iter()
+        .find(|family| family.name() == "lapin_channels")
+        .map(|family| family.get_metric()[0].get_gauge().get_value())
+        .unwrap();
+    assert_eq!(channels, 0.0);
+}
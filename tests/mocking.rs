@@ -0,0 +1,142 @@
+use futures_lite::stream::StreamExt;
+use lapin::{
+    Acknowledger, BasicProperties, Confirmation, Publisher,
+    options::{
+        BasicAckOptions, BasicConsumeOptions, BasicPublishOptions, BasicRejectOptions,
+        ConfirmSelectOptions, QueueDeclareOptions,
+    },
+    testing::{
+        Acknowledgement, FakeBroker, PublishedMessage, RecordingAcknowledger, RecordingPublisher,
+    },
+    types::FieldTable,
+};
+
+// The application code under test
+async fn forward(
+    publisher: &dyn Publisher,
+    acknowledger: &dyn Acknowledger,
+    payload: &[u8],
+) -> lapin::Result<()> {
+    if payload.is_empty() {
+        acknowledger.reject(BasicRejectOptions::default()).await?;
+        return Ok(());
+    }
+    let confirmation = publisher
+        .basic_publish(
+            "".into(),
+            "forwarded".into(),
+            BasicPublishOptions::default(),
+            payload,
+            BasicProperties::default().with_priority(1),
+        )
+        .await?
+        .await?;
+    if confirmation.is_nack() {
+        acknowledger
+            .reject(BasicRejectOptions { requeue: true })
+            .await?;
+    } else {
+        acknowledger.ack(BasicAckOptions::default()).await?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn recording() {
+    let publisher = RecordingPublisher::new().with_confirms();
+    let acknowledgements = RecordingAcknowledger::new();
+
+    forward(&publisher, &acknowledgements.delivery(1), b"first")
+        .await
+        .unwrap();
+    forward(&publisher, &acknowledgements.delivery(2), b"")
+        .await
+        .unwrap();
+    publisher.nack_next();
+    forward(&publisher, &acknowledgements.delivery(3), b"third")
+        .await
+        .unwrap();
+
+    let published = publisher.published();
+    assert_eq!(published.len(), 2);
+    assert_eq!(
+        published[0],
+        PublishedMessage {
+            exchange: "".into(),
+            routing_key: "forwarded".into(),
+            options: BasicPublishOptions::default(),
+            payload: b"first".to_vec(),
+            properties: BasicProperties::default().with_priority(1),
+        }
+    );
+    assert_eq!(published[1].payload, b"third");
+    assert_eq!(
+        acknowledgements.acknowledgements(),
+        vec![
+            Acknowledgement::Ack(1, BasicAckOptions::default()),
+            Acknowledgement::Reject(2, BasicRejectOptions::default()),
+            Acknowledgement::Reject(3, BasicRejectOptions { requeue: true }),
+        ]
+    );
+
+    // Like with an Acker, a delivery can only be acknowledged once
+    assert!(
+        !acknowledgements
+            .delivery(1)
+            .ack(BasicAckOptions::default())
+            .await
+            .unwrap()
+    );
+    assert_eq!(acknowledgements.acknowledgements().len(), 3);
+}
+
+#[tokio::test]
+async fn channel_and_acker() {
+    let broker = FakeBroker::new();
+    let connection = broker.connect(Default::default()).await.unwrap();
+    let channel = connection.create_channel().await.unwrap();
+    channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .await
+        .unwrap();
+    for queue in ["incoming", "forwarded"] {
+        channel
+            .queue_declare(
+                queue.into(),
+                QueueDeclareOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .unwrap();
+    }
+    let confirmation = Publisher::basic_publish(
+        &channel,
+        "".into(),
+        "incoming".into(),
+        BasicPublishOptions::default(),
+        b"payload",
+        BasicProperties::default(),
+    )
+    .await
+    .unwrap()
+    .await
+    .unwrap();
+    assert_eq!(confirmation, Confirmation::Ack(None));
+
+    let mut consumer = channel
+        .basic_consume(
+            "incoming".into(),
+            "".into(),
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+    let delivery = consumer.next().await.unwrap().unwrap();
+    forward(&channel, &delivery.acker, &delivery.data)
+        .await
+        .unwrap();
+    assert!(!delivery.acker.usable());
+    assert_eq!(broker.message_count("incoming"), Some(0));
+    assert_eq!(broker.message_count("forwarded"), Some(1));
+}
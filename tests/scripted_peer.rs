@@ -0,0 +1,244 @@
+use async_rs::{Runtime, traits::RuntimeKit};
+use futures_lite::stream::StreamExt;
+use lapin::{
+    BasicProperties, Channel, Confirmation, Connection, ErrorKind, Event,
+    options::{
+        BasicConsumeOptions, BasicPublishOptions, ConfirmSelectOptions, QueueDeclareOptions,
+    },
+    protocol::{
+        AMQPClass, AMQPErrorKind, AMQPHardError, basic, channel, confirm, connection, queue,
+    },
+    testing::{Script, ScriptedPeer},
+    types::FieldTable,
+};
+use std::time::Duration;
+
+fn open_channel(script: Script) -> Script {
+    script
+        .expect_method(1, |method| {
+            matches!(method, AMQPClass::Channel(channel::AMQPMethod::Open(_)))
+        })
+        .send_method(
+            1,
+            AMQPClass::Channel(channel::AMQPMethod::OpenOk(channel::OpenOk {})),
+        )
+}
+
+fn publish_with_confirm(script: Script) -> Script {
+    open_channel(script)
+        .expect_method(1, |method| {
+            matches!(method, AMQPClass::Confirm(confirm::AMQPMethod::Select(_)))
+        })
+        .send_method(
+            1,
+            AMQPClass::Confirm(confirm::AMQPMethod::SelectOk(confirm::SelectOk {})),
+        )
+        .expect_method(1, |method| {
+            matches!(method, AMQPClass::Basic(basic::AMQPMethod::Publish(_)))
+        })
+        .expect_content(1)
+}
+
+async fn publish(connection: &Connection) -> (Channel, lapin::Result<Confirmation>) {
+    let channel = connection.create_channel().await.unwrap();
+    channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .await
+        .unwrap();
+    let confirm = channel
+        .basic_publish(
+            "".into(),
+            "queue".into(),
+            BasicPublishOptions::default(),
+            b"payload",
+            BasicProperties::default(),
+        )
+        .await
+        .unwrap();
+    (channel, confirm.await)
+}
+
+async fn connection_closed_mid_publish<RK: RuntimeKit + Clone + Send + 'static>(
+    runtime: Runtime<RK>,
+) {
+    let peer = ScriptedPeer::new(
+        publish_with_confirm(Script::new().with_handshake())
+            .send_method(
+                0,
+                AMQPClass::Connection(connection::AMQPMethod::Close(connection::Close {
+                    reply_code: AMQPHardError::CONNECTIONFORCED.get_id(),
+                    reply_text: "CONNECTION_FORCED - broker forced connection closure".into(),
+                    class_id: 0,
+                    method_id: 0,
+                })),
+            )
+            .disconnect(),
+    );
+    let connection = peer
+        .connect_with_runtime(Default::default(), runtime)
+        .await
+        .unwrap();
+    let (_channel, confirmation) = publish(&connection).await;
+    // Depending on which the client notices first, the connection.close or the socket shutdown
+    let error = confirmation.unwrap_err();
+    let forced = AMQPErrorKind::Hard(AMQPHardError::CONNECTIONFORCED);
+    match error.kind() {
+        ErrorKind::ProtocolError(error) => assert_eq!(error.kind(), &forced),
+        ErrorKind::IOError(_) => {}
+        error => panic!("unexpected error: {error:?}"),
+    }
+    peer.finished().await;
+    peer.assert_finished();
+    assert!(!connection.status().connected());
+}
+
+#[tokio::test]
+async fn tokio_connection_closed_mid_publish() {
+    connection_closed_mid_publish(Runtime::tokio_current()).await;
+}
+
+#[cfg(feature = "smol")]
+#[test]
+fn smol_connection_closed_mid_publish() {
+    smol::block_on(connection_closed_mid_publish(Runtime::smol()));
+}
+
+#[tokio::test]
+async fn nacked_confirm() {
+    let peer = ScriptedPeer::new(
+        publish_with_confirm(Script::new().with_handshake()).send_method(
+            1,
+            AMQPClass::Basic(basic::AMQPMethod::Nack(basic::Nack {
+                delivery_tag: 1,
+                multiple: false,
+                requeue: false,
+            })),
+        ),
+    );
+    let connection = peer.connect(Default::default()).await.unwrap();
+    let (_channel, confirmation) = publish(&connection).await;
+    assert_eq!(confirmation.unwrap(), Confirmation::Nack(None));
+    peer.assert_finished();
+}
+
+#[tokio::test]
+async fn channel_flow() {
+    let peer = ScriptedPeer::new(
+        open_channel(Script::new().with_handshake())
+            .send_method(
+                1,
+                AMQPClass::Channel(channel::AMQPMethod::Flow(channel::Flow { active: false })),
+            )
+            .expect_method(1, |method| {
+                matches!(
+                    method,
+                    AMQPClass::Channel(channel::AMQPMethod::FlowOk(channel::FlowOk {
+                        active: false
+                    }))
+                )
+            }),
+    );
+    let connection = peer.connect(Default::default()).await.unwrap();
+    let mut events = connection.events_listener();
+    let _channel = connection.create_channel().await.unwrap();
+    while !matches!(
+        events.next().await,
+        Some(Event::SendFlow {
+            channel_id: 1,
+            active: false
+        })
+    ) {}
+    peer.finished().await;
+    peer.assert_finished();
+}
+
+#[tokio::test]
+async fn consumer_cancelled_by_server() {
+    let peer = ScriptedPeer::new(
+        open_channel(Script::new().with_handshake())
+            .expect_method(1, |method| {
+                matches!(method, AMQPClass::Basic(basic::AMQPMethod::Consume(_)))
+            })
+            .send_method(
+                1,
+                AMQPClass::Basic(basic::AMQPMethod::ConsumeOk(basic::ConsumeOk {
+                    consumer_tag: "consumer".into(),
+                })),
+            )
+            .send_method(
+                1,
+                AMQPClass::Basic(basic::AMQPMethod::Cancel(basic::Cancel {
+                    consumer_tag: "consumer".into(),
+                    nowait: false,
+                })),
+            )
+            .expect_method(1, |method| {
+                matches!(method, AMQPClass::Basic(basic::AMQPMethod::CancelOk(_)))
+            }),
+    );
+    let connection = peer.connect(Default::default()).await.unwrap();
+    let channel = connection.create_channel().await.unwrap();
+    let mut consumer = channel
+        .basic_consume(
+            "queue".into(),
+            "consumer".into(),
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+    assert!(consumer.next().await.is_none());
+    peer.finished().await;
+    peer.assert_finished();
+}
+
+#[tokio::test]
+async fn rpc_timeout_closes_channel() {
+    let peer = ScriptedPeer::new(
+        open_channel(Script::new().with_handshake())
+            .expect_method(1, |method| {
+                matches!(method, AMQPClass::Queue(queue::AMQPMethod::Declare(_)))
+            })
+            .expect_method(1, |method| {
+                matches!(method, AMQPClass::Channel(channel::AMQPMethod::Close(_)))
+            })
+            .send_method(
+                1,
+                AMQPClass::Channel(channel::AMQPMethod::CloseOk(channel::CloseOk {})),
+            ),
+    );
+    let connection = peer.connect(Default::default()).await.unwrap();
+    let mut events = connection.events_listener();
+    let channel = connection.create_channel().await.unwrap();
+    let error = channel
+        .with_rpc_timeout(Some(Duration::from_millis(50)))
+        .queue_declare(
+            "queue".into(),
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(error.kind(), ErrorKind::Timeout("queue.declare")));
+    while !matches!(
+        events.next().await,
+        Some(Event::ChannelClosed { channel_id: 1, .. })
+    ) {}
+    assert!(!channel.status().connected());
+    assert!(connection.status().connected());
+    peer.assert_finished();
+}
+
+#[tokio::test]
+async fn unexpected_frame() {
+    let peer = ScriptedPeer::new(
+        open_channel(Script::new().with_handshake()).expect_method(1, |method| {
+            matches!(method, AMQPClass::Confirm(confirm::AMQPMethod::Select(_)))
+        }),
+    );
+    let connection = peer.connect(Default::default()).await.unwrap();
+    let channel = connection.create_channel().await.unwrap();
+    assert!(channel.basic_qos(10, Default::default()).await.is_err());
+    peer.finished().await;
+    assert!(peer.failure().unwrap().contains("Qos"));
+}
@@ -0,0 +1,131 @@
+mod common;
+
+use common::publish;
+use futures_lite::stream::StreamExt;
+use lapin::{
+    BasicProperties, Channel, ChannelState, ConnectionProperties, ConnectionState,
+    options::{
+        BasicAckOptions, BasicConsumeOptions, BasicGetOptions, BasicNackOptions,
+        ConfirmSelectOptions, QueueDeclareOptions,
+    },
+    snapshot::ChannelSnapshot,
+    testing::FakeBroker,
+    types::FieldTable,
+};
+use std::time::Duration;
+
+// The deliveries are dispatched to the consumers by the IO loop
+async fn wait_for(channel: &Channel, condition: impl Fn(&ChannelSnapshot) -> bool) {
+    for _ in 0..100 {
+        if condition(&channel.snapshot()) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("unexpected snapshot: {:?}", channel.snapshot());
+}
+
+#[tokio::test]
+async fn channels_and_consumers() {
+    let broker = FakeBroker::new();
+    let connection = broker
+        .connect(ConnectionProperties::default())
+        .await
+        .unwrap();
+    let idle = connection.create_channel().await.unwrap();
+    let channel = connection.create_channel().await.unwrap();
+    channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .await
+        .unwrap();
+    for queue in ["consumed", "polled"] {
+        channel
+            .queue_declare(
+                queue.into(),
+                QueueDeclareOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .unwrap();
+    }
+    let mut consumer = channel
+        .basic_consume(
+            "consumed".into(),
+            "consumer".into(),
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+    for _ in 0..3 {
+        publish(&channel, "", "consumed", BasicProperties::default()).await;
+    }
+    publish(&channel, "", "polled", BasicProperties::default()).await;
+    wait_for(&channel, |snapshot| {
+        snapshot.consumers[0].buffered_deliveries == 3
+    })
+    .await;
+
+    let snapshot = connection.snapshot();
+    assert_eq!(snapshot.state, ConnectionState::Connected);
+    assert_eq!(
+        snapshot
+            .channels
+            .iter()
+            .map(|channel| channel.channel_id)
+            .collect::<Vec<_>>(),
+        vec![idle.id(), channel.id()]
+    );
+    let idle = &snapshot.channels[0];
+    assert_eq!(idle.state, ChannelState::Connected);
+    assert!(!idle.confirm);
+    assert!(idle.consumers.is_empty());
+    let snapshot = &snapshot.channels[1];
+    assert_eq!(snapshot.state, ChannelState::Connected);
+    assert!(snapshot.confirm);
+    assert_eq!(snapshot.pending_confirms, 0);
+    assert_eq!(snapshot.consumers.len(), 1);
+    assert_eq!(snapshot.consumers[0].tag.as_str(), "consumer");
+    assert_eq!(snapshot.consumers[0].queue.as_str(), "consumed");
+    assert!(!snapshot.consumers[0].no_ack);
+    assert_eq!(snapshot.unacked_deliveries, 3);
+    assert_eq!(snapshot.queued_frames, 0);
+    assert_eq!(snapshot.expected_replies, 0);
+
+    let first = consumer.next().await.unwrap().unwrap();
+    first.ack(BasicAckOptions::default()).await.unwrap();
+    let snapshot = channel.snapshot();
+    assert_eq!(snapshot.consumers[0].buffered_deliveries, 2);
+    assert_eq!(snapshot.unacked_deliveries, 2);
+
+    let polled = channel
+        .basic_get("polled".into(), BasicGetOptions::default())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(channel.snapshot().unacked_deliveries, 3);
+
+    // Nack everything received so far at once
+    channel
+        .basic_nack(
+            polled.delivery.delivery_tag,
+            BasicNackOptions {
+                multiple: true,
+                requeue: false,
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(channel.snapshot().unacked_deliveries, 0);
+
+    channel.close(200, "OK".into()).await.unwrap();
+    let snapshot = connection.snapshot();
+    assert_eq!(snapshot.channels.len(), 1);
+
+    #[cfg(feature = "serde")]
+    {
+        let json = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(json["state"], "Connected");
+        assert_eq!(json["channels"][0]["state"], "Connected");
+    }
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use common::connect;
+use futures_lite::stream::StreamExt;
+use lapin::{
+    BasicProperties, ConnectionProperties, Event,
+    options::{BasicPublishOptions, QueueDeclareOptions},
+    testing::FakeBroker,
+    types::FieldTable,
+    uri::AMQPUri,
+};
+use std::time::Duration;
+
+#[tokio::test]
+async fn traffic() {
+    let broker = FakeBroker::new();
+    let connection = connect(
+        &broker,
+        AMQPUri::default(),
+        ConnectionProperties::default().enable_auto_recover(),
+    )
+    .await;
+    let stats = connection.stats();
+    // protocol header, start-ok, tune-ok, open
+    assert_eq!(stats.frames_sent.protocol_header, 1);
+    assert_eq!(stats.frames_sent.method, 3);
+    assert_eq!(stats.frames_received.protocol_header, 0);
+    assert!(stats.bytes_sent > 0);
+    assert!(stats.bytes_received > 0);
+    assert!(stats.last_read.is_some());
+    assert!(stats.last_write.is_some());
+    assert_eq!(stats.reconnections, 0);
+
+    let channel = connection.create_channel().await.unwrap();
+    channel
+        .queue_declare(
+            "queue".into(),
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+    channel
+        .basic_publish(
+            "".into(),
+            "queue".into(),
+            BasicPublishOptions::default(),
+            b"payload",
+            BasicProperties::default(),
+        )
+        .await
+        .unwrap();
+    let stats = connection.stats();
+    assert_eq!(stats.frames_sent.header, 1);
+    assert_eq!(stats.frames_sent.body, 1);
+    assert_eq!(stats.frames_sent.method, 6);
+    assert_eq!(stats.frames_sent.total(), 9);
+    // Everything got written and parsed
+    assert_eq!(stats.send_buffer, 0);
+    assert_eq!(stats.receive_buffer, 0);
+
+    let mut events = connection.events_listener();
+    broker.disconnect_all();
+    while !matches!(
+        events.next().await,
+        Some(Event::RecoverySucceeded { channel_id }) if channel_id == channel.id()
+    ) {}
+    let recovered = connection.stats();
+    assert_eq!(recovered.reconnections, 1);
+    assert_eq!(recovered.frames_sent.protocol_header, 2);
+    assert!(recovered.bytes_sent > stats.bytes_sent);
+}
+
+#[tokio::test]
+async fn heartbeats() {
+    let broker = FakeBroker::new();
+    let connection = connect(
+        &broker,
+        "amqp://localhost/%2f?heartbeat=1".parse().unwrap(),
+        ConnectionProperties::default().enable_auto_recover(),
+    )
+    .await;
+    // Heartbeats are sent every half heartbeat interval
+    tokio::time::sleep(Duration::from_millis(1200)).await;
+    let stats = connection.stats();
+    assert!(stats.heartbeats_sent() >= 2, "{stats:?}");
+    // The fake broker answers each heartbeat
+    assert!(stats.heartbeats_received() >= 1, "{stats:?}");
+    assert!(stats.last_read.unwrap().elapsed() < Duration::from_secs(1));
+}
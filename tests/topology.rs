@@ -0,0 +1,94 @@
+use lapin::{
+    ExchangeKind,
+    options::{ExchangeDeclareOptions, QueueDeclareOptions},
+    topology::{Topology, TopologyMismatch, TopologyVerification},
+    types::FieldTable,
+};
+
+#[test]
+fn build_topology() {
+    let topology = Topology::new()
+        .with_exchange(
+            "events".into(),
+            ExchangeKind::Topic,
+            ExchangeDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .with_exchange_binding(
+            "events".into(),
+            "amq.topic".into(),
+            "events.#".into(),
+            FieldTable::default(),
+        )
+        .with_queue_binding(
+            "audit".into(),
+            "events".into(),
+            "#".into(),
+            FieldTable::default(),
+        )
+        .with_queue(
+            "audit".into(),
+            QueueDeclareOptions {
+                durable: true,
+                ..QueueDeclareOptions::default()
+            },
+            FieldTable::default(),
+        )
+        .with_queue(
+            "".into(),
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .with_queue(
+            "".into(),
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        );
+
+    assert_eq!(topology.exchanges.len(), 1);
+    assert!(topology.exchanges[0].is_declared);
+    assert_eq!(topology.exchanges[0].bindings.len(), 1);
+    assert_eq!(topology.queues.len(), 3);
+    assert!(topology.queues[0].is_declared);
+    assert!(topology.queues[0].options.unwrap().durable);
+    assert_eq!(topology.queues[0].bindings.len(), 1);
+    assert!(topology.queues[1].server_named);
+    assert!(topology.queues[2].server_named);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn load_topology_from_json() {
+    let topology: Topology = serde_json::from_str(
+        r##"{
+            "exchanges": [{ "name": "events", "kind": "topic", "options": { "durable": true } }],
+            "queues": [
+                {
+                    "name": "audit",
+                    "bindings": [{ "source": "events", "routing_key": "#" }]
+                },
+                { "name": "legacy", "is_declared": false }
+            ]
+        }"##,
+    )
+    .unwrap();
+
+    assert_eq!(topology.exchanges[0].kind, Some(ExchangeKind::Topic));
+    assert!(topology.exchanges[0].is_declared);
+    assert!(topology.exchanges[0].options.unwrap().durable);
+    assert!(topology.queues[0].is_declared);
+    assert_eq!(topology.queues[0].bindings[0].source.as_str(), "events");
+    assert_eq!(topology.queues[0].bindings[0].routing_key.as_str(), "#");
+    assert!(!topology.queues[1].is_declared);
+}
+
+#[test]
+fn topology_verification() {
+    let mut report = TopologyVerification::default();
+    assert!(report.is_valid());
+    report.mismatched_queues.push(TopologyMismatch {
+        name: "audit".into(),
+        reason: "PRECONDITION_FAILED - inequivalent arg 'durable'".into(),
+    });
+    assert!(!report.is_valid());
+}
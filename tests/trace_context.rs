@@ -0,0 +1,188 @@
+use futures_lite::stream::StreamExt;
+use lapin::{
+    BasicProperties, ConnectionProperties,
+    options::{BasicAckOptions, BasicConsumeOptions, BasicPublishOptions, QueueDeclareOptions},
+    testing::FakeBroker,
+    trace_context::{TRACEPARENT_HEADER, TRACESTATE_HEADER, extract, inject},
+    types::{AMQPValue, FieldTable},
+};
+use opentelemetry::{
+    Context,
+    trace::{
+        SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState, TracerProvider,
+    },
+};
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use std::{
+    str::FromStr,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+use tracing::{Instrument, Subscriber, span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{
+    Layer,
+    layer::{Context as LayerContext, SubscriberExt},
+};
+
+fn header(properties: &BasicProperties, name: &str) -> Option<String> {
+    match properties.headers().as_ref()?.inner().get(name)? {
+        AMQPValue::LongString(value) => Some(String::from_utf8_lossy(value.as_bytes()).into()),
+        _ => None,
+    }
+}
+
+fn with_traceparent(traceparent: &str) -> BasicProperties {
+    let mut headers = FieldTable::default();
+    headers.insert(
+        TRACEPARENT_HEADER.into(),
+        AMQPValue::LongString(traceparent.into()),
+    );
+    BasicProperties::default().with_headers(headers)
+}
+
+#[test]
+fn inject_and_extract() {
+    let span_context = SpanContext::new(
+        TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+        SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+        TraceFlags::SAMPLED,
+        false,
+        TraceState::from_str("vendor=value").unwrap(),
+    );
+    let context = Context::new().with_remote_span_context(span_context.clone());
+
+    let properties = inject(&context, BasicProperties::default());
+    assert_eq!(
+        header(&properties, TRACEPARENT_HEADER).as_deref(),
+        Some("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01")
+    );
+    assert_eq!(
+        header(&properties, TRACESTATE_HEADER).as_deref(),
+        Some("vendor=value")
+    );
+
+    let extracted = extract(&properties);
+    let extracted = extracted.span().span_context().clone();
+    assert_eq!(extracted.trace_id(), span_context.trace_id());
+    assert_eq!(extracted.span_id(), span_context.span_id());
+    assert!(extracted.is_sampled());
+    assert!(extracted.is_remote());
+    assert_eq!(extracted.trace_state().get("vendor"), Some("value"));
+
+    // An existing traceparent is left untouched
+    let properties = inject(&context, with_traceparent("custom"));
+    assert_eq!(
+        header(&properties, TRACEPARENT_HEADER).as_deref(),
+        Some("custom")
+    );
+
+    // Nothing to inject without a valid span context
+    let properties = inject(&Context::new(), BasicProperties::default());
+    assert!(properties.headers().is_none());
+}
+
+#[test]
+fn extract_invalid() {
+    for traceparent in [
+        "",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+        "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+        "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e473-00f067aa0ba902b7-01",
+    ] {
+        let context = extract(&with_traceparent(traceparent));
+        assert!(
+            !context.span().span_context().is_valid(),
+            "{traceparent} should be invalid"
+        );
+    }
+
+    // Future versions may append fields
+    let context = extract(&with_traceparent(
+        "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+    ));
+    assert!(context.span().span_context().is_valid());
+}
+
+// Count the delivery spans which got created
+struct DeliverySpans(Arc<AtomicUsize>);
+
+impl<S: Subscriber> Layer<S> for DeliverySpans {
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, _id: &span::Id, _ctx: LayerContext<'_, S>) {
+        if attrs.metadata().name() == "lapin.delivery" {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+#[tokio::test]
+async fn propagate_through_broker() {
+    let provider = SdkTracerProvider::builder().build();
+    let delivery_spans = Arc::new(AtomicUsize::new(0));
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("lapin")))
+        .with(DeliverySpans(delivery_spans.clone()));
+    // The deliveries are created in the IO loop thread
+    tracing::subscriber::set_global_default(subscriber).unwrap();
+
+    let broker = FakeBroker::new();
+    let connection = broker
+        .connect(ConnectionProperties::default())
+        .await
+        .unwrap();
+    let channel = connection.create_channel().await.unwrap();
+    channel
+        .queue_declare(
+            "queue".into(),
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+
+    let publish_span = tracing::info_span!("publish");
+    let trace_id = publish_span.context().span().span_context().trace_id();
+    channel
+        .basic_publish(
+            "".into(),
+            "queue".into(),
+            BasicPublishOptions::default(),
+            b"payload",
+            BasicProperties::default(),
+        )
+        .instrument(publish_span)
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+
+    let mut consumer = channel
+        .basic_consume(
+            "queue".into(),
+            "consumer".into(),
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .unwrap();
+    let delivery = consumer.next().await.unwrap().unwrap();
+    let traceparent = header(&delivery.properties, TRACEPARENT_HEADER).unwrap();
+    assert!(traceparent.starts_with(&format!("00-{trace_id}-")));
+    // The span doesn't cover the time spent waiting to be consumed
+    assert_eq!(delivery_spans.load(Ordering::SeqCst), 0);
+
+    let span = delivery.span();
+    assert_eq!(delivery.span(), span);
+    assert_eq!(delivery_spans.load(Ordering::SeqCst), 1);
+    assert!(!span.is_none());
+    assert_eq!(span.context().span().span_context().trace_id(), trace_id);
+
+    delivery.ack(BasicAckOptions::default()).await.unwrap();
+    assert!(delivery.span().is_none());
+}
@@ -0,0 +1,134 @@
+use amq_protocol::{
+    frame::{AMQPFrame, gen_frame, parse_frame},
+    protocol::{AMQPClass, channel, connection},
+};
+use futures_lite::{AsyncReadExt, AsyncWriteExt, future, io};
+use lapin::{ConnectionProperties, DefaultConnectionBuilder, websocket};
+use smol::net::{TcpListener, TcpStream};
+use std::net::SocketAddr;
+
+async fn read_frame(stream: &mut TcpStream, buffer: &mut Vec<u8>) -> Option<AMQPFrame> {
+    loop {
+        if let Ok((rest, frame)) = parse_frame(buffer.as_slice()) {
+            let consumed = buffer.len() - rest.len();
+            buffer.drain(..consumed);
+            return Some(frame);
+        }
+        let mut chunk = [0u8; 4096];
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return None,
+            Ok(sz) => buffer.extend_from_slice(&chunk[..sz]),
+        }
+    }
+}
+
+async fn write_frame(stream: &mut TcpStream, frame: AMQPFrame) {
+    let buffer = gen_frame(&frame)(Vec::new().into()).unwrap().into_inner().0;
+    stream.write_all(&buffer).await.unwrap();
+}
+
+// Just enough of the broker side of the protocol to open and close connections and channels
+async fn fake_broker(mut stream: TcpStream) {
+    let mut buffer = Vec::new();
+    let mut header = [0u8; 8];
+    stream.read_exact(&mut header).await.unwrap();
+    assert_eq!(&header, b"AMQP\x00\x00\x09\x01");
+    write_frame(
+        &mut stream,
+        AMQPFrame::Method(
+            0,
+            AMQPClass::Connection(connection::AMQPMethod::Start(connection::Start {
+                version_major: 0,
+                version_minor: 9,
+                server_properties: Default::default(),
+                mechanisms: "PLAIN".into(),
+                locales: "en_US".into(),
+            })),
+        ),
+    )
+    .await;
+    while let Some(frame) = read_frame(&mut stream, &mut buffer).await {
+        let AMQPFrame::Method(id, method) = frame else {
+            continue;
+        };
+        let reply = match method {
+            AMQPClass::Connection(connection::AMQPMethod::StartOk(_)) => {
+                AMQPClass::Connection(connection::AMQPMethod::Tune(connection::Tune {
+                    channel_max: 2047,
+                    frame_max: 131072,
+                    heartbeat: 0,
+                }))
+            }
+            AMQPClass::Connection(connection::AMQPMethod::TuneOk(_)) => continue,
+            AMQPClass::Connection(connection::AMQPMethod::Open(_)) => {
+                AMQPClass::Connection(connection::AMQPMethod::OpenOk(connection::OpenOk {}))
+            }
+            AMQPClass::Connection(connection::AMQPMethod::Close(_)) => {
+                AMQPClass::Connection(connection::AMQPMethod::CloseOk(connection::CloseOk {}))
+            }
+            AMQPClass::Channel(channel::AMQPMethod::Open(_)) => {
+                AMQPClass::Channel(channel::AMQPMethod::OpenOk(channel::OpenOk {}))
+            }
+            AMQPClass::Channel(channel::AMQPMethod::Close(_)) => {
+                AMQPClass::Channel(channel::AMQPMethod::CloseOk(channel::CloseOk {}))
+            }
+            _ => continue,
+        };
+        write_frame(&mut stream, AMQPFrame::Method(id, reply)).await;
+    }
+}
+
+// Accepts WebSocket connections and forwards their binary payload to the broker over TCP
+async fn bridge(listener: TcpListener, broker: SocketAddr) {
+    while let Ok((stream, _)) = listener.accept().await {
+        let ws = async_tungstenite::accept_async(stream).await.unwrap();
+        let (ws_reader, ws_writer) = io::split(websocket::WebSocketStream::new(ws));
+        let tcp = TcpStream::connect(broker).await.unwrap();
+        smol::spawn(async move {
+            let _ = future::zip(io::copy(ws_reader, tcp.clone()), io::copy(tcp, ws_writer)).await;
+        })
+        .detach();
+    }
+}
+
+fn start_bridge() -> SocketAddr {
+    let (broker, bridge_listener) = smol::block_on(async {
+        (
+            TcpListener::bind("127.0.0.1:0").await.unwrap(),
+            TcpListener::bind("127.0.0.1:0").await.unwrap(),
+        )
+    });
+    let broker_addr = broker.local_addr().unwrap();
+    let bridge_addr = bridge_listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        smol::block_on(async move {
+            smol::spawn(bridge(bridge_listener, broker_addr)).detach();
+            while let Ok((stream, _)) = broker.accept().await {
+                smol::spawn(fake_broker(stream)).detach();
+            }
+        })
+    });
+    bridge_addr
+}
+
+#[tokio::test]
+async fn connection_over_websocket() {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let addr = start_bridge();
+    let connection = DefaultConnectionBuilder::new()
+        .unwrap()
+        .with_uri_str(format!("ws://guest:guest@{addr}/ws?vhost=%2f"))
+        .with_properties(ConnectionProperties::default())
+        .connect()
+        .await
+        .unwrap();
+    assert!(connection.status().connected());
+
+    let channel = connection.create_channel().await.unwrap();
+    assert!(channel.status().connected());
+    channel.close(200, "OK".into()).await.unwrap();
+    assert!(!channel.status().connected());
+
+    connection.close(200, "OK".into()).await.unwrap();
+}